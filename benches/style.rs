@@ -0,0 +1,33 @@
+use std::time::{Duration, Instant};
+
+use br_eng::{bench, css, html, layout, media, style};
+
+///Style a large generated document several times in each traversal mode and
+/// print the timings. Run with 'cargo bench --bench style'.
+fn main() {
+   const RUNS: usize = 10;
+   let (doc, sheet,) = bench::generate(50, 100, 2000,);
+   let root = html::parse(doc,);
+   let stylesheets = [css::parse(sheet, css::Origin::Author,),];
+   let mut viewport: layout::Dimensions = Default::default();
+   viewport.content.width = 800.0;
+   viewport.content.height = 600.0;
+   let env = media::MediaEnvironment::new(&viewport,);
+   let containers = style::ContainerSizes::new();
+   println!("styling {} elements with {} rules", bench::count_elements(&root,), stylesheets[0].rules.len());
+   for traversal in [style::Traversal::Sequential, style::Traversal::Parallel,] {
+      let mut times: Vec<Duration,> = (0..RUNS)
+         .map(|_| {
+            let start = Instant::now();
+            style::style_tree(&root, &stylesheets, &env, &containers, traversal,);
+            start.elapsed()
+         },)
+         .collect();
+      times.sort();
+      let mode = if traversal == style::Traversal::Parallel { "parallel" } else { "sequential" };
+      println!("{mode}: fastest {:?}, median {:?} over {RUNS} runs", times[0], times[RUNS / 2]);
+   }
+   let sequential = style::style_tree(&root, &stylesheets, &env, &containers, style::Traversal::Sequential,);
+   let parallel = style::style_tree(&root, &stylesheets, &env, &containers, style::Traversal::Parallel,);
+   assert!(bench::same_styles(&sequential, &parallel,), "parallel styling differs from sequential");
+}
@@ -0,0 +1,48 @@
+#![allow(unused)]
+use crate::{css, dom, html, layout, media, properties, style};
+
+///A generated document of 'sections' sections with 'items' items each, and a
+/// stylesheet of about 'rules' rules with id, class, tag, universal and
+/// descendant selectors.
+pub fn generate(sections: usize, items: usize, rules: usize,) -> (String, String,) {
+   let mut doc = String::from("<html><body>",);
+   for s in 0..sections {
+      doc.push_str(&format!("<div class=\"section c{}\"><h2 id=\"h{s}\">Section</h2><ul>", s % rules),);
+      for i in 0..items {
+         let n = s * items + i;
+         //Most items look alike, and can share their style.
+         match i % 10 {
+            0 => doc.push_str(&format!("<li id=\"e{n}\" class=\"item c{}\"><span>Item</span></li>", n % rules),),
+            _ => doc.push_str(&format!("<li class=\"item c{}\"><span>Item</span></li>", i % 3),),
+         }
+      }
+      doc.push_str("</ul></div>",);
+   }
+   doc.push_str("</body></html>",);
+   let mut sheet = String::from("* { margin: 0 } li { padding: 2px } span { color: #333333 }\n",);
+   for r in 0..rules {
+      let css = match r % 6 {
+         0 => format!(".c{r} {{ color: #{:06x} }}\n", r * 4099 % 0x1000000),
+         1 => format!("#e{r} {{ margin-left: {}px }}\n", r % 50),
+         2 => format!("li.c{r}.item {{ border-width: {}px }}\n", r % 5),
+         3 => format!("h{} {{ font-size: {}px }}\n", r % 6 + 1, r % 20 + 10),
+         4 => format!(".section.c{r} li span {{ color: #{:06x} }}\n", r * 811 % 0x1000000),
+         _ => format!("#missing{r} > ul li {{ padding: 1px }}\n"),
+      };
+      sheet.push_str(&css,);
+   }
+   (doc, sheet,)
+}
+
+///Do the trees have the same specified and computed values everywhere?
+pub fn same_styles(a: &style::StyledNode, b: &style::StyledNode,) -> bool {
+   a.computed() == b.computed()
+      && properties::LonghandId::ALL.iter().all(|&id| a.val(id,) == b.val(id,),)
+      && a.children.len() == b.children.len()
+      && a.children.iter().zip(&b.children,).all(|(a, b,)| same_styles(a, b,),)
+}
+
+pub fn count_elements(node: &dom::Node,) -> usize {
+   let own = matches!(node.node_type, dom::NodeType::Element(_)) as usize;
+   own + node.children.iter().map(count_elements,).sum::<usize>()
+}
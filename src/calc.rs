@@ -0,0 +1,511 @@
+#![allow(unused)]
+use std::fmt;
+
+use crate::{
+   css::{Unit, Value},
+   Parser,
+};
+
+///A math expression from 'calc()', 'min()', 'max()' or 'clamp()', simplified
+/// as far as possible when parsed.
+#[derive(Clone, PartialEq,)]
+pub enum Calc {
+   ///A number, length or percentage.
+   Leaf(Value,),
+   Sum(Vec<Calc,>,),
+   ///A product that can't be simplified, e.g. of two lengths.
+   Product(Vec<Calc,>,),
+   ///'1 / x' for an 'x' that isn't a number.
+   Invert(Box<Calc,>,),
+   Min(Vec<Calc,>,),
+   Max(Vec<Calc,>,),
+}
+
+///What an expression resolves to.
+#[derive(Clone, Copy, PartialEq, Eq,)]
+pub enum CalcKind {
+   Number,
+   Length,
+   Percentage,
+   ///A mix of lengths and percentages, like '100% - 10px'.
+   LengthPercentage,
+}
+
+impl Calc {
+   ///'terms[0] + terms[1] + ...', adding up terms of the same unit.
+   pub fn sum(terms: Vec<Calc,>,) -> Calc {
+      let mut leaves: Vec<Value,> = vec![];
+      let mut others = vec![];
+      let mut stack = terms;
+      while let Some(term,) = stack.pop() {
+         match term {
+            Calc::Sum(inner,) => stack.extend(inner,),
+            Calc::Leaf(val,) => match leaves.iter_mut().find(|leaf| same_unit(leaf, &val,),) {
+               Some(leaf,) => *leaf = scale_leaf(leaf, 1.0,) + leaf_amount(&val,),
+               None => leaves.push(val,),
+            },
+            other => others.push(other,),
+         }
+      }
+      let mut terms: Vec<Calc,> = leaves.into_iter().rev().map(Calc::Leaf,).chain(others.into_iter().rev(),).collect();
+      if terms.len() == 1 {
+         terms.pop().unwrap()
+      } else {
+         Calc::Sum(terms,)
+      }
+   }
+
+   ///'self * k' for a number 'k'.
+   pub fn scale(self, k: f64,) -> Calc {
+      match self {
+         Calc::Leaf(val,) => Calc::Leaf(scale_leaf(&val, k,),),
+         Calc::Sum(terms,) => Calc::Sum(terms.into_iter().map(|t| t.scale(k,),).collect(),),
+         //Scaling by a negative number swaps minimum and maximum.
+         Calc::Min(args,) if k < 0.0 => Calc::Max(args.into_iter().map(|a| a.scale(k,),).collect(),),
+         Calc::Max(args,) if k < 0.0 => Calc::Min(args.into_iter().map(|a| a.scale(k,),).collect(),),
+         Calc::Min(args,) => Calc::Min(args.into_iter().map(|a| a.scale(k,),).collect(),),
+         Calc::Max(args,) => Calc::Max(args.into_iter().map(|a| a.scale(k,),).collect(),),
+         Calc::Product(mut factors,) => {
+            factors.insert(0, Calc::Leaf(Value::Number(k,),),);
+            Calc::Product(factors,)
+         }
+         inverted @ Calc::Invert(_,) => Calc::Product(vec![Calc::Leaf(Value::Number(k,),), inverted],),
+      }
+   }
+
+   ///'a * b'. Only a product with a number can be simplified.
+   pub fn product(a: Calc, b: Calc,) -> Calc {
+      match (a, b,) {
+         (Calc::Leaf(Value::Number(n,),), other,) | (other, Calc::Leaf(Value::Number(n,),),) => other.scale(n,),
+         (Calc::Product(mut factors,), other,) => {
+            factors.push(other,);
+            Calc::Product(factors,)
+         }
+         (a, b,) => Calc::Product(vec![a, b],),
+      }
+   }
+
+   ///'a / b'.
+   pub fn divide(a: Calc, b: Calc,) -> Calc {
+      match b {
+         Calc::Leaf(Value::Number(n,),) => a.scale(1.0 / n,),
+         b => Calc::product(a, Calc::Invert(Box::new(b,),),),
+      }
+   }
+
+   ///'min(args...)', folded to one value when all arguments share a unit.
+   pub fn min(args: Vec<Calc,>,) -> Calc { Calc::fold(args, f64::min, Calc::Min,) }
+
+   ///'max(args...)', folded to one value when all arguments share a unit.
+   pub fn max(args: Vec<Calc,>,) -> Calc { Calc::fold(args, f64::max, Calc::Max,) }
+
+   ///'clamp(lo, val, hi)', which is 'max(lo, min(val, hi))'.
+   pub fn clamp(lo: Calc, val: Calc, hi: Calc,) -> Calc { Calc::max(vec![lo, Calc::min(vec![val, hi],)],) }
+
+   fn fold(args: Vec<Calc,>, pick: fn(f64, f64,) -> f64, unfolded: fn(Vec<Calc,>,) -> Calc,) -> Calc {
+      let first = match args.first() {
+         Some(Calc::Leaf(val,),) => val.clone(),
+         _ => return unfolded(args,),
+      };
+      let mut amount = leaf_amount(&first,);
+      for arg in &args[1..] {
+         match arg {
+            Calc::Leaf(val,) if same_unit(val, &first,) => amount = pick(amount, leaf_amount(val,),),
+            _ => return unfolded(args,),
+         }
+      }
+      Calc::Leaf(scale_leaf(&first, 0.0,) + amount,)
+   }
+
+   ///What the expression resolves to, or 'None' if it mixes incompatible types.
+   pub fn kind(&self,) -> Option<CalcKind,> {
+      match self {
+         Calc::Leaf(Value::Number(_,),) => Some(CalcKind::Number,),
+         Calc::Leaf(Value::Length(..,),) => Some(CalcKind::Length,),
+         Calc::Leaf(Value::Percentage(_,),) => Some(CalcKind::Percentage,),
+         Calc::Leaf(_,) => None,
+         Calc::Sum(args,) | Calc::Min(args,) | Calc::Max(args,) => {
+            args.iter().try_fold(None, |acc, arg| Some(Some(combine(acc, arg.kind()?,)?,),),)?
+         }
+         Calc::Product(factors,) => {
+            let kinds = factors.iter().map(Calc::kind,).collect::<Option<Vec<_,>,>>()?;
+            match kinds.iter().filter(|k| **k != CalcKind::Number,).collect::<Vec<_,>>()[..] {
+               [] => Some(CalcKind::Number,),
+               [kind] => Some(*kind,),
+               _ => None,
+            }
+         }
+         Calc::Invert(x,) => x.kind().filter(|k| *k == CalcKind::Number,),
+      }
+   }
+
+   ///Evaluate with 'em' and 'rem' in px, and percentages of 'basis'.
+   pub fn eval(&self, font_size: f64, root_font_size: f64, basis: f64,) -> f64 {
+      let eval = |c: &Calc| c.eval(font_size, root_font_size, basis,);
+      match self {
+         Calc::Leaf(val,) => match *val {
+            Value::Number(n,) => n,
+            Value::Percentage(pct,) => basis * pct / 100.0,
+            Value::Length(f, Unit::Em,) => f * font_size,
+            Value::Length(f, Unit::Rem,) => f * root_font_size,
+            _ => val.to_px(),
+         },
+         Calc::Sum(terms,) => terms.iter().map(eval,).sum(),
+         Calc::Product(factors,) => factors.iter().map(eval,).product(),
+         Calc::Invert(x,) => 1.0 / eval(x,),
+         Calc::Min(args,) => args.iter().map(eval,).fold(f64::INFINITY, f64::min,),
+         Calc::Max(args,) => args.iter().map(eval,).fold(f64::NEG_INFINITY, f64::max,),
+      }
+   }
+
+   ///Replace 'em' and 'rem' with px and simplify again, leaving only px,
+   /// percentages and numbers.
+   pub fn absolutize(&self, font_size: f64, root_font_size: f64,) -> Calc {
+      let abs = |c: &Calc| c.absolutize(font_size, root_font_size,);
+      match self {
+         Calc::Leaf(val @ Value::Length(..,),) => {
+            Calc::Leaf(Value::Length(self.eval(font_size, root_font_size, 0.0,), Unit::Px,),)
+         }
+         Calc::Leaf(_,) => self.clone(),
+         Calc::Sum(terms,) => Calc::sum(terms.iter().map(abs,).collect(),),
+         Calc::Product(factors,) => factors.iter().map(abs,).reduce(Calc::product,).unwrap(),
+         Calc::Invert(x,) => Calc::divide(Calc::Leaf(Value::Number(1.0,),), abs(x,),),
+         Calc::Min(args,) => Calc::min(args.iter().map(abs,).collect(),),
+         Calc::Max(args,) => Calc::max(args.iter().map(abs,).collect(),),
+      }
+   }
+
+   ///Does the expression refer to a percentage?
+   pub fn has_percentage(&self,) -> bool {
+      match self {
+         Calc::Leaf(val,) => matches!(val, Value::Percentage(_)),
+         Calc::Sum(args,) | Calc::Product(args,) | Calc::Min(args,) | Calc::Max(args,) => args.iter().any(Calc::has_percentage,),
+         Calc::Invert(x,) => x.has_percentage(),
+      }
+   }
+
+   ///A single value stays a plain 'Value', as in 'calc(10px + 5px)' becoming '15px'.
+   pub fn into_value(self,) -> Value {
+      match self {
+         Calc::Leaf(val,) => val,
+         other => Value::Calc(Box::new(other,),),
+      }
+   }
+}
+
+impl fmt::Display for Calc {
+   ///'min()' and 'max()' as themselves, anything else wrapped in 'calc()'.
+   fn fmt(&self, f: &mut fmt::Formatter,) -> fmt::Result {
+      match self {
+         Calc::Min(_,) | Calc::Max(_,) => self.fmt_term(f,),
+         _ => {
+            f.write_str("calc(",)?;
+            self.fmt_sum(f,)?;
+            f.write_str(")",)
+         }
+      }
+   }
+}
+
+impl Calc {
+   ///Write the terms of a sum without parentheses, e.g. '100% - 10px'.
+   fn fmt_sum(&self, f: &mut fmt::Formatter,) -> fmt::Result {
+      let Calc::Sum(terms,) = self else { return self.fmt_term(f,) };
+      for (i, term,) in terms.iter().enumerate() {
+         match term.negated() {
+            Some(negated,) if i > 0 => {
+               f.write_str(" - ",)?;
+               negated.fmt_term(f,)?;
+            }
+            _ => {
+               if i > 0 {
+                  f.write_str(" + ",)?;
+               }
+               term.fmt_term(f,)?;
+            }
+         }
+      }
+      Ok((),)
+   }
+
+   ///'-self' if this is a negative value or a product with a negative number,
+   /// so that it can be written after '-' in a sum.
+   fn negated(&self,) -> Option<Calc,> {
+      match self {
+         Calc::Leaf(val,) if leaf_amount(val,) < 0.0 => Some(Calc::Leaf(match val {
+            Value::Length(n, unit,) => Value::Length(-n, unit.clone(),),
+            other => scale_leaf(other, -1.0,),
+         },),),
+         Calc::Product(factors,) => match &factors[..] {
+            [Calc::Leaf(Value::Number(k,),), rest @ ..] if *k < 0.0 => {
+               let mut factors = rest.to_vec();
+               if *k != -1.0 {
+                  factors.insert(0, Calc::Leaf(Value::Number(-k,),),);
+               }
+               Some(if factors.len() == 1 { factors.pop().unwrap() } else { Calc::Product(factors,) },)
+            }
+            _ => None,
+         },
+         _ => None,
+      }
+   }
+
+   ///Write an operand of a product or function, putting sums in parentheses.
+   fn fmt_term(&self, f: &mut fmt::Formatter,) -> fmt::Result {
+      match self {
+         Calc::Leaf(val,) => write!(f, "{val}"),
+         Calc::Sum(_,) => {
+            f.write_str("(",)?;
+            self.fmt_sum(f,)?;
+            f.write_str(")",)
+         }
+         Calc::Product(factors,) => {
+            for (i, factor,) in factors.iter().enumerate() {
+               match factor {
+                  Calc::Invert(_,) if i == 0 => f.write_str("1 / ",)?,
+                  Calc::Invert(_,) => f.write_str(" / ",)?,
+                  _ if i > 0 => f.write_str(" * ",)?,
+                  _ => {}
+               }
+               match factor {
+                  Calc::Invert(x,) => x.fmt_factor(f,)?,
+                  _ => factor.fmt_factor(f,)?,
+               }
+            }
+            Ok((),)
+         }
+         Calc::Invert(x,) => {
+            f.write_str("1 / ",)?;
+            x.fmt_factor(f,)
+         }
+         Calc::Min(args,) | Calc::Max(args,) => {
+            f.write_str(if matches!(self, Calc::Min(_)) { "min(" } else { "max(" },)?;
+            for (i, arg,) in args.iter().enumerate() {
+               if i > 0 {
+                  f.write_str(", ",)?;
+               }
+               arg.fmt_sum(f,)?;
+            }
+            f.write_str(")",)
+         }
+      }
+   }
+
+   ///Write a factor of a product, putting sums and products in parentheses.
+   fn fmt_factor(&self, f: &mut fmt::Formatter,) -> fmt::Result {
+      match self {
+         Calc::Product(_,) | Calc::Invert(_,) => {
+            f.write_str("(",)?;
+            self.fmt_term(f,)?;
+            f.write_str(")",)
+         }
+         _ => self.fmt_term(f,),
+      }
+   }
+}
+
+///The type two operands of a sum or comparison have together.
+fn combine(acc: Option<CalcKind,>, kind: CalcKind,) -> Option<CalcKind,> {
+   match (acc, kind,) {
+      (None, kind,) => Some(kind,),
+      (Some(a,), b,) if a == b => Some(a,),
+      (Some(CalcKind::Number,), _,) | (Some(_,), CalcKind::Number,) => None,
+      _ => Some(CalcKind::LengthPercentage,),
+   }
+}
+
+///Can two leaves be added into one?
+fn same_unit(a: &Value, b: &Value,) -> bool {
+   match (a, b,) {
+      (Value::Number(_,), Value::Number(_,),) | (Value::Percentage(_,), Value::Percentage(_,),) => true,
+      (Value::Length(_, a,), Value::Length(_, b,),) => a == b,
+      _ => false,
+   }
+}
+
+///The numeric part of a leaf, with absolute lengths in px.
+fn leaf_amount(val: &Value,) -> f64 {
+   match *val {
+      Value::Number(n,) | Value::Percentage(n,) => n,
+      Value::Length(f, Unit::Em | Unit::Rem,) => f,
+      Value::Length(..,) => val.to_px(),
+      _ => 0.0,
+   }
+}
+
+///A leaf times 'k', with absolute lengths converted to px.
+fn scale_leaf(val: &Value, k: f64,) -> Value {
+   let amount = leaf_amount(val,) * k;
+   match val {
+      Value::Number(_,) => Value::Number(amount,),
+      Value::Percentage(_,) => Value::Percentage(amount,),
+      Value::Length(_, unit @ (Unit::Em | Unit::Rem),) => Value::Length(amount, unit.clone(),),
+      Value::Length(..,) => Value::Length(amount, Unit::Px,),
+      other => other.clone(),
+   }
+}
+
+impl std::ops::Add<f64,> for Value {
+   type Output = Value;
+
+   ///Add to the numeric part of a leaf.
+   fn add(self, rhs: f64,) -> Value {
+      match self {
+         Value::Number(n,) => Value::Number(n + rhs,),
+         Value::Percentage(n,) => Value::Percentage(n + rhs,),
+         Value::Length(f, unit,) => Value::Length(f + rhs, unit,),
+         other => other,
+      }
+   }
+}
+
+impl Parser {
+   ///Parse the arguments of a math function after its '(', e.g. 'min(10px, 5%)'.
+   /// 'None' if they aren't a valid expression for 'nam', or mix types that
+   /// can't be combined, as 'calc(1px + 2)' does.
+   pub fn parse_math_fn(&mut self, nam: &str,) -> Option<Calc,> {
+      let mut args = vec![];
+      loop {
+         self.cnsm_whitespace();
+         args.push(self.parse_calc_sum()?,);
+         self.cnsm_whitespace();
+         match (!self.eof()).then(|| self.cnsm_chr(),) {
+            Some(',',) => {}
+            Some(')',) => break,
+            _ => return None,
+         }
+      }
+      let calc = match (nam, args.len(),) {
+         ("calc", 1,) => args.pop(),
+         ("min", _,) => Some(Calc::min(args,),),
+         ("max", _,) => Some(Calc::max(args,),),
+         ("clamp", 3,) => {
+            let hi = args.pop()?;
+            let val = args.pop()?;
+            Some(Calc::clamp(args.pop()?, val, hi,),)
+         }
+         _ => None,
+      };
+      calc.filter(|calc| calc.kind().is_some(),)
+   }
+
+   ///Parse 'product [(+|-) product]...'. The operators need whitespace around them.
+   fn parse_calc_sum(&mut self,) -> Option<Calc,> {
+      let mut terms = vec![self.parse_calc_product()?];
+      loop {
+         let start = self.pos;
+         self.cnsm_whitespace();
+         let op = if self.eof() { ')' } else { self.next_char() };
+         if matches!(op, '+' | '-') && self.inp[self.pos + 1..].starts_with(char::is_whitespace,) {
+            self.cnsm_chr();
+            self.cnsm_whitespace();
+            let term = self.parse_calc_product()?;
+            terms.push(if op == '-' { term.scale(-1.0,) } else { term },);
+         } else {
+            self.pos = start;
+            break;
+         }
+      }
+      Some(Calc::sum(terms,),)
+   }
+
+   ///Parse 'value [(*|/) value]...'. 'None' on division by zero.
+   fn parse_calc_product(&mut self,) -> Option<Calc,> {
+      let mut acc = self.parse_calc_value()?;
+      loop {
+         let start = self.pos;
+         self.cnsm_whitespace();
+         if self.eof() || !matches!(self.next_char(), '*' | '/') {
+            self.pos = start;
+            break;
+         }
+         let op = self.cnsm_chr();
+         self.cnsm_whitespace();
+         let rhs = self.parse_calc_value()?;
+         acc = match op {
+            '*' => Calc::product(acc, rhs,),
+            _ if rhs == Calc::Leaf(Value::Number(0.0,),) => return None,
+            _ => Calc::divide(acc, rhs,),
+         };
+      }
+      Some(acc,)
+   }
+
+   ///Parse a number, length, percentage, nested math function or '(sum)'.
+   fn parse_calc_value(&mut self,) -> Option<Calc,> {
+      if self.eof() {
+         return None;
+      }
+      if self.next_char() == '(' {
+         self.cnsm_chr();
+         self.cnsm_whitespace();
+         let sum = self.parse_calc_sum()?;
+         self.cnsm_whitespace();
+         return (!self.eof() && self.cnsm_chr() == ')').then_some(sum,);
+      }
+      match self.parse_val()? {
+         Value::Calc(calc,) => Some(*calc,),
+         val @ (Value::Number(_,) | Value::Length(..,) | Value::Percentage(_,)) => Some(Calc::Leaf(scale_leaf(&val, 1.0,),),),
+         _ => None,
+      }
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use {super::*, crate::css};
+
+   ///'src' parsed and serialized again. 'None' if it's invalid.
+   fn parsed(src: &str,) -> Option<String,> { css::parse_value(src.to_string(),).map(|val| val.to_string(),) }
+
+   #[test]
+   fn same_units_are_added() {
+      assert_eq!(parsed("calc(10px + 5px)").as_deref(), Some("15px"));
+      assert_eq!(parsed("calc(2 * (75pt - 90px) / 2)").as_deref(), Some("10px"));
+      assert_eq!(parsed("calc(100% - 10px + 1em)").as_deref(), Some("calc(100% - 10px + 1em)"));
+   }
+
+   #[test]
+   fn mixed_types_are_rejected() {
+      assert_eq!(parsed("calc(1px + 2)"), None);
+      assert_eq!(parsed("calc(1px * 2px)"), None);
+      assert_eq!(parsed("min(10%, 3)"), None);
+      assert_eq!(parsed("calc(10px / 0)"), None);
+      assert_eq!(parsed("calc(10px / (2 - 2))"), None);
+   }
+
+   #[test]
+   fn kind_of_mixed_lengths_and_percentages() {
+      let kind = |src: &str| match css::parse_value(src.to_string(),) {
+         Some(Value::Calc(calc,),) => calc.kind(),
+         Some(Value::Number(_,),) => Some(CalcKind::Number,),
+         _ => None,
+      };
+      assert!(kind("calc(100% - 10px)") == Some(CalcKind::LengthPercentage));
+      assert!(kind("calc(2em * 3 - 1px)") == Some(CalcKind::Length));
+      assert!(kind("calc(2 * 3)") == Some(CalcKind::Number));
+   }
+
+   #[test]
+   fn min_max_and_clamp_fold() {
+      assert_eq!(parsed("min(10px, 5px, 30pt)").as_deref(), Some("5px"));
+      assert_eq!(parsed("max(10px, 5px)").as_deref(), Some("10px"));
+      assert_eq!(parsed("clamp(10px, 50px, 20px)").as_deref(), Some("20px"));
+      assert_eq!(parsed("min(10px, 5%)").as_deref(), Some("min(10px, 5%)"));
+      assert_eq!(parsed("clamp(1em, 50%, 20px)").as_deref(), Some("max(1em, min(50%, 20px))"));
+   }
+
+   #[test]
+   fn negative_scale_swaps_min_and_max() {
+      let min = Calc::min(vec![Calc::Leaf(Value::Length(10.0, Unit::Px,),), Calc::Leaf(Value::Percentage(5.0,),)],);
+      assert_eq!(min.clone().scale(-2.0,).to_string(), "max(-20px, -10%)");
+      assert_eq!(min.scale(2.0,).to_string(), "min(20px, 10%)");
+      assert_eq!(parsed("calc(10px - min(1em, 5%))").as_deref(), Some("calc(10px + max(-1em, -5%))"));
+   }
+
+   #[test]
+   fn math_resolves_against_the_font_size_and_basis() {
+      let Some(Value::Calc(calc,),) = css::parse_value("calc(50% - 1em + 2rem)".to_string(),) else { panic!() };
+      assert_eq!(calc.eval(10.0, 20.0, 200.0,), 130.0);
+   }
+}
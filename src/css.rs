@@ -1,11 +1,40 @@
 #![allow(unused)]
+use std::fmt;
 
-use crate::Parser;
+use crate::{
+   calc::Calc,
+   font::FontFaceRule,
+   media::{MediaCondition, MediaQueryList},
+   properties::{LonghandId, PropertyId},
+   Parser,
+};
 
 pub type Specificity = (usize, usize, usize,);
 
 pub struct Stylesheet {
-   pub rules: Vec<Rule,>,
+   pub origin: Origin,
+   pub rules:  Vec<CssRule,>,
+}
+
+///Where a stylesheet comes from. Normal declarations of a later origin win
+/// over earlier ones, and '!important' declarations reverse the order.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord,)]
+pub enum Origin {
+   UserAgent,
+   User,
+   Author,
+}
+
+///A rule at the top level of a stylesheet or nested in an at-rule.
+pub enum CssRule {
+   Style(Rule,),
+   Media(MediaRule,),
+   Supports(SupportsRule,),
+   Layer(LayerRule,),
+   ///'@layer a, b.c;', which fixes the order of layers before their rules appear.
+   LayerStatement(Vec<Vec<String,>,>,),
+   Container(ContainerRule,),
+   FontFace(FontFaceRule,),
 }
 
 pub struct Rule {
@@ -13,18 +42,89 @@ pub struct Rule {
    pub declarations: Vec<Declaration,>,
 }
 
+///'@media <queries> { <rules> }'
+pub struct MediaRule {
+   pub queries: MediaQueryList,
+   pub rules:   Vec<CssRule,>,
+}
+
+///'@supports <condition> { <rules> }'
+pub struct SupportsRule {
+   pub condition: SupportsCondition,
+   pub rules:     Vec<CssRule,>,
+}
+
+pub enum SupportsCondition {
+   ///'(<property>: <value>)', with the value as written.
+   Declaration(String, String,),
+   Not(Box<SupportsCondition,>,),
+   And(Vec<SupportsCondition,>,),
+   Or(Vec<SupportsCondition,>,),
+   ///Anything else in parentheses, such as 'selector(...)', kept as written. It is false.
+   Unknown(String,),
+}
+
+impl SupportsCondition {
+   ///Evaluate against the properties and values the engine can parse.
+   pub fn matches(&self,) -> bool {
+      match self {
+         SupportsCondition::Declaration(nam, val,) => supports_declaration(nam, val,),
+         SupportsCondition::Not(c,) => !c.matches(),
+         SupportsCondition::And(cs,) => cs.iter().all(SupportsCondition::matches,),
+         SupportsCondition::Or(cs,) => cs.iter().any(SupportsCondition::matches,),
+         SupportsCondition::Unknown(_,) => false,
+      }
+   }
+}
+
+///'@layer <name>? { <rules> }'. A layer name like 'a.b' is stored as its parts.
+pub struct LayerRule {
+   ///'None' for an anonymous layer.
+   pub name:  Option<Vec<String,>,>,
+   pub rules: Vec<CssRule,>,
+}
+
+///'@container <name>? <condition> { <rules> }'
+pub struct ContainerRule {
+   pub name:      Option<String,>,
+   pub condition: MediaCondition,
+   pub rules:     Vec<CssRule,>,
+}
+
 pub enum Selector {
    Simple(SimpleSelector,),
+   ///Simple selectors joined by combinators, e.g. 'ul > li span'. Those before
+   /// the subject are outermost first, each with the combinator after it.
+   Complex(Vec<(SimpleSelector, Combinator,),>, SimpleSelector,),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq,)]
+pub enum Combinator {
+   ///'a b': 'b' is anywhere inside 'a'.
+   Descendant,
+   ///'a > b': 'b' is a child of 'a'.
+   Child,
 }
 
 impl Selector {
    ///Ranking when confilct occurs
    pub fn specificity(&self,) -> Specificity {
-      let Selector::Simple(ref simple,) = *self;
-      let a = simple.id.iter().count();
-      let b = simple.class.len();
-      let c = simple.tag_name.iter().count();
-      (a, b, c,)
+      let simple_specificity =
+         |simple: &SimpleSelector| (simple.id.iter().count(), simple.class.len(), simple.tag_name.iter().count(),);
+      match self {
+         Selector::Simple(simple,) => simple_specificity(simple,),
+         Selector::Complex(ancestors, subject,) => ancestors
+            .iter()
+            .map(|(simple, _,)| simple_specificity(simple,),)
+            .fold(simple_specificity(subject,), |(a, b, c,), (x, y, z,)| (a + x, b + y, c + z,),),
+      }
+   }
+
+   ///The rightmost simple selector, which the element itself must match.
+   pub fn subject(&self,) -> &SimpleSelector {
+      match self {
+         Selector::Simple(simple,) | Selector::Complex(_, simple,) => simple,
+      }
    }
 }
 
@@ -35,29 +135,62 @@ pub struct SimpleSelector {
 }
 
 pub struct Declaration {
-   pub nam: String,
-   pub val: Value,
+   pub id:        PropertyId,
+   pub val:       Value,
+   pub important: bool,
 }
 
 #[derive(Clone, PartialEq,)]
 pub enum Value {
    Keyword(String,),
    Length(f64, Unit,),
-   ColorValue(Color,),
+   ///An angle in degrees, written in 'deg', 'grad', 'rad' or 'turn'.
+   Angle(f64,),
+   Percentage(f64,),
+   Number(f64,),
+   Color(Color,),
+   Str(String,),
+   ///Comma-separated values, e.g. 'font-family: Arial, sans-serif'
+   List(Vec<Value,>,),
+   ///',' or '/' between component values. Only seen before shorthand expansion.
+   Delim(char,),
+   ///Raw CSS of a custom property, or of a declaration using 'var()' that can
+   /// only be parsed once variables are substituted. 'shorthand' names the
+   /// shorthand the declaration was written with, if any.
+   Unparsed {
+      css:       String,
+      shorthand: Option<String,>,
+   },
+   ///A math function that couldn't be simplified to a single value, e.g. 'calc(100% - 10px)'.
+   Calc(Box<Calc,>,),
+   ///Any other function, with its comma-separated arguments, e.g. 'rotate(45deg)'.
+   Function(String, Vec<Value,>,),
 }
 
 impl Value {
    pub fn to_px(&self,) -> f64 {
       match self {
          Value::Length(f, Unit::Px,) => *f,
+         Value::Length(f, Unit::Pt,) => *f * 4.0 / 3.0,
          _ => 0.0,
       }
    }
+
+   ///The keyword's name if this is a 'Keyword'.
+   pub fn keyword(&self,) -> Option<&str,> {
+      match self {
+         Value::Keyword(kw,) => Some(kw,),
+         _ => None,
+      }
+   }
 }
 
 #[derive(PartialEq, Clone,)]
 pub enum Unit {
    Px,
+   Pt,
+   Em,
+   Rem,
 }
 
 #[derive(Clone, PartialEq,)]
@@ -68,55 +201,591 @@ pub struct Color {
    pub a: u8,
 }
 
+//Serialization. ------------------------
+
+impl fmt::Display for Value {
+   fn fmt(&self, f: &mut fmt::Formatter,) -> fmt::Result {
+      match self {
+         Value::Keyword(kw,) => f.write_str(kw,),
+         Value::Length(n, unit,) => write!(f, "{n}{unit}"),
+         Value::Angle(n,) => write!(f, "{n}deg"),
+         Value::Percentage(n,) => write!(f, "{n}%"),
+         Value::Number(n,) => write!(f, "{n}"),
+         Value::Color(clr,) => write!(f, "{clr}"),
+         Value::Str(s,) => f.write_str(&serialize_string(s,),),
+         //Items of a comma-separated list may be space-separated lists.
+         Value::List(vals,) => {
+            for (i, val,) in vals.iter().enumerate() {
+               if i > 0 {
+                  f.write_str(", ",)?;
+               }
+               match val {
+                  Value::List(words,) => {
+                     let words: Vec<String,> = words.iter().map(Value::to_string,).collect();
+                     f.write_str(&words.join(" ",),)?;
+                  }
+                  _ => write!(f, "{val}")?,
+               }
+            }
+            Ok((),)
+         }
+         Value::Delim(c,) => write!(f, "{c}"),
+         Value::Unparsed { css, .. } => f.write_str(css,),
+         Value::Calc(calc,) => write!(f, "{calc}"),
+         Value::Function(nam, args,) => {
+            let args: Vec<String,> = args.iter().map(Value::to_string,).collect();
+            write!(f, "{nam}({})", args.join(", ",))
+         }
+      }
+   }
+}
+
+impl fmt::Display for Unit {
+   fn fmt(&self, f: &mut fmt::Formatter,) -> fmt::Result {
+      f.write_str(match self {
+         Unit::Px => "px",
+         Unit::Pt => "pt",
+         Unit::Em => "em",
+         Unit::Rem => "rem",
+      },)
+   }
+}
+
+impl fmt::Display for Color {
+   ///'#rrggbb', or '#rrggbbaa' if not opaque.
+   fn fmt(&self, f: &mut fmt::Formatter,) -> fmt::Result {
+      write!(f, "#{:02x}{:02x}{:02x}", self.r, self.g, self.b)?;
+      if self.a != 255 {
+         write!(f, "{:02x}", self.a)?;
+      }
+      Ok((),)
+   }
+}
+
+impl fmt::Display for Selector {
+   fn fmt(&self, f: &mut fmt::Formatter,) -> fmt::Result { f.write_str(&self.css(" > ",),) }
+}
+
+impl Selector {
+   ///The selector as CSS, with 'child' written for the '>' combinator.
+   fn css(&self, child: &str,) -> String {
+      let mut out = String::new();
+      if let Selector::Complex(ancestors, _,) = self {
+         for (simple, combinator,) in ancestors {
+            out.push_str(&simple.to_string(),);
+            out.push_str(match combinator {
+               Combinator::Descendant => " ",
+               Combinator::Child => child,
+            },);
+         }
+      }
+      out.push_str(&self.subject().to_string(),);
+      out
+   }
+}
+
+impl fmt::Display for SimpleSelector {
+   fn fmt(&self, f: &mut fmt::Formatter,) -> fmt::Result {
+      match &self.tag_name {
+         Some(tag,) => f.write_str(tag,)?,
+         None if self.id.is_none() && self.class.is_empty() => f.write_str("*",)?,
+         None => {}
+      }
+      if let Some(id,) = &self.id {
+         write!(f, "#{id}")?;
+      }
+      for class in &self.class {
+         write!(f, ".{class}")?;
+      }
+      Ok((),)
+   }
+}
+
+impl fmt::Display for Declaration {
+   fn fmt(&self, f: &mut fmt::Formatter,) -> fmt::Result {
+      write!(f, "{}: {}", self.id.name(), self.val)?;
+      if self.important {
+         f.write_str(" !important",)?;
+      }
+      Ok((),)
+   }
+}
+
+impl fmt::Display for SupportsCondition {
+   fn fmt(&self, f: &mut fmt::Formatter,) -> fmt::Result {
+      match self {
+         SupportsCondition::Declaration(nam, val,) => write!(f, "({nam}: {val})"),
+         SupportsCondition::Not(c,) => {
+            f.write_str("not ",)?;
+            c.fmt_in_parens(f,)
+         }
+         SupportsCondition::And(cs,) | SupportsCondition::Or(cs,) => {
+            let joiner = if matches!(self, SupportsCondition::And(_)) { " and " } else { " or " };
+            for (i, c,) in cs.iter().enumerate() {
+               if i > 0 {
+                  f.write_str(joiner,)?;
+               }
+               c.fmt_in_parens(f,)?;
+            }
+            Ok((),)
+         }
+         SupportsCondition::Unknown(css,) => f.write_str(css,),
+      }
+   }
+}
+
+impl SupportsCondition {
+   ///Write the condition as an operand of 'not', 'and' or 'or'.
+   fn fmt_in_parens(&self, f: &mut fmt::Formatter,) -> fmt::Result {
+      match self {
+         SupportsCondition::Not(_,) | SupportsCondition::And(_,) | SupportsCondition::Or(_,) => write!(f, "({self})"),
+         _ => write!(f, "{self}"),
+      }
+   }
+}
+
+///'s' as a quoted CSS string. The parser has no escapes, so a string containing
+/// '"' is put in single quotes.
+pub fn serialize_string(s: &str,) -> String {
+   if s.contains('"',) {
+      format!("'{s}'")
+   } else {
+      format!("\"{s}\"")
+   }
+}
+
+impl fmt::Display for Stylesheet {
+   ///Canonical CSS with one declaration per line and nested rules indented.
+   fn fmt(&self, f: &mut fmt::Formatter,) -> fmt::Result {
+      let mut writer = CssWriter { out: String::new(), minify: false, depth: 0, };
+      writer.rules(&self.rules,);
+      f.write_str(&writer.out,)
+   }
+}
+
+impl fmt::Display for CssRule {
+   fn fmt(&self, f: &mut fmt::Formatter,) -> fmt::Result {
+      let mut writer = CssWriter { out: String::new(), minify: false, depth: 0, };
+      writer.rule(self,);
+      f.write_str(&writer.out,)
+   }
+}
+
+impl Stylesheet {
+   ///The stylesheet as CSS without optional whitespace or the last ';' of blocks.
+   pub fn minified(&self,) -> String {
+      let mut writer = CssWriter { out: String::new(), minify: true, depth: 0, };
+      writer.rules(&self.rules,);
+      writer.out
+   }
+}
+
+///Writes rules as CSS text, either indented or minified.
+struct CssWriter {
+   out:    String,
+   minify: bool,
+   ///Number of blocks the next line is nested in.
+   depth:  usize,
+}
+
+impl CssWriter {
+   fn rules(&mut self, rules: &[CssRule],) {
+      for rule in rules {
+         self.rule(rule,);
+      }
+   }
+
+   fn rule(&mut self, rule: &CssRule,) {
+      match rule {
+         CssRule::Style(rule,) => {
+            let child = if self.minify { ">" } else { " > " };
+            let selectors: Vec<String,> = rule.selectors.iter().map(|selector| selector.css(child,),).collect();
+            self.open(&selectors.join(if self.minify { "," } else { ", " },),);
+            self.declarations(declaration_texts(&rule.declarations,),);
+            self.close();
+         }
+         CssRule::Media(media,) => self.group(format!("@media {}", media.queries).trim_end(), &media.rules,),
+         CssRule::Supports(supports,) => self.group(&format!("@supports {}", supports.condition), &supports.rules,),
+         CssRule::Layer(layer,) => match &layer.name {
+            Some(name,) => self.group(&format!("@layer {}", name.join(".",)), &layer.rules,),
+            None => self.group("@layer", &layer.rules,),
+         },
+         CssRule::LayerStatement(names,) => {
+            let names: Vec<String,> = names.iter().map(|name| name.join(".",),).collect();
+            self.line(&format!("@layer {};", names.join(if self.minify { "," } else { ", " },)),);
+         }
+         CssRule::Container(container,) => {
+            let prelude = match &container.name {
+               Some(name,) => format!("@container {name} {}", container.condition),
+               None => format!("@container {}", container.condition),
+            };
+            self.group(&prelude, &container.rules,);
+         }
+         CssRule::FontFace(font_face,) => {
+            self.open("@font-face",);
+            self.declarations(font_face.descriptors().into_iter().map(|(nam, val,)| (nam.to_string(), val, false,),).collect(),);
+            self.close();
+         }
+      }
+   }
+
+   ///Write an at-rule with nested rules.
+   fn group(&mut self, prelude: &str, rules: &[CssRule],) {
+      self.open(prelude,);
+      self.rules(rules,);
+      self.close();
+   }
+
+   fn open(&mut self, prelude: &str,) {
+      self.line(prelude,);
+      self.out.push_str(if self.minify { "{" } else { " {" },);
+      self.depth += 1;
+   }
+
+   fn close(&mut self,) {
+      self.depth -= 1;
+      self.line("}",);
+   }
+
+   ///Write '(name, value, important)' declarations. Minified, the last one has no ';'.
+   fn declarations(&mut self, declarations: Vec<(String, String, bool,),>,) {
+      for (i, (nam, val, important,),) in declarations.iter().enumerate() {
+         let important = if !*important {
+            ""
+         } else if self.minify {
+            "!important"
+         } else {
+            " !important"
+         };
+         match self.minify {
+            true if i + 1 == declarations.len() => self.line(&format!("{nam}:{val}{important}"),),
+            true => self.line(&format!("{nam}:{val}{important};"),),
+            false => self.line(&format!("{nam}: {val}{important};"),),
+         }
+      }
+   }
+
+   ///Start a line at the current depth. Minified output has no line breaks.
+   fn line(&mut self, text: &str,) {
+      if !self.minify {
+         if !self.out.is_empty() {
+            self.out.push('\n',);
+         }
+         self.out.push_str(&"  ".repeat(self.depth,),);
+      }
+      self.out.push_str(text,);
+   }
+}
+
+///'(name, value, important)' of each declaration. The longhands of a shorthand
+/// that uses 'var()' are written back as the shorthand, as long as all of them are
+/// still there.
+fn declaration_texts(declarations: &[Declaration],) -> Vec<(String, String, bool,),> {
+   let mut texts = vec![];
+   let mut rest = declarations;
+   while let [decl, tail @ ..] = rest {
+      if let Value::Unparsed { css, shorthand: Some(shorthand,), } = &decl.val {
+         let count = longhands(shorthand,).map_or(usize::MAX, |names| names.len(),);
+         let run = rest.iter().take_while(|d| d.val == decl.val && d.important == decl.important,).count();
+         if run >= count {
+            texts.push((shorthand.clone(), css.clone(), decl.important,),);
+            rest = &rest[count..];
+            continue;
+         }
+      }
+      texts.push((decl.id.name().to_string(), decl.val.to_string(), decl.important,),);
+      rest = tail;
+   }
+   texts
+}
+
 impl Parser {
    ///Parse a list of rule sets, separated by optional whitespace.
-   fn parse_rules(&mut self,) -> Vec<Rule,> {
+   fn parse_rules(&mut self,) -> Vec<CssRule,> {
       let mut rules = vec![];
       loop {
          self.cnsm_whitespace();
-         if self.eof() {
+         //A block of nested rules ends at '}'.
+         if self.eof() || self.next_char() == '}' {
             break;
          }
-         rules.push(self.parse_rule(),);
+         if self.next_char() == '@' {
+            rules.extend(self.parse_at_rule(),);
+         } else {
+            //A rule set with an invalid or unsupported selector is dropped whole.
+            match self.parse_rule() {
+               Some(rule,) => rules.push(CssRule::Style(rule,),),
+               None => self.skip_at_rule(),
+            }
+         }
       }
       rules
    }
 
-   ///Parse a rule set: '<selectors>{<declarations>}'.
-   fn parse_rule(&mut self,) -> Rule { Rule { selectors: self.parse_selectors(), declarations: self.parse_declarations(), } }
+   ///Parse the rules of a whole stylesheet. A '}' that closes no block is skipped.
+   fn parse_top_level_rules(&mut self,) -> Vec<CssRule,> {
+      let mut rules = self.parse_rules();
+      while !self.eof() {
+         self.cnsm_chr();
+         rules.extend(self.parse_rules(),);
+      }
+      rules
+   }
 
-   ///Parse a comma-separated list of selectors.
-   fn parse_selectors(&mut self,) -> Vec<Selector,> {
-      let mut selectors = vec![];
+   ///Parse an at-rule. Unknown at-rules are skipped.
+   fn parse_at_rule(&mut self,) -> Option<CssRule,> {
+      assert_eq!(self.cnsm_chr(), '@');
+      let nam = self.parse_idf().to_ascii_lowercase();
+      self.cnsm_whitespace();
+      match &*nam {
+         "media" => {
+            let queries = self.parse_media_query_list();
+            Some(CssRule::Media(MediaRule { queries, rules: self.parse_rule_block()?, },),)
+         }
+         //A rule whose condition can't be parsed is dropped.
+         "supports" => {
+            let Some(condition,) = self.parse_supports_condition() else {
+               self.skip_at_rule();
+               return None;
+            };
+            self.cnsm_whitespace();
+            Some(CssRule::Supports(SupportsRule { condition, rules: self.parse_rule_block()?, },),)
+         }
+         "layer" => self.parse_layer_rule(),
+         "container" => {
+            let name = match (!self.eof()).then(|| self.next_char(),) {
+               None | Some('(',) => None,
+               _ if self.starts_with_keyword("not",) => None,
+               _ => Some(self.parse_idf(),),
+            };
+            self.cnsm_whitespace();
+            let Some(condition,) = self.parse_media_condition(true,) else {
+               self.skip_at_rule();
+               return None;
+            };
+            self.cnsm_whitespace();
+            Some(CssRule::Container(ContainerRule { name, condition, rules: self.parse_rule_block()?, },),)
+         }
+         //Without a block, it's skipped like an unknown at-rule.
+         "font-face" if !self.eof() && self.next_char() == '{' => self.parse_font_face().map(CssRule::FontFace,),
+         _ => {
+            self.skip_at_rule();
+            None
+         }
+      }
+   }
+
+   ///Parse the rest of '@layer <name> {...}', '@layer {...}' or '@layer <name>, <name>;'.
+   /// 'None' if it's invalid or its block isn't closed.
+   fn parse_layer_rule(&mut self,) -> Option<CssRule,> {
+      if !self.eof() && self.next_char() == '{' {
+         return Some(CssRule::Layer(LayerRule { name: None, rules: self.parse_rule_block()?, },),);
+      }
+      let mut names = vec![];
       loop {
-         selectors.push(Selector::Simple(self.parse_simple_selector(),),);
+         let mut name = vec![self.parse_idf()];
+         while !self.eof() && self.next_char() == '.' {
+            self.cnsm_chr();
+            name.push(self.parse_idf(),);
+         }
+         let valid = name.iter().all(|part| !part.is_empty(),);
+         names.push(name,);
          self.cnsm_whitespace();
+         if !valid || self.eof() {
+            break;
+         }
          match self.next_char() {
-            '.' => {
+            '{' if names.len() == 1 => {
+               return Some(CssRule::Layer(LayerRule { name: names.pop(), rules: self.parse_rule_block()?, },),);
+            }
+            ',' => {
                self.cnsm_chr();
                self.cnsm_whitespace();
             }
-            '{' => break,
-            c => panic!("Unexpected cahracter {c} in selector list"),
+            ';' => {
+               self.cnsm_chr();
+               return Some(CssRule::LayerStatement(names,),);
+            }
+            _ => break,
+         }
+      }
+      self.skip_at_rule();
+      None
+   }
+
+   ///Parse 'not <in-parens>' or '<in-parens>' joined by all 'and' or all 'or'.
+   /// 'None' if it's invalid, e.g. mixes 'and' and 'or'.
+   fn parse_supports_condition(&mut self,) -> Option<SupportsCondition,> {
+      if self.starts_with_keyword("not",) {
+         self.parse_media_keyword();
+         self.cnsm_whitespace();
+         return Some(SupportsCondition::Not(Box::new(self.parse_supports_in_parens()?,),),);
+      }
+      let mut conditions = vec![self.parse_supports_in_parens()?];
+      let mut joiner = None;
+      loop {
+         let start = self.pos;
+         self.cnsm_whitespace();
+         let kw = match () {
+            _ if self.starts_with_keyword("and",) => "and",
+            _ if self.starts_with_keyword("or",) => "or",
+            _ => {
+               self.pos = start;
+               break;
+            }
+         };
+         if joiner.is_some_and(|j| j != kw,) {
+            return None;
+         }
+         joiner = Some(kw,);
+         self.parse_media_keyword();
+         self.cnsm_whitespace();
+         conditions.push(self.parse_supports_in_parens()?,);
+      }
+      match joiner {
+         None => conditions.pop(),
+         Some("and",) => Some(SupportsCondition::And(conditions,),),
+         Some(_,) => Some(SupportsCondition::Or(conditions,),),
+      }
+   }
+
+   ///Parse '(<condition>)', '(<declaration>)' or a function like 'selector(...)'.
+   /// Anything else in parentheses is unknown. 'None' if there's no '(' or it
+   /// isn't closed.
+   fn parse_supports_in_parens(&mut self,) -> Option<SupportsCondition,> {
+      let start = self.pos;
+      //A function, which the engine doesn't support.
+      let function = !self.parse_idf().is_empty();
+      if self.eof() || self.cnsm_chr() != '(' {
+         return None;
+      }
+      self.cnsm_whitespace();
+      let inner = self.pos;
+      if !function && !self.eof() && (self.next_char() == '(' || self.starts_with_keyword("not",)) {
+         if let Some(condition,) = self.parse_supports_condition() {
+            self.cnsm_whitespace();
+            if !self.eof() && self.cnsm_chr() == ')' {
+               return Some(condition,);
+            }
+         }
+         self.pos = inner;
+      }
+      let len = block_len(&self.inp[self.pos..],)?;
+      let text = self.inp[self.pos..self.pos + len].to_string();
+      self.pos += len + 1;
+      match text.split_once(':',) {
+         Some((nam, val,),) if !function && !nam.trim().is_empty() && nam.trim().chars().all(valid_idf_chr,) => {
+            let nam = nam.trim();
+            let nam = if nam.starts_with("--",) { nam.to_string() } else { nam.to_ascii_lowercase() };
+            Some(SupportsCondition::Declaration(nam, val.trim().to_string(),),)
+         }
+         _ => Some(SupportsCondition::Unknown(self.inp[start..self.pos].to_string(),),),
+      }
+   }
+
+   ///Parse '{<rules>}'. 'None' if the block is missing or isn't closed.
+   fn parse_rule_block(&mut self,) -> Option<Vec<CssRule,>,> {
+      if self.eof() || self.next_char() != '{' {
+         self.skip_at_rule();
+         return None;
+      }
+      self.cnsm_chr();
+      let rules = self.parse_rules();
+      if self.eof() {
+         return None;
+      }
+      self.cnsm_chr();
+      Some(rules,)
+   }
+
+   ///Skip the rest of an at-rule or rule set, up to its ';' or the end of its
+   /// block. The '}' of an enclosing block is left to it.
+   fn skip_at_rule(&mut self,) {
+      let mut depth = 0;
+      while !(self.eof() || depth == 0 && self.next_char() == '}') {
+         match self.cnsm_chr() {
+            ';' if depth == 0 => return,
+            '{' => depth += 1,
+            '}' if depth == 1 => return,
+            '}' => depth -= 1,
+            _ => {}
+         }
+      }
+   }
+
+   ///Parse a rule set: '<selectors>{<declarations>}'. 'None' if a selector is
+   /// invalid or unsupported, or the block isn't closed.
+   fn parse_rule(&mut self,) -> Option<Rule,> {
+      Some(Rule { selectors: self.parse_selectors()?, declarations: self.parse_declarations()?, },)
+   }
+
+   ///Parse a comma-separated list of selectors, up to the '{' after it. 'None'
+   /// if any selector is invalid or unsupported.
+   fn parse_selectors(&mut self,) -> Option<Vec<Selector,>,> {
+      let mut selectors = vec![];
+      loop {
+         selectors.push(self.parse_selector()?,);
+         match self.next_char() {
+            ',' => {
+               self.cnsm_chr();
+               self.cnsm_whitespace();
+            }
+            _ => break,
          }
       }
       //Return selectors with highest specificity first, for use in matching.
-      selectors.sort_by(|st, nd| nd.specificity().cmp(&st.specificity(),),);
-      selectors
+      selectors.sort_by_key(|slctr| std::cmp::Reverse(slctr.specificity(),),);
+      Some(selectors,)
+   }
+
+   ///Parse simple selectors joined by combinators, up to the ',' or '{' after them.
+   fn parse_selector(&mut self,) -> Option<Selector,> {
+      let mut ancestors = vec![];
+      loop {
+         let start = self.pos;
+         let simple = self.parse_simple_selector()?;
+         if self.pos == start {
+            return None;
+         }
+         self.cnsm_whitespace();
+         if self.eof() {
+            return None;
+         }
+         let combinator = match self.next_char() {
+            ',' | '{' if ancestors.is_empty() => return Some(Selector::Simple(simple,),),
+            ',' | '{' => return Some(Selector::Complex(ancestors, simple,),),
+            '>' => {
+               self.cnsm_chr();
+               self.cnsm_whitespace();
+               Combinator::Child
+            }
+            _ => Combinator::Descendant,
+         };
+         ancestors.push((simple, combinator,),);
+      }
    }
 
-   ///Parse one simple seelctor, e.g.: 'type#id.class1.class2.class3'
-   fn parse_simple_selector(&mut self,) -> SimpleSelector {
+   ///Parse one simple seelctor, e.g.: 'type#id.class1.class2.class3'. 'None'
+   /// if a '#' or '.' has no name after it.
+   fn parse_simple_selector(&mut self,) -> Option<SimpleSelector,> {
       let mut selector = SimpleSelector { tag_name: None, id: None, class: vec![], };
       while !self.eof() {
          match self.next_char() {
             '#' => {
                self.cnsm_chr();
-               selector.id = Some(self.parse_idf(),);
+               let id = self.parse_idf();
+               if id.is_empty() {
+                  return None;
+               }
+               selector.id = Some(id,);
             }
             '.' => {
                self.cnsm_chr();
-               selector.class.push(self.parse_idf(),);
+               let class = self.parse_idf();
+               if class.is_empty() {
+                  return None;
+               }
+               selector.class.push(class,);
             }
             '*' => {
                //universal selector
@@ -128,88 +797,1106 @@ impl Parser {
             _ => break,
          }
       }
-      selector
+      Some(selector,)
    }
 
-   ///Parse a list of declarations enclosed in '{...}'.
-   fn parse_declarations(&mut self,) -> Vec<Declaration,> {
+   ///Parse a list of declarations enclosed in '{...}'. 'None' if the block
+   /// isn't closed.
+   fn parse_declarations(&mut self,) -> Option<Vec<Declaration,>,> {
       assert_eq!(self.cnsm_chr(), '{');
+      let declarations = self.parse_declaration_list();
+      if self.eof() {
+         return None;
+      }
+      self.cnsm_chr();
+      Some(declarations,)
+   }
+
+   ///Parse declarations up to a '}' or the end of input.
+   fn parse_declaration_list(&mut self,) -> Vec<Declaration,> {
       let mut declarations = vec![];
       loop {
          self.cnsm_whitespace();
-         if self.next_char() == '}' {
-            self.cnsm_chr();
+         if self.eof() || self.next_char() == '}' {
             break;
          }
-         declarations.push(self.parse_declaration(),);
+         declarations.extend(self.parse_declaration(),);
       }
       declarations
    }
 
-   ///Parse one '<property>: <value>;' declaration.
-   fn parse_declaration(&mut self,) -> Declaration {
-      let property_name = self.parse_idf();
-      self.cnsm_whitespace();
-      assert_eq!(self.cnsm_chr(), ':');
-      self.cnsm_whitespace();
-      let val = self.parse_val();
+   ///Parse one '<property>: <value>... [!important];' declaration. Shorthands are
+   /// expanded into their longhands, and an invalid shorthand yields no declaration.
+   fn parse_declaration(&mut self,) -> Vec<Declaration,> {
+      let mut property_name = self.parse_idf();
+      //Custom property names are case-sensitive.
+      if !property_name.starts_with("--",) {
+         property_name.make_ascii_lowercase();
+      }
       self.cnsm_whitespace();
-      assert_eq!(self.cnsm_chr(), ';');
+      //Without a ':', it's no declaration, and is skipped up to its ';'.
+      if self.eof() || self.next_char() != ':' {
+         self.parse_raw_value();
+         return vec![];
+      }
+      self.cnsm_chr();
+      let (css, important,) = self.parse_raw_value();
+      let mut declarations = match property_name.starts_with("--",) || has_var(&css,) {
+         true => unparsed_declarations(property_name, css,),
+         //A value that can't be parsed drops only this declaration.
+         false => parse_declaration_value(&property_name, css,).unwrap_or_default(),
+      };
+      for decl in &mut declarations {
+         decl.important = important;
+      }
+      declarations
+   }
 
-      Declaration { nam: property_name, val, }
+   ///Consume a declaration's value up to its ';' as raw CSS, returning the text
+   /// without '!important' and whether it was important.
+   pub fn parse_raw_value(&mut self,) -> (String, bool,) {
+      let mut css = String::new();
+      let mut depth = 0;
+      while !self.eof() {
+         match self.next_char() {
+            ';' | '}' if depth == 0 => break,
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth -= 1,
+            '"' | '\'' => {
+               let quote = self.next_char();
+               css.push_str(&format!("{quote}{}{quote}", self.parse_string()),);
+               continue;
+            }
+            _ => {}
+         }
+         css.push(self.cnsm_chr(),);
+      }
+      if !self.eof() && self.next_char() == ';' {
+         self.cnsm_chr();
+      }
+      let css = css.trim();
+      let lower = css.to_ascii_lowercase();
+      match lower.strip_suffix("important",).map(str::trim_end,).and_then(|s| s.strip_suffix('!',),) {
+         Some(rest,) => (css[..rest.len()].trim_end().to_string(), true,),
+         None => (css.to_string(), false,),
+      }
    }
 
    //Methods for parsing Value. ------------------------
 
-   fn parse_val(&mut self,) -> Value {
+   ///Parse the whitespace-separated component values of a declaration. 'None'
+   /// if one of them can't be parsed.
+   fn parse_vals(&mut self,) -> Option<Vec<Value,>,> {
+      let mut vals = vec![];
+      loop {
+         self.cnsm_whitespace();
+         if self.eof() {
+            break;
+         }
+         match self.next_char() {
+            ';' | '}' | '!' => break,
+            c @ (',' | '/') => {
+               self.cnsm_chr();
+               vals.push(Value::Delim(c,),);
+            }
+            _ => vals.push(self.parse_val()?,),
+         }
+      }
+      Some(vals,)
+   }
+
+   ///Parse one component value. 'None' if it isn't one, or has a unit, color
+   /// or function the engine doesn't know.
+   pub fn parse_val(&mut self,) -> Option<Value,> {
       match self.next_char() {
-         '0'..='9' => self.parse_length(),
+         '0'..='9' | '.' | '+' | '-' if self.starts_with_number() => self.parse_numeric(),
          '#' => self.parse_color(),
-         _ => Value::Keyword(self.parse_idf(),),
+         '"' | '\'' => Some(Value::Str(self.parse_string(),),),
+         _ => {
+            let kw = self.parse_idf();
+            if kw.is_empty() {
+               return None;
+            }
+            if !self.eof() && self.next_char() == '(' {
+               self.cnsm_chr();
+               return self.parse_function(&kw.to_ascii_lowercase(),);
+            }
+            match color_keyword(&kw,) {
+               Some(clr,) => Some(Value::Color(clr,),),
+               None => Some(Value::Keyword(kw,),),
+            }
+         }
       }
    }
 
-   fn parse_length(&mut self,) -> Value { Value::Length(self.parse_float(), self.parse_unit(),) }
+   ///Parse a function's arguments after its '('.
+   fn parse_function(&mut self, nam: &str,) -> Option<Value,> {
+      if let "calc" | "min" | "max" | "clamp" = nam {
+         return Some(self.parse_math_fn(nam,)?.into_value(),);
+      }
+      let mut args = vec![];
+      loop {
+         self.cnsm_whitespace();
+         if self.eof() {
+            return None;
+         }
+         if self.next_char() == ')' && args.is_empty() {
+            self.cnsm_chr();
+            break;
+         }
+         args.push(self.parse_val()?,);
+         self.cnsm_whitespace();
+         match (!self.eof()).then(|| self.cnsm_chr(),) {
+            Some(',',) => {}
+            Some(')',) => break,
+            _ => return None,
+         }
+      }
+      Some(Value::Function(nam.to_string(), args,),)
+   }
+
+   ///Does the input start with '[+-]digit' or '[+-].digit'?
+   fn starts_with_number(&self,) -> bool {
+      let rest = self.inp[self.pos..].trim_start_matches(['+', '-',],);
+      let rest = rest.strip_prefix('.',).unwrap_or(rest,);
+      rest.starts_with(|c: char| c.is_ascii_digit(),)
+   }
+
+   ///Parse a number followed by an optional unit or '%'.
+   fn parse_numeric(&mut self,) -> Option<Value,> {
+      let num = self.parse_float()?;
+      if self.eof() {
+         return Some(Value::Number(num,),);
+      }
+      Some(match self.next_char() {
+         '%' => {
+            self.cnsm_chr();
+            Value::Percentage(num,)
+         }
+         c if c.is_ascii_alphabetic() => {
+            let start = self.pos;
+            match &*self.parse_idf().to_ascii_lowercase() {
+               "deg" => Value::Angle(num,),
+               "grad" => Value::Angle(num * 0.9,),
+               "rad" => Value::Angle(num.to_degrees(),),
+               "turn" => Value::Angle(num * 360.0,),
+               _ => {
+                  self.pos = start;
+                  Value::Length(num, self.parse_unit()?,)
+               }
+            }
+         }
+         _ => Value::Number(num,),
+      },)
+   }
 
-   fn parse_float(&mut self,) -> f64 {
-      let s = self.cnsm_while(|c| match c {
-         '0'..='9' | '.' => true,
-         _ => false,
-      },);
-      s.parse().unwrap()
+   fn parse_float(&mut self,) -> Option<f64,> {
+      let sign = match self.next_char() {
+         '+' | '-' => self.cnsm_chr(),
+         _ => '+',
+      };
+      let s = self.cnsm_while(|c| matches!(c, '0'..='9' | '.'),);
+      let f: f64 = s.parse().ok()?;
+      Some(if sign == '-' { -f } else { f },)
    }
 
-   fn parse_unit(&mut self,) -> Unit {
+   ///Parse a length unit. 'None' for units the engine doesn't know, like 'vw'.
+   fn parse_unit(&mut self,) -> Option<Unit,> {
       match &*self.parse_idf().to_ascii_lowercase() {
-         "px" => Unit::Px,
-         _ => panic!("unrecognized unit"),
+         "px" => Some(Unit::Px,),
+         "pt" => Some(Unit::Pt,),
+         "em" => Some(Unit::Em,),
+         "rem" => Some(Unit::Rem,),
+         _ => None,
       }
    }
 
-   fn parse_color(&mut self,) -> Value {
+   ///Parse '#rgb', '#rgba', '#rrggbb' or '#rrggbbaa'.
+   fn parse_color(&mut self,) -> Option<Value,> {
       assert_eq!(self.cnsm_chr(), '#');
-      Value::ColorValue(Color { r: self.parse_hex_pair(), g: self.parse_hex_pair(), b: self.parse_hex_pair(), a: 255, },)
+      let hex = self.cnsm_while(|c| c.is_ascii_hexdigit(),);
+      let digits: Vec<u8,> = match hex.len() {
+         //Short forms repeat each digit: '#f80' is '#ff8800'.
+         3 | 4 => hex.chars().map(|c| c.to_digit(16,).unwrap() as u8 * 17,).collect(),
+         6 | 8 => (0..hex.len()).step_by(2,).map(|i| parse_hex_pair(&hex[i..i + 2],),).collect(),
+         _ => return None,
+      };
+      Some(Value::Color(Color { r: digits[0], g: digits[1], b: digits[2], a: digits.get(3,).copied().unwrap_or(255,), },),)
    }
 
-   ///Parse two hexadecimal digits.
-   fn parse_hex_pair(&mut self,) -> u8 {
-      let s = &self.inp[self.pos..self.pos + 2];
-      self.pos += 2;
-      u8::from_str_radix(s, 16,).unwrap()
+   ///Parse a quoted string, returning its contents. The end of input closes
+   /// an unterminated string.
+   pub fn parse_string(&mut self,) -> String {
+      let open_quote = self.cnsm_chr();
+      let s = self.cnsm_while(|c| c != open_quote,);
+      if !self.eof() {
+         self.cnsm_chr();
+      }
+      s
    }
 
    ///Parse a property name or Keyword.
-   fn parse_idf(&mut self,) -> String { self.cnsm_while((valid_idf_chr),) }
+   pub fn parse_idf(&mut self,) -> String { self.cnsm_while(valid_idf_chr,) }
+}
+
+///Parse two hexadecimal digits.
+fn parse_hex_pair(s: &str,) -> u8 { u8::from_str_radix(s, 16,).unwrap() }
+
+fn valid_idf_chr(c: char,) -> bool { matches!(c, 'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_') }
+
+///Look up a named color such as 'red'.
+fn color_keyword(nam: &str,) -> Option<Color,> {
+   let (r, g, b, a,) = match &*nam.to_ascii_lowercase() {
+      "black" => (0, 0, 0, 255,),
+      "silver" => (192, 192, 192, 255,),
+      "gray" | "grey" => (128, 128, 128, 255,),
+      "white" => (255, 255, 255, 255,),
+      "maroon" => (128, 0, 0, 255,),
+      "red" => (255, 0, 0, 255,),
+      "purple" => (128, 0, 128, 255,),
+      "fuchsia" => (255, 0, 255, 255,),
+      "green" => (0, 128, 0, 255,),
+      "lime" => (0, 255, 0, 255,),
+      "olive" => (128, 128, 0, 255,),
+      "yellow" => (255, 255, 0, 255,),
+      "navy" => (0, 0, 128, 255,),
+      "blue" => (0, 0, 255, 255,),
+      "teal" => (0, 128, 128, 255,),
+      "aqua" => (0, 255, 255, 255,),
+      "orange" => (255, 165, 0, 255,),
+      "transparent" => (0, 0, 0, 0,),
+      _ => return None,
+   };
+   Some(Color { r, g, b, a, },)
+}
+
+//Shorthand expansion. ------------------------
+
+const SIDES: [&str; 4] = ["top", "right", "bottom", "left",];
+
+///Keywords valid for every property, shorthand or not.
+pub fn is_css_wide_keyword(kw: &str,) -> bool { matches!(kw, "inherit" | "initial" | "unset" | "revert") }
+
+///Declarations of a custom property, or of a property whose value uses 'var()'.
+/// The latter are parsed after substitution, and a shorthand sets each of its
+/// longhands to the same pending value.
+fn unparsed_declarations(nam: String, css: String,) -> Vec<Declaration,> {
+   let decl = |id, shorthand| Declaration { id, val: Value::Unparsed { css: css.clone(), shorthand, }, important: false, };
+   if nam.starts_with("--",) {
+      return vec![decl(PropertyId::Custom(nam.clone(),), None,)];
+   }
+   match longhands(&nam,) {
+      Some(names,) => names
+         .iter()
+         .filter_map(|longhand| LonghandId::from_name(longhand,),)
+         .map(|id| decl(PropertyId::Longhand(id,), Some(nam.clone(),),),)
+         .collect(),
+      None => LonghandId::from_name(&nam,).map(|id| decl(PropertyId::Longhand(id,), None,),).into_iter().collect(),
+   }
+}
+
+///Turn a parsed declaration into longhand declarations. Unknown properties and
+/// invalid values yield no declaration, and neither does a shorthand with an
+/// invalid part.
+fn expand_declaration(nam: String, vals: Vec<Value,>,) -> Vec<Declaration,> {
+   let longhands: Vec<(String, Value,),> = match longhands(&nam,) {
+      None => longhand_val(vals,).map(|val| vec![(nam, val,)],).unwrap_or_default(),
+      Some(names,) => {
+         let vals = match &*vals {
+            //'margin: inherit' sets every longhand to 'inherit'.
+            [Value::Keyword(kw,)] if is_css_wide_keyword(kw,) => Some(vec![vals[0].clone(); names.len()],),
+            _ => expand(&nam, vals,),
+         };
+         vals.map(|vals| names.into_iter().zip(vals,).collect(),).unwrap_or_default()
+      }
+   };
+   longhands
+      .into_iter()
+      .map(|(nam, val,)| {
+         let id = LonghandId::from_name(&nam,).filter(|id| id.is_valid(&val,),)?;
+         Some(Declaration { id: PropertyId::Longhand(id,), val, important: false, },)
+      },)
+      .collect::<Option<_,>>()
+      .unwrap_or_default()
+}
+
+///The value of a longhand declaration. Comma-separated values become a 'List',
+/// and space-separated keywords like 'Times New Roman' are joined. Other
+/// space-separated values are a 'List' inside the 'List', even if there is no
+/// comma, like 'transform: scale(2) rotate(45deg)'.
+fn longhand_val(vals: Vec<Value,>,) -> Option<Value,> {
+   let mut groups: Vec<Value,> = vals
+      .split(|v| *v == Value::Delim(',',),)
+      .map(|group| match group {
+         [val] => Some(val.clone(),),
+         [] => None,
+         _ if group.iter().all(|v| matches!(v, Value::Keyword(_)),) => {
+            let words: Vec<&str,> = group.iter().filter_map(|v| v.keyword(),).collect();
+            Some(Value::Keyword(words.join(" ",),),)
+         }
+         _ => Some(Value::List(group.to_vec(),),),
+      },)
+      .collect::<Option<_,>>()?;
+   if groups.len() == 1 && !matches!(groups[0], Value::List(_)) {
+      groups.pop()
+   } else {
+      Some(Value::List(groups,),)
+   }
+}
+
+///Longhand names set by a shorthand, in the order 'expand' returns their values.
+/// 'None' if 'nam' isn't a shorthand.
+fn longhands(nam: &str,) -> Option<Vec<String,>,> {
+   let sides = |fmt: &dyn Fn(&str,) -> String| SIDES.iter().map(|side| fmt(side,),).collect::<Vec<_,>>();
+   let names = match nam {
+      "margin" | "padding" => sides(&|side| format!("{nam}-{side}"),),
+      "inset" => sides(&|side| side.to_string(),),
+      "border-width" | "border-style" | "border-color" => {
+         let part = &nam["border-".len()..];
+         sides(&|side| format!("border-{side}-{part}"),)
+      }
+      "border" => ["width", "style", "color",]
+         .iter()
+         .flat_map(|part| SIDES.iter().map(move |side| format!("border-{side}-{part}"),),)
+         .collect(),
+      "border-top" | "border-right" | "border-bottom" | "border-left" => {
+         ["width", "style", "color",].iter().map(|part| format!("{nam}-{part}"),).collect()
+      }
+      "background" => ["color", "image", "repeat", "attachment",].iter().map(|part| format!("background-{part}"),).collect(),
+      "font" => ["style", "variant", "weight", "stretch", "size", "line-height", "family",]
+         .iter()
+         .map(|part| if *part == "line-height" { part.to_string() } else { format!("font-{part}") },)
+         .collect(),
+      "flex" => ["grow", "shrink", "basis",].iter().map(|part| format!("flex-{part}"),).collect(),
+      "container" => vec!["container-name".to_string(), "container-type".to_string()],
+      "overflow" => vec!["overflow-x".to_string(), "overflow-y".to_string()],
+      _ => return None,
+   };
+   Some(names,)
+}
+
+///Values for each of 'longhands(nam)'. 'None' if the declaration is invalid.
+fn expand(nam: &str, vals: Vec<Value,>,) -> Option<Vec<Value,>,> {
+   match nam {
+      "margin" | "padding" | "inset" | "border-width" | "border-style" | "border-color" => four_sides(vals,).map(Vec::from,),
+      "border" => {
+         let [width, style, color] = border_parts(vals,)?;
+         Some([width, style, color,].into_iter().flat_map(|v| vec![v; 4],).collect(),)
+      }
+      "border-top" | "border-right" | "border-bottom" | "border-left" => border_parts(vals,).map(Vec::from,),
+      "background" => background_parts(vals,).map(Vec::from,),
+      "font" => font_parts(vals,).map(Vec::from,),
+      "flex" => flex_parts(vals,).map(Vec::from,),
+      "container" => container_parts(vals,).map(Vec::from,),
+      //One value sets both axes, two set 'overflow-x' then 'overflow-y'.
+      "overflow" => match &*vals {
+         [both] => Some(vec![both.clone(), both.clone()],),
+         [x, y] => Some(vals,),
+         _ => None,
+      },
+      _ => None,
+   }
+}
+
+///Expand 1 to 4 values into [top, right, bottom, left], as in 'margin: 10px 20px'.
+fn four_sides(v: Vec<Value,>,) -> Option<[Value; 4],> {
+   if v.iter().any(|v| matches!(v, Value::Delim(_)),) {
+      return None;
+   }
+   match v.len() {
+      1 => Some([v[0].clone(), v[0].clone(), v[0].clone(), v[0].clone(),],),
+      2 => Some([v[0].clone(), v[1].clone(), v[0].clone(), v[1].clone(),],),
+      3 => Some([v[0].clone(), v[1].clone(), v[2].clone(), v[1].clone(),],),
+      4 => Some([v[0].clone(), v[1].clone(), v[2].clone(), v[3].clone(),],),
+      _ => None,
+   }
 }
 
-fn valid_idf_chr(c: char,) -> bool {
-   match c {
-      'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' => true,
-      _ => false,
+///Put 'val' into the empty 'slot', failing if the slot was already taken.
+fn fill(slot: &mut Option<Value,>, val: &Value,) -> Option<(),> {
+   match slot {
+      Some(_,) => None,
+      None => {
+         *slot = Some(val.clone(),);
+         Some((),)
+      }
    }
 }
 
-pub fn parse(src: String,) -> Stylesheet {
+fn initial() -> Value { Value::Keyword("initial".to_string(),) }
+
+///[width, style, color] of '<line-width> || <line-style> || <color>'.
+fn border_parts(vals: Vec<Value,>,) -> Option<[Value; 3],> {
+   let (mut width, mut style, mut color,) = (None, None, None,);
+   for val in &vals {
+      match val {
+         Value::Length(..,) | Value::Number(0.0,) | Value::Calc(_,) => fill(&mut width, val,)?,
+         Value::Keyword(kw,) => match &**kw {
+            "thin" | "medium" | "thick" => fill(&mut width, val,)?,
+            "none" | "hidden" | "dotted" | "dashed" | "solid" | "double" | "groove" | "ridge" | "inset" | "outset" => {
+               fill(&mut style, val,)?
+            }
+            "currentcolor" => fill(&mut color, val,)?,
+            _ => return None,
+         },
+         Value::Color(_,) => fill(&mut color, val,)?,
+         _ => return None,
+      }
+   }
+   if vals.is_empty() {
+      return None;
+   }
+   Some([width, style, color,].map(|v| v.unwrap_or_else(initial,),),)
+}
+
+///[color, image, repeat, attachment] of a single background layer.
+fn background_parts(vals: Vec<Value,>,) -> Option<[Value; 4],> {
+   let (mut color, mut image, mut repeat, mut attachment,) = (None, None, None, None,);
+   for val in &vals {
+      match val {
+         Value::Color(_,) => fill(&mut color, val,)?,
+         Value::Keyword(kw,) => match &**kw {
+            "currentcolor" => fill(&mut color, val,)?,
+            "none" => fill(&mut image, val,)?,
+            "repeat" | "repeat-x" | "repeat-y" | "no-repeat" | "space" | "round" => fill(&mut repeat, val,)?,
+            "scroll" | "fixed" | "local" => fill(&mut attachment, val,)?,
+            _ => return None,
+         },
+         _ => return None,
+      }
+   }
+   if vals.is_empty() {
+      return None;
+   }
+   Some([color, image, repeat, attachment,].map(|v| v.unwrap_or_else(initial,),),)
+}
+
+///[style, variant, weight, stretch, size, line-height, family] of
+/// '[<style> || <variant> || <weight> || <stretch>]? <size> [/ <line-height>]? <family>'.
+fn font_parts(vals: Vec<Value,>,) -> Option<[Value; 7],> {
+   let (mut style, mut variant, mut weight, mut stretch,) = (None, None, None, None,);
+   let mut rest = &vals[..];
+   //Leading keywords, up to the mandatory size.
+   let size = loop {
+      let (val, tail,) = rest.split_first()?;
+      rest = tail;
+      match val {
+         Value::Length(..,) | Value::Percentage(_,) | Value::Calc(_,) => break val.clone(),
+         Value::Number(n,) if (1.0..=1000.0).contains(n,) => fill(&mut weight, val,)?,
+         Value::Keyword(kw,) => match &**kw {
+            //'normal' resets whichever of the properties is still unset.
+            "normal" => {}
+            "italic" | "oblique" => fill(&mut style, val,)?,
+            "small-caps" => fill(&mut variant, val,)?,
+            "bold" | "bolder" | "lighter" => fill(&mut weight, val,)?,
+            "ultra-condensed" | "extra-condensed" | "condensed" | "semi-condensed" | "semi-expanded" | "expanded"
+            | "extra-expanded" | "ultra-expanded" => fill(&mut stretch, val,)?,
+            "xx-small" | "x-small" | "small" | "medium" | "large" | "x-large" | "xx-large" | "larger" | "smaller" => {
+               break val.clone()
+            }
+            _ => return None,
+         },
+         _ => return None,
+      }
+   };
+   let mut line_height = None;
+   if let [Value::Delim('/',), lh, tail @ ..] = rest {
+      match lh {
+         Value::Length(..,) | Value::Percentage(_,) | Value::Number(_,) | Value::Calc(_,) => line_height = Some(lh.clone(),),
+         Value::Keyword(kw,) if kw == "normal" => line_height = Some(lh.clone(),),
+         _ => return None,
+      }
+      rest = tail;
+   }
+   let family = longhand_val(rest.to_vec(),)?;
+   Some([style, variant, weight, stretch, Some(size,), line_height, Some(family,),].map(|v| v.unwrap_or_else(initial,),),)
+}
+
+///[grow, shrink, basis] of 'none | auto | <grow> <shrink>? || <basis>'.
+fn flex_parts(vals: Vec<Value,>,) -> Option<[Value; 3],> {
+   let num = Value::Number;
+   let auto = || Value::Keyword("auto".to_string(),);
+   match &*vals {
+      [Value::Keyword(kw,)] if kw == "none" => return Some([num(0.0,), num(0.0,), auto(),],),
+      [Value::Keyword(kw,)] if kw == "auto" => return Some([num(1.0,), num(1.0,), auto(),],),
+      _ => {}
+   }
+   let (mut grow, mut shrink, mut basis,) = (None, None, None,);
+   for val in &vals {
+      match val {
+         //A second number is the shrink factor, which must follow grow directly.
+         Value::Number(_,) if grow.is_none() => grow = Some(val.clone(),),
+         Value::Number(_,) if shrink.is_none() && basis.is_none() => shrink = Some(val.clone(),),
+         Value::Length(..,) | Value::Percentage(_,) | Value::Calc(_,) => fill(&mut basis, val,)?,
+         Value::Keyword(kw,) if kw == "auto" || kw == "content" => fill(&mut basis, val,)?,
+         _ => return None,
+      }
+   }
+   if vals.is_empty() {
+      return None;
+   }
+   //Omitted grow and shrink become 1, and an omitted basis becomes 0.
+   Some([grow.unwrap_or(num(1.0,),), shrink.unwrap_or(num(1.0,),), basis.unwrap_or(Value::Length(0.0, Unit::Px,),),],)
+}
+
+///[name, type] of '<name> [/ <type>]?'.
+fn container_parts(vals: Vec<Value,>,) -> Option<[Value; 2],> {
+   match vals.iter().position(|v| *v == Value::Delim('/',),) {
+      Some(i,) => match &vals[i + 1..] {
+         [ty] => Some([longhand_val(vals[..i].to_vec(),)?, ty.clone(),],),
+         _ => None,
+      },
+      None => Some([longhand_val(vals,)?, Value::Keyword("normal".to_string(),),],),
+   }
+}
+
+///Can the engine parse 'val' as a value of the property 'nam'? Values using
+/// 'var()' are only checked once substituted, so any known property accepts them.
+/// A value with a unit, color or function the engine doesn't know is false.
+fn supports_declaration(nam: &str, val: &str,) -> bool {
+   if nam.starts_with("--",) {
+      return !val.is_empty();
+   }
+   if has_var(val,) {
+      return LonghandId::from_name(nam,).is_some() || longhands(nam,).is_some();
+   }
+   parse_declaration_value(nam, val.to_string(),).is_some_and(|declarations| !declarations.is_empty(),)
+}
+
+//Variable substitution. ------------------------
+
+///Does 'css' contain a 'var()' reference?
+fn has_var(css: &str,) -> bool { find_var(css,).is_some() }
+
+///Byte offset of the first 'var(' in 'css' outside of strings.
+fn find_var(css: &str,) -> Option<usize,> {
+   let mut quote = None;
+   for (i, c,) in css.char_indices() {
+      match (quote, c,) {
+         (Some(q,), _,) if c == q => quote = None,
+         (Some(_,), _,) => {}
+         (None, '"' | '\'',) => quote = Some(c,),
+         (None, _,) => {
+            let at_start = !css[..i].ends_with(valid_idf_chr,);
+            if at_start && css[i..].len() >= 4 && css[i..i + 4].eq_ignore_ascii_case("var(",) {
+               return Some(i,);
+            }
+         }
+      }
+   }
+   None
+}
+
+///Length of the text before the ')' closing a block that starts at 'css', or
+/// 'None' if the block isn't closed.
+fn block_len(css: &str,) -> Option<usize,> {
+   let mut depth = 0;
+   let mut quote = None;
+   for (i, c,) in css.char_indices() {
+      match (quote, c,) {
+         (Some(q,), _,) if c == q => quote = None,
+         (Some(_,), _,) => {}
+         (None, '"' | '\'',) => quote = Some(c,),
+         (None, '(' | '[',) => depth += 1,
+         (None, ']',) => depth -= 1,
+         (None, ')',) if depth == 0 => return Some(i,),
+         (None, ')',) => depth -= 1,
+         _ => {}
+      }
+   }
+   None
+}
+
+///Replace every 'var(--name[, fallback])' in 'css' with the value 'lookup'
+/// gives for '--name', or with its fallback. 'None' if a reference has neither,
+/// which makes the declaration invalid at computed-value time.
+pub fn substitute_vars(css: &str, lookup: &mut dyn FnMut(&str,) -> Option<String,>,) -> Option<String,> {
+   let mut out = String::new();
+   let mut rest = css;
+   while let Some(start,) = find_var(rest,) {
+      out.push_str(&rest[..start],);
+      let args = &rest[start + 4..];
+      let len = block_len(args,)?;
+      let (nam, fallback,) = match args[..len].split_once(',',) {
+         Some((nam, fallback,),) => (nam.trim(), Some(fallback,),),
+         None => (args[..len].trim(), None,),
+      };
+      if !nam.starts_with("--",) {
+         return None;
+      }
+      let val = match (lookup(nam,), fallback,) {
+         (Some(val,), _,) => val,
+         (None, Some(fallback,),) => substitute_vars(fallback.trim(), lookup,)?,
+         (None, None,) => return None,
+      };
+      //Keep the substituted tokens apart from their neighbours.
+      out.push(' ',);
+      out.push_str(&val,);
+      out.push(' ',);
+      rest = &args[len + 1..];
+   }
+   out.push_str(rest,);
+   Some(out,)
+}
+
+//Object model. ------------------------
+
+impl Stylesheet {
+   ///Parse 'src' as one rule and insert it at 'index', returning the index.
+   /// 'None' if 'src' isn't exactly one rule the engine keeps, e.g. an unknown
+   /// at-rule. Panics if 'index' is out of bounds.
+   pub fn insert_rule(&mut self, src: &str, index: usize,) -> Option<usize,> {
+      assert!(index <= self.rules.len(), "rule index {index} out of bounds");
+      self.rules.insert(index, parse_rule(src.to_string(),)?,);
+      Some(index,)
+   }
+
+   ///Remove and return the rule at 'index'.
+   pub fn delete_rule(&mut self, index: usize,) -> CssRule { self.rules.remove(index,) }
+}
+
+impl CssRule {
+   ///The rules nested in '@media', '@supports', '@layer' or '@container'.
+   pub fn rules(&self,) -> Option<&Vec<CssRule,>,> {
+      match self {
+         CssRule::Media(MediaRule { rules, .. },)
+         | CssRule::Supports(SupportsRule { rules, .. },)
+         | CssRule::Layer(LayerRule { rules, .. },)
+         | CssRule::Container(ContainerRule { rules, .. },) => Some(rules,),
+         CssRule::Style(_,) | CssRule::LayerStatement(_,) | CssRule::FontFace(_,) => None,
+      }
+   }
+
+   ///The nested rules, which 'parse_rule' can add to.
+   pub fn rules_mut(&mut self,) -> Option<&mut Vec<CssRule,>,> {
+      match self {
+         CssRule::Media(MediaRule { rules, .. },)
+         | CssRule::Supports(SupportsRule { rules, .. },)
+         | CssRule::Layer(LayerRule { rules, .. },)
+         | CssRule::Container(ContainerRule { rules, .. },) => Some(rules,),
+         CssRule::Style(_,) | CssRule::LayerStatement(_,) | CssRule::FontFace(_,) => None,
+      }
+   }
+}
+
+impl Rule {
+   ///The selector list as CSS, e.g. 'h1, p.note'.
+   pub fn selector_text(&self,) -> String {
+      let selectors: Vec<String,> = self.selectors.iter().map(Selector::to_string,).collect();
+      selectors.join(", ",)
+   }
+
+   ///Replace the selectors with those of the list 'src'. An invalid or
+   /// unsupported list leaves them as they were.
+   pub fn set_selector_text(&mut self, src: &str,) {
+      //The selector parser stops at the '{' of the declaration block, which must be the one added here.
+      let mut parser = Parser { pos: 0, inp: format!("{}{{", src.trim()), };
+      if let Some(selectors,) = parser.parse_selectors().filter(|_| parser.pos + 1 == parser.inp.len(),) {
+         self.selectors = selectors;
+      }
+   }
+
+   ///The value of the property 'nam' as CSS. A shorthand is put together from
+   /// its longhands, leaving out those at their initial value. 'None' if the
+   /// property isn't set, or the longhands can't be written as the shorthand,
+   /// e.g. 'border' with sides that differ.
+   pub fn property_value(&self, nam: &str,) -> Option<String,> {
+      let vals = property_ids(nam,)
+         .iter()
+         .map(|id| self.declarations.iter().rev().find(|decl| decl.id == *id,).map(|decl| &decl.val,),)
+         .collect::<Option<Vec<_,>,>>()?;
+      match &vals[..] {
+         [] => None,
+         [val] => Some(val.to_string(),),
+         [Value::Unparsed { css, shorthand: Some(shorthand,), }, ..]
+            if shorthand == nam && vals.iter().all(|val| *val == vals[0],) =>
+         {
+            Some(css.clone(),)
+         }
+         [top, right, bottom, left]
+            if matches!(nam, "margin" | "padding" | "inset" | "border-width" | "border-style" | "border-color") =>
+         {
+            let sides = match () {
+               _ if left != right => vec![top, right, bottom, left],
+               _ if bottom != top => vec![top, right, bottom],
+               _ if right != top => vec![top, right],
+               _ => vec![top],
+            };
+            Some(sides.iter().map(|v| v.to_string(),).collect::<Vec<_,>>().join(" ",),)
+         }
+         [x, y] if nam == "overflow" && x == y => Some(x.to_string(),),
+         [x, y] if nam == "overflow" => Some(format!("{x} {y}"),),
+         //A CSS-wide keyword can only be written as the shorthand if it's on every longhand.
+         [first, ..] if vals.iter().all(|val| val == first,) && first.keyword().is_some_and(is_css_wide_keyword,) => {
+            Some(first.to_string(),)
+         }
+         _ if vals.iter().any(|val| {
+            matches!(val, Value::Unparsed { .. }) || val.keyword().is_some_and(is_css_wide_keyword,) && **val != initial()
+         },) =>
+         {
+            None
+         }
+         //Widths, then styles, then colors, of the four sides.
+         _ if nam == "border" => {
+            let parts = [&vals[0..4], &vals[4..8], &vals[8..12],];
+            parts
+               .iter()
+               .all(|sides| sides.iter().all(|side| *side == sides[0],),)
+               .then(|| shorthand_text(&[vals[0], vals[4], vals[8],],),)
+         }
+         [_, _, _] if nam.starts_with("border-",) => Some(shorthand_text(&vals,),),
+         [grow, shrink, basis] if nam == "flex" => Some(format!("{grow} {shrink} {basis}"),),
+         _ if nam == "background" => Some(shorthand_text(&vals,),),
+         [style, variant, weight, stretch, size, line_height, family] if nam == "font" && **family != initial() => {
+            let mut text = shorthand_text(&[style, variant, weight, stretch,],);
+            let size = if **size == initial() { "medium".to_string() } else { size.to_string() };
+            text = format!("{text} {size}").trim_start().to_string();
+            if **line_height != initial() {
+               text = format!("{text} / {line_height}");
+            }
+            Some(format!("{text} {family}"),)
+         }
+         _ => None,
+      }
+   }
+
+   ///Whether the property 'nam', or every longhand of it, is '!important'.
+   pub fn property_priority(&self, nam: &str,) -> bool {
+      let ids = property_ids(nam,);
+      !ids.is_empty()
+         && ids.iter().all(|id| self.declarations.iter().rev().find(|decl| decl.id == *id,).is_some_and(|decl| decl.important,),)
+   }
+
+   ///Declare 'nam: value', replacing earlier declarations of the same
+   /// longhands. Returns false, leaving the rule as it was, if the value is invalid.
+   pub fn set_property(&mut self, nam: &str, value: &str, important: bool,) -> bool {
+      let important = if important { " !important" } else { "" };
+      let declarations = Parser { pos: 0, inp: format!("{nam}: {value}{important}"), }.parse_declaration();
+      if declarations.is_empty() {
+         return false;
+      }
+      self.declarations.retain(|decl| declarations.iter().all(|new| new.id != decl.id,),);
+      self.declarations.extend(declarations,);
+      true
+   }
+
+   ///Remove the declarations of 'nam', or of its longhands, returning the old value.
+   pub fn remove_property(&mut self, nam: &str,) -> Option<String,> {
+      let old = self.property_value(nam,);
+      let ids = property_ids(nam,);
+      self.declarations.retain(|decl| !ids.contains(&decl.id,),);
+      old
+   }
+}
+
+///The values of a shorthand's longhands as they're written in it, leaving out
+/// those at their initial value.
+fn shorthand_text(vals: &[&Value],) -> String {
+   let written: Vec<String,> = vals.iter().filter(|val| ***val != initial(),).map(|val| val.to_string(),).collect();
+   written.join(" ",)
+}
+
+///The properties a declaration of 'nam' sets: the longhands of a shorthand, or
+/// the property itself. Empty for unknown properties.
+fn property_ids(nam: &str,) -> Vec<PropertyId,> {
+   if nam.starts_with("--",) {
+      return vec![PropertyId::Custom(nam.to_string(),)];
+   }
+   let nam = nam.to_ascii_lowercase();
+   match longhands(&nam,) {
+      Some(names,) => names.iter().filter_map(|longhand| LonghandId::from_name(longhand,),).map(PropertyId::Longhand,).collect(),
+      None => LonghandId::from_name(&nam,).map(PropertyId::Longhand,).into_iter().collect(),
+   }
+}
+
+pub fn parse(src: String, origin: Origin,) -> Stylesheet {
+   let mut parser = Parser { pos: 0, inp: src, };
+   Stylesheet { origin, rules: parser.parse_top_level_rules(), }
+}
+
+///Parse a declaration's value once its 'var()' references are substituted,
+/// returning the longhands it sets. 'None' if the value can't be parsed, and
+/// no declaration if it's invalid.
+pub fn parse_substituted(nam: &str, src: String,) -> Option<Vec<Declaration,>,> { parse_declaration_value(nam, src,) }
+
+///Parse 'src', all of it, as the value of the property 'nam', returning the
+/// longhands it sets. 'None' if it can't be parsed, and no declaration if the
+/// value is invalid for 'nam'.
+fn parse_declaration_value(nam: &str, src: String,) -> Option<Vec<Declaration,>,> {
+   let mut parser = Parser { pos: 0, inp: src, };
+   let vals = parser.parse_vals()?;
+   parser.eof().then(|| expand_declaration(nam.to_string(), vals,),)
+}
+
+///Parse the value of a longhand property, e.g. '1px' or 'Arial, sans-serif'.
+pub fn parse_value(src: String,) -> Option<Value,> {
+   let mut parser = Parser { pos: 0, inp: src, };
+   let vals = parser.parse_vals()?;
+   longhand_val(vals,).filter(|_| parser.eof(),)
+}
+
+///Parse one rule, e.g. 'p { color: red }' or '@media print { ... }'. 'None' if
+/// 'src' holds no rule the engine keeps, or more than one.
+pub fn parse_rule(src: String,) -> Option<CssRule,> {
    let mut parser = Parser { pos: 0, inp: src, };
-   Stylesheet { rules: parser.parse_rules(), }
+   let mut rules = parser.parse_rules();
+   match rules.len() {
+      1 if parser.eof() => rules.pop(),
+      _ => None,
+   }
+}
+
+///Parse the declarations of a 'style' attribute, e.g. 'color: red; margin: 0'.
+pub fn parse_inline(src: String,) -> Vec<Declaration,> { Parser { pos: 0, inp: src, }.parse_declaration_list() }
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   ///Whether '@supports <condition>' holds.
+   fn supports(condition: &str,) -> bool {
+      match parse_rule(format!("@supports {condition} {{}}"),) {
+         Some(CssRule::Supports(rule,),) => rule.condition.matches(),
+         _ => panic!("not an @supports rule: {condition}"),
+      }
+   }
+
+   ///How many rules the stylesheet 'src' has.
+   fn rule_count(src: &str,) -> usize { parse(src.to_string(), Origin::Author,).rules.len() }
+
+   #[test]
+   fn supports_declarations_and_operators() {
+      assert!(supports("(display: block)"));
+      assert!(!supports("(display: grid)"));
+      assert!(!supports("(frobnicate: 1)"));
+      assert!(supports("(display: block) and (not (display: grid))"));
+      assert!(supports("(display: grid) or (color: red)"));
+      assert!(supports("not ((display: grid) and (color: red))"));
+   }
+
+   #[test]
+   fn unknown_supports_conditions_are_false() {
+      assert!(!supports("selector(p:hover)"));
+      assert!(!supports("(display block)"));
+      assert!(!supports("((display: block) and (color: red) or (width: 1px))"));
+      assert!(supports("(display: block) or font-tech(color-COLRv1)"));
+   }
+
+   #[test]
+   fn malformed_supports_rules_are_dropped() {
+      for src in [
+         "@supports foo {}",
+         "@supports (a: b) and (c: d) or (e: f) {}",
+         "@supports (display: block {",
+         "@supports",
+         "@supports;",
+      ] {
+         assert_eq!(rule_count(src,), 0, "{src}");
+      }
+      assert_eq!(rule_count("@supports foo { p {} } p {}",), 1);
+      assert_eq!(rule_count("@supports (display: block) junk { p {} } p {}",), 1);
+   }
+
+   #[test]
+   fn malformed_container_and_layer_rules_are_dropped() {
+      for src in [
+         "@container",
+         "@container name",
+         "@container (width > 1px",
+         "@container foo bar {}",
+         "@layer",
+         "@layer a b {}",
+         "@layer a. {}",
+      ] {
+         assert_eq!(rule_count(src,), 0, "{src}");
+      }
+      assert_eq!(rule_count("@container (width < 1px) or (height) and (x) {} p {}",), 1);
+   }
+
+   #[test]
+   fn supports_unknown_unit_is_false() {
+      assert!(!supports("(width: 10vw)"));
+      assert!(supports("(width: 10px)"));
+   }
+
+   #[test]
+   fn supports_bad_hex_color_is_false() {
+      assert!(!supports("(color: #12345)"));
+      assert!(supports("not (color: #12345)"));
+   }
+
+   ///The rule set 'src' parses to.
+   fn style_rule(src: &str,) -> Rule {
+      match parse_rule(src.to_string(),) {
+         Some(CssRule::Style(rule,),) => rule,
+         _ => panic!("not a rule set: {src}"),
+      }
+   }
+
+   #[test]
+   fn set_property_rejects_unparsable_value() {
+      let mut rule = style_rule("p { width: 5px; color: red }",);
+      assert!(!rule.set_property("width", "10vh", false,));
+      assert!(!rule.set_property("color", "@@", false,));
+      assert_eq!(rule.property_value("width",).as_deref(), Some("5px"));
+      assert_eq!(rule.property_value("color",).as_deref(), Some("#ff0000"));
+   }
+
+   #[test]
+   fn insert_unclosed_rule_is_none() {
+      let mut sheet = parse(String::new(), Origin::Author,);
+      assert_eq!(sheet.insert_rule("p {", 0,), None);
+      assert_eq!(sheet.insert_rule("@media print { p {}", 0,), None);
+      assert_eq!(sheet.insert_rule("@layer a {", 0,), None);
+      assert!(sheet.rules.is_empty());
+   }
+
+   #[test]
+   fn set_unsupported_selector_text_keeps_selectors() {
+      let mut rule = style_rule("h1 > em { color: red }",);
+      for src in ["p:hover", "p {", "p,", "#", "",] {
+         rule.set_selector_text(src,);
+         assert_eq!(rule.selector_text(), "h1 > em");
+      }
+      rule.set_selector_text("ul li, p.note",);
+      assert_eq!(rule.selector_text(), "p.note, ul li");
+   }
+
+   #[test]
+   fn rule_with_unsupported_selector_is_dropped() {
+      let sheet = parse("p:hover { color: red } a { color: blue }".to_string(), Origin::Author,);
+      assert_eq!(sheet.to_string(), "a {\n  color: #0000ff;\n}");
+   }
+
+   #[test]
+   fn calc_subtraction_round_trips() {
+      let src = "div {\n  width: calc(100% - 10px);\n  height: calc(50% - 2em + 3px);\n}";
+      let sheet = parse(src.to_string(), Origin::Author,);
+      assert_eq!(sheet.to_string(), src);
+      assert_eq!(parse(sheet.to_string(), Origin::Author,).to_string(), src);
+   }
+
+   #[test]
+   fn minified_combinators_round_trip() {
+      let src = "ul>li a,p>span{color:#ff0000}";
+      let sheet = parse("ul > li a, p > span { color: red }".to_string(), Origin::Author,);
+      assert_eq!(sheet.minified(), src);
+      assert_eq!(parse(src.to_string(), Origin::Author,).minified(), src);
+      assert_eq!(sheet.to_string(), "ul > li a, p > span {\n  color: #ff0000;\n}");
+   }
+
+   ///The declarations of the first rule in 'src'.
+   fn declarations(src: &str,) -> Vec<Declaration,> {
+      match parse(src.to_string(), Origin::Author,).rules.remove(0,) {
+         CssRule::Style(rule,) => rule.declarations,
+         _ => panic!("not a style rule: {src}"),
+      }
+   }
+
+   fn names(declarations: &[Declaration],) -> Vec<&str,> { declarations.iter().map(|decl| decl.id.name(),).collect() }
+
+   #[test]
+   fn box_shorthands_repeat_their_sides() {
+      let decls = declarations("p { margin: 1px 2px }",);
+      assert_eq!(names(&decls,), ["margin-top", "margin-right", "margin-bottom", "margin-left"]);
+      let px = |f| Value::Length(f, Unit::Px,);
+      assert!(decls.iter().map(|decl| &decl.val,).eq([&px(1.0,), &px(2.0,), &px(1.0,), &px(2.0,),]));
+   }
+
+   #[test]
+   fn omitted_shorthand_parts_are_initial() {
+      let decls = declarations("p { border: 1px solid }",);
+      assert_eq!(decls.len(), 12);
+      let solid = Value::Keyword("solid".to_string(),);
+      assert!(decls.iter().filter(|decl| decl.id.name().ends_with("-style",),).all(|decl| decl.val == solid));
+      assert!(decls.iter().filter(|decl| decl.id.name().ends_with("-color",),).all(|decl| decl.val == initial()));
+   }
+
+   #[test]
+   fn invalid_shorthands_produce_no_declarations() {
+      assert!(declarations("p { margin: 1px 2px 3px 4px 5px; flex: auto auto }",).is_empty());
+   }
+
+   #[test]
+   fn unknown_units_colors_and_functions_drop_only_their_declaration() {
+      let decls = declarations("p { margin: 1vh; color: #12; background: url(a.png); width: 5px }",);
+      assert_eq!(names(&decls,), ["width"]);
+   }
+
+   #[test]
+   fn bang_must_be_followed_by_important() {
+      let decls = declarations("p { width: 1px ! important; height: 2px !later; color: red }",);
+      assert_eq!(names(&decls,), ["width", "color"]);
+      assert!(decls[0].important && !decls[1].important);
+   }
+
+   #[test]
+   fn values_with_trailing_input_are_invalid() {
+      assert!(parse_value("1px".to_string(),) == Some(Value::Length(1.0, Unit::Px,),));
+      assert!(parse_value("1px }".to_string(),).is_none());
+   }
+
+   #[test]
+   fn media_queries_round_trip() {
+      for src in [
+         "screen",
+         "not print",
+         "screen and (orientation: landscape)",
+         "(width >= 600px)",
+         "(400px < width <= 800px)",
+         "(width < 600px), print",
+         "(hover) and (not (width > 1px))",
+         "screen and ((width < 1px) or (height > 1px))",
+         "screen, not all",
+      ] {
+         let src = format!("@media {src} {{\n}}");
+         assert_eq!(parse(src.clone(), Origin::Author,).to_string(), src);
+      }
+      let sheet = parse("@media only screen and (min-width: 40em), (a) or (b) and (c) {}".to_string(), Origin::Author,);
+      assert_eq!(sheet.to_string(), "@media screen and (width >= 640px), not all {\n}");
+      assert_eq!(parse("@media {}".to_string(), Origin::Author,).to_string(), "@media {\n}");
+   }
+
+   #[test]
+   fn shorthands_are_put_together_from_longhands() {
+      let rule = style_rule(
+         "p { border: 1px solid red; background: blue; font: italic bold 12px / 1.5 Arial, serif; border-left: dashed; flex: 2 }",
+      );
+      let value = |nam: &str| rule.property_value(nam,);
+      assert_eq!(value("border-top").as_deref(), Some("1px solid #ff0000"));
+      assert_eq!(value("border-left").as_deref(), Some("dashed"));
+      assert_eq!(value("border"), None);
+      assert_eq!(value("background").as_deref(), Some("#0000ff"));
+      assert_eq!(value("font").as_deref(), Some("italic bold 12px / 1.5 Arial, serif"));
+      assert_eq!(value("flex").as_deref(), Some("2 1 0px"));
+      let rule = style_rule("p { border: thin solid; font: 1em serif }",);
+      assert_eq!(rule.property_value("border",).as_deref(), Some("thin solid"));
+      assert_eq!(rule.property_value("font",).as_deref(), Some("1em serif"));
+   }
+
+   #[test]
+   fn shorthand_values_parse_back_to_the_same_longhands() {
+      for (nam, src,) in
+         [("border", "2px dotted #008000",), ("background", "#ff0000 none no-repeat",), ("font", "small-caps 10px / 2 serif",),]
+      {
+         let rule = style_rule(&format!("p {{ {nam}: {src} }}"),);
+         assert_eq!(rule.property_value(nam,).as_deref(), Some(src), "{nam}");
+      }
+   }
+
+   #[test]
+   fn shorthand_with_keyword_on_some_longhands_is_unknown() {
+      let rule = style_rule("p { border: inherit }",);
+      assert_eq!(rule.property_value("border",).as_deref(), Some("inherit"));
+      let rule = style_rule("p { border: 1px solid; border-top-color: inherit }",);
+      assert_eq!(rule.property_value("border-top",), None);
+      assert_eq!(rule.property_value("border-bottom",).as_deref(), Some("1px solid"));
+   }
+
+   #[test]
+   fn stray_closing_brace_is_skipped() {
+      let sheet = parse("} p { color: red } } a { color: blue } }".to_string(), Origin::Author,);
+      assert_eq!(sheet.to_string(), "p {\n  color: #ff0000;\n}\na {\n  color: #0000ff;\n}");
+      assert!(parse_rule("p {} }".to_string()).is_none());
+   }
 }
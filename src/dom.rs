@@ -1,4 +1,9 @@
-use std::collections::{HashMap, HashSet};
+#![allow(unused)]
+use std::{
+   collections::{HashMap, HashSet},
+   mem,
+   sync::atomic::{AtomicUsize, Ordering},
+};
 
 pub type AttrMap = HashMap<String, String,>;
 
@@ -8,6 +13,83 @@ pub struct Node {
    pub children:  Vec<Node,>,
    //data specific to each node type
    pub node_type: NodeType,
+   id:            NodeId,
+   ///Changes since the node was last styled.
+   changes:       Changes,
+}
+
+///Identifies a node for as long as it exists, even as the tree around it changes.
+#[derive(Clone, Copy, PartialEq, Eq, Hash,)]
+pub struct NodeId(usize,);
+
+impl NodeId {
+   fn next() -> NodeId {
+      static NEXT: AtomicUsize = AtomicUsize::new(0,);
+      NodeId(NEXT.fetch_add(1, Ordering::Relaxed,),)
+   }
+}
+
+///Changes to an element since it was last styled, which tell incremental
+/// restyle what to match again. New nodes need no record: they have no style yet.
+#[derive(Clone, Default,)]
+pub struct Changes {
+   ///Classes added or removed.
+   pub classes: Vec<String,>,
+   ///The old and new id, when the 'id' attribute changed.
+   pub ids:     Vec<String,>,
+   ///Names of other attributes that changed, e.g. 'style'.
+   pub attrs:   Vec<String,>,
+}
+
+impl Changes {
+   pub fn is_empty(&self,) -> bool { self.classes.is_empty() && self.ids.is_empty() && self.attrs.is_empty() }
+}
+
+impl Node {
+   pub fn id(&self,) -> NodeId { self.id }
+
+   ///Set an attribute of an element, recording the change for restyle.
+   pub fn set_attr(&mut self, nam: &str, val: String,) { self.change_attr(nam, Some(val,),); }
+
+   ///Remove an attribute of an element, recording the change for restyle.
+   pub fn remove_attr(&mut self, nam: &str,) { self.change_attr(nam, None,); }
+
+   fn change_attr(&mut self, nam: &str, val: Option<String,>,) {
+      let NodeType::Element(ref mut elem,) = self.node_type else { panic!("Only elements have attributes") };
+      let old = match val {
+         Some(val,) => elem.attributes.insert(nam.to_string(), val,),
+         None => elem.attributes.remove(nam,),
+      };
+      let new = elem.attributes.get(nam,);
+      if old.as_ref() == new {
+         return;
+      }
+      match nam {
+         "class" => {
+            fn words(list: Option<&String,>,) -> HashSet<&str,> { list.map(|l| l.split(' ',).collect(),).unwrap_or_default() }
+            let (old, new,) = (words(old.as_ref(),), words(new,),);
+            self.changes.classes.extend(old.symmetric_difference(&new,).map(|class| class.to_string(),),);
+         }
+         "id" => self.changes.ids.extend(old.into_iter().chain(new.cloned(),),),
+         _ => self.changes.attrs.push(nam.to_string(),),
+      }
+   }
+
+   ///Remove the changes recorded in this subtree, keyed by node.
+   pub fn take_changes(&mut self,) -> HashMap<NodeId, Changes,> {
+      let mut changes = HashMap::new();
+      self.collect_changes(&mut changes,);
+      changes
+   }
+
+   fn collect_changes(&mut self, out: &mut HashMap<NodeId, Changes,>,) {
+      if !self.changes.is_empty() {
+         out.insert(self.id, mem::take(&mut self.changes,),);
+      }
+      for child in &mut self.children {
+         child.collect_changes(out,);
+      }
+   }
 }
 
 //#[derive(Debug,)]
@@ -17,12 +99,16 @@ pub enum NodeType {
 }
 
 //#[derive(Debug,)]
+#[derive(PartialEq,)]
 pub struct ElementData {
    pub tag_name: String,
    attributes:   AttrMap,
 }
 
 impl ElementData {
+   ///Get the value of an attribute
+   pub fn attr(&self, nam: &str,) -> Option<&String,> { self.attributes.get(nam,) }
+
    ///Get attribute's id
    pub fn id(&self,) -> Option<&String,> { self.attributes.get("id",) }
 
@@ -35,7 +121,10 @@ impl ElementData {
    }
 }
 
-pub fn text(data: String,) -> Node { Node { children: Vec::new(), node_type: NodeType::Text(data,), } }
+pub fn text(data: String,) -> Node {
+   Node { children: Vec::new(), node_type: NodeType::Text(data,), id: NodeId::next(), changes: Changes::default(), }
+}
 pub fn elem(name: String, attrs: AttrMap, children: Vec<Node,>,) -> Node {
-   Node { children, node_type: NodeType::Element(ElementData { tag_name: name, attributes: attrs, },), }
+   let node_type = NodeType::Element(ElementData { tag_name: name, attributes: attrs, },);
+   Node { children, node_type, id: NodeId::next(), changes: Changes::default(), }
 }
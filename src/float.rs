@@ -0,0 +1,81 @@
+#![allow(unused)]
+use crate::{
+   layout::Rct,
+   properties::{Clear, Float},
+};
+
+///The floats of a block formatting context, which later floats and the line
+/// boxes of the context flow around.
+#[derive(Default,)]
+pub struct FloatContext {
+   ///The side each float went to, and its margin box, in the order placed.
+   floats: Vec<(Float, Rct,),>,
+}
+
+impl FloatContext {
+   ///The part of the band from 'y' to 'y + height' between 'left' and 'right'
+   /// that no float covers, as its left and right.
+   pub fn available(&self, y: f64, height: f64, left: f64, right: f64,) -> (f64, f64,) {
+      self.in_band(y, height,).fold((left, right,), |(l, r,), (side, rct,)| match side {
+         Float::Left => (l.max(rct.x + rct.width,), r,),
+         _ => (l, r.min(rct.x,),),
+      },)
+   }
+
+   ///Place a float of 'side' whose margin box is 'width' by 'height' at the
+   /// highest position at or below 'y' where it fits between 'left' and
+   /// 'right' and the floats already there, as far to its side as it goes
+   /// (CSS 2.1 §9.5.1). Returns the top left of its margin box.
+   pub fn place(&mut self, side: Float, width: f64, height: f64, y: f64, left: f64, right: f64,) -> (f64, f64,) {
+      //A float's top may not be higher than that of an earlier float.
+      let mut y = self.floats.last().map_or(y, |(_, rct,)| y.max(rct.y,),);
+      loop {
+         let (l, r,) = self.available(y, height, left, right,);
+         //Where no float is in the way, a float too wide for the space overflows it.
+         let clear = (l, r,) == (left, right,);
+         match self.next_bottom(y, height,) {
+            Some(bottom,) if !clear && width > r - l => y = bottom,
+            _ => {
+               let x = if side == Float::Left { l } else { r - width };
+               self.floats.push((side, Rct { x, y, width, height, },),);
+               return (x, y,);
+            }
+         }
+      }
+   }
+
+   ///The bottom of the lowest float that 'clear' moves content below. 'None'
+   /// if there is none.
+   pub fn clearance(&self, clear: Clear,) -> Option<f64,> {
+      self
+         .floats
+         .iter()
+         .filter(|(side, _,)| match clear {
+            Clear::None => false,
+            Clear::Left => *side == Float::Left,
+            Clear::Right => *side == Float::Right,
+            Clear::Both => true,
+         },)
+         .map(|(_, rct,)| rct.y + rct.height,)
+         .reduce(f64::max,)
+   }
+
+   ///The highest bottom of the floats in the band from 'y' to 'y + height',
+   /// where the space beside them may widen.
+   pub fn next_bottom(&self, y: f64, height: f64,) -> Option<f64,> {
+      self.in_band(y, height,).map(|(_, rct,)| rct.y + rct.height,).reduce(f64::min,)
+   }
+
+   ///The bottom of the lowest float, which a block formatting context root
+   /// grows to contain.
+   pub fn bottom(&self,) -> Option<f64,> { self.clearance(Clear::Both,) }
+
+   ///The floats in the band from 'y' to 'y + height'. A band of no height is
+   /// the line at 'y'.
+   fn in_band(&self, y: f64, height: f64,) -> impl Iterator<Item = &(Float, Rct,),> {
+      self.floats.iter().filter(move |(_, rct,)| {
+         let starts = if height > 0.0 { rct.y < y + height } else { rct.y <= y };
+         starts && y < rct.y + rct.height
+      },)
+   }
+}
@@ -0,0 +1,631 @@
+#![allow(unused)]
+use std::{fs, path::Path, sync::Arc};
+
+use crate::{
+   css::{self, CssRule},
+   media::MediaEnvironment,
+   properties::FontStyle,
+   resource::ResourceLoader,
+   woff2, Parser,
+};
+
+///'@font-face { <descriptors> }'
+pub struct FontFaceRule {
+   pub family:        String,
+   pub src:           Vec<FontSource,>,
+   ///Weights the face covers, as in 'font-weight: 100 900'. 'None' for 'auto',
+   /// which takes the weight from the font file.
+   pub weight:        Option<(u16, u16,),>,
+   ///'None' for 'auto', which takes the style from the font file.
+   pub style:         Option<FontStyle,>,
+   ///Code points the face is used for, as inclusive ranges.
+   pub unicode_range: Vec<(u32, u32,),>,
+}
+
+pub enum FontSource {
+   ///'url(<url>) format(<format>)?'
+   Url(String, Option<String,>,),
+   ///'local(<name>)', an installed font by its full or PostScript name.
+   Local(String,),
+}
+
+///Formats of 'format()' hints the engine can load.
+const FORMATS: [&str; 4] = ["truetype", "opentype", "woff2", "collection",];
+
+impl FontFaceRule {
+   ///The descriptors as '(name, value)' pairs of CSS text. Those left at their
+   /// initial value are omitted.
+   pub fn descriptors(&self,) -> Vec<(&'static str, String,),> {
+      let src = self.src.iter().map(|src| match src {
+         FontSource::Url(url, None,) => format!("url({})", css::serialize_string(url,)),
+         FontSource::Url(url, Some(format,),) => {
+            format!("url({}) format({})", css::serialize_string(url,), css::serialize_string(format,))
+         }
+         FontSource::Local(nam,) => format!("local({})", css::serialize_string(nam,)),
+      },);
+      let mut descriptors =
+         vec![("font-family", css::serialize_string(&self.family,),), ("src", src.collect::<Vec<_,>>().join(", ",),)];
+      match self.weight {
+         Some((lo, hi,),) if lo == hi => descriptors.push(("font-weight", lo.to_string(),),),
+         Some((lo, hi,),) => descriptors.push(("font-weight", format!("{lo} {hi}"),),),
+         None => {}
+      }
+      if let Some(style,) = self.style {
+         descriptors.push(("font-style", style.keyword().to_string(),),);
+      }
+      if self.unicode_range != [(0, 0x10FFFF,),] {
+         let ranges =
+            self.unicode_range.iter().map(|&(lo, hi,)| if lo == hi { format!("U+{lo:X}") } else { format!("U+{lo:X}-{hi:X}") },);
+         descriptors.push(("unicode-range", ranges.collect::<Vec<_,>>().join(", ",),),);
+      }
+      descriptors
+   }
+}
+
+impl Parser {
+   ///Parse the descriptor block of '@font-face'. 'None' if there's no block,
+   /// it isn't closed, or 'font-family' or 'src' is missing or invalid. Other
+   /// invalid descriptors are ignored.
+   pub fn parse_font_face(&mut self,) -> Option<FontFaceRule,> {
+      if self.eof() || self.cnsm_chr() != '{' {
+         return None;
+      }
+      let (mut family, mut src,) = (None, None,);
+      let (mut weight, mut style,) = (None, None,);
+      let mut unicode_range = vec![(0, 0x10FFFF,)];
+      loop {
+         self.cnsm_whitespace();
+         if self.eof() {
+            return None;
+         }
+         if self.next_char() == '}' {
+            self.cnsm_chr();
+            break;
+         }
+         let nam = self.parse_idf().to_ascii_lowercase();
+         self.cnsm_whitespace();
+         //A descriptor without a ':' is skipped up to its ';' or the end of the block.
+         if self.eof() || self.next_char() != ':' {
+            self.parse_raw_value();
+            continue;
+         }
+         self.cnsm_chr();
+         let (val, _,) = self.parse_raw_value();
+         let mut p = Parser { pos: 0, inp: val, };
+         match &*nam {
+            "font-family" => family = p.parse_family_name().or(family,),
+            "src" => src = p.parse_font_src().or(src,),
+            "font-weight" => weight = p.parse_weight_range().unwrap_or(weight,),
+            "font-style" => style = p.parse_face_style().unwrap_or(style,),
+            "unicode-range" => unicode_range = p.parse_unicode_range().unwrap_or(unicode_range,),
+            _ => {}
+         }
+      }
+      Some(FontFaceRule { family: family?, src: src?, weight, style, unicode_range, },)
+   }
+
+   ///A quoted family name, or identifiers joined by spaces.
+   fn parse_family_name(&mut self,) -> Option<String,> {
+      if self.eof() {
+         return None;
+      }
+      if matches!(self.next_char(), '"' | '\'') {
+         let nam = self.parse_string();
+         return self.eof().then_some(nam,);
+      }
+      let words = self.inp.split_whitespace().map(str::to_string,).collect::<Vec<_,>>();
+      words.iter().all(|w| w.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_',),).then(|| words.join(" ",),)
+   }
+
+   ///A comma-separated list of 'url()' and 'local()' sources.
+   fn parse_font_src(&mut self,) -> Option<Vec<FontSource,>,> {
+      let mut sources = vec![];
+      loop {
+         self.cnsm_whitespace();
+         let func = self.parse_idf().to_ascii_lowercase();
+         let arg = self.parse_function_arg()?;
+         self.cnsm_whitespace();
+         match &*func {
+            "url" => {
+               let mut format = None;
+               if self.starts_with_keyword("format",) {
+                  self.parse_idf();
+                  format = Some(self.parse_function_arg()?.to_ascii_lowercase(),);
+                  self.cnsm_whitespace();
+               }
+               sources.push(FontSource::Url(arg, format,),);
+            }
+            "local" => sources.push(FontSource::Local(arg,),),
+            _ => return None,
+         }
+         if self.eof() {
+            return Some(sources,);
+         }
+         if self.cnsm_chr() != ',' {
+            return None;
+         }
+      }
+   }
+
+   ///The argument of a function after its name: '("quoted")' or '(unquoted)'.
+   fn parse_function_arg(&mut self,) -> Option<String,> {
+      if self.eof() || self.cnsm_chr() != '(' {
+         return None;
+      }
+      self.cnsm_whitespace();
+      let arg = match self.next_char() {
+         '"' | '\'' => self.parse_string(),
+         _ => self.cnsm_while(|c| c != ')',).trim_end().to_string(),
+      };
+      self.cnsm_whitespace();
+      (!self.eof() && self.cnsm_chr() == ')').then_some(arg,)
+   }
+
+   ///'auto', or one or two weights. 'Some(None)' stands for 'auto'.
+   fn parse_weight_range(&mut self,) -> Option<Option<(u16, u16,),>,> {
+      if self.inp.trim().eq_ignore_ascii_case("auto",) {
+         return Some(None,);
+      }
+      let mut weights = vec![];
+      for word in self.inp.split_whitespace() {
+         weights.push(match word.to_ascii_lowercase().as_str() {
+            "normal" => 400,
+            "bold" => 700,
+            w => w.parse::<f64>().ok().filter(|w| (1.0..=1000.0).contains(w,),)? as u16,
+         },);
+      }
+      match weights[..] {
+         [w] => Some(Some((w, w,),),),
+         [lo, hi] => Some(Some((lo.min(hi,), lo.max(hi,),),),),
+         _ => None,
+      }
+   }
+
+   ///'auto', 'normal', 'italic' or 'oblique' with optional angles, which are ignored.
+   fn parse_face_style(&mut self,) -> Option<Option<FontStyle,>,> {
+      let kw = self.parse_idf().to_ascii_lowercase();
+      self.cnsm_whitespace();
+      match &*kw {
+         "auto" if self.eof() => Some(None,),
+         "oblique" => Some(Some(FontStyle::Oblique,),),
+         _ if self.eof() => FontStyle::from_keyword(&kw,).map(Some,),
+         _ => None,
+      }
+   }
+
+   ///A comma-separated list of 'U+0-7F', 'U+4??' or 'U+20AC'.
+   fn parse_unicode_range(&mut self,) -> Option<Vec<(u32, u32,),>,> {
+      let mut ranges = vec![];
+      for part in self.inp.split(',',) {
+         let part = part.trim();
+         let digits = part.strip_prefix("U+",).or_else(|| part.strip_prefix("u+",),)?;
+         let (lo, hi,) = match digits.split_once('-',) {
+            Some((lo, hi,),) => (lo.to_string(), hi.to_string(),),
+            //'?' stands for any hex digit.
+            None => (digits.replace('?', "0",), digits.replace('?', "F",),),
+         };
+         let hex = |s: &str| (1..=6).contains(&s.len(),).then(|| u32::from_str_radix(s, 16,).ok(),).flatten();
+         let (lo, hi,) = (hex(&lo,)?, hex(&hi,)?.min(0x10FFFF,),);
+         if lo > hi {
+            return None;
+         }
+         ranges.push((lo, hi,),);
+      }
+      Some(ranges,)
+   }
+}
+
+///A font face that text can be drawn with.
+#[derive(Clone,)]
+pub struct FontFace {
+   pub family:        String,
+   pub weight:        (u16, u16,),
+   pub style:         FontStyle,
+   pub unicode_range: Vec<(u32, u32,),>,
+   ///The face's tables, parsed once when loaded.
+   parsed:            Arc<ParsedFace,>,
+   ///Index of the face in a collection.
+   pub index:         u32,
+   ///Full name and PostScript name, which 'local()' refers to.
+   names:             Vec<String,>,
+}
+
+impl FontFace {
+   ///The faces in a TrueType, OpenType or WOFF2 file or collection, described
+   /// by the file's own names and metadata. 'None' if the data can't be read.
+   pub fn load(data: Vec<u8,>,) -> Option<Vec<FontFace,>,> {
+      let data = match data.get(..4,)? {
+         b"wOF2" => woff2::decode(&data,)?,
+         _ => data,
+      };
+      let data = Arc::new(data,);
+      let count = ttf_parser::fonts_in_collection(&data,).unwrap_or(1,);
+      (0..count).map(|index| FontFace::from_data(data.clone(), index,),).collect()
+   }
+
+   fn from_data(data: Arc<Vec<u8,>,>, index: u32,) -> Option<FontFace,> {
+      use ttf_parser::name_id;
+      let parsed = Arc::new(ParsedFace::parse(data, index,)?,);
+      let face = &parsed.face;
+      let name = |id: u16| face.names().into_iter().filter(|n| n.name_id == id,).find_map(|n| n.to_string(),);
+      let family = name(name_id::TYPOGRAPHIC_FAMILY,).or_else(|| name(name_id::FAMILY,),)?;
+      let names = [name_id::FULL_NAME, name_id::POST_SCRIPT_NAME,].into_iter().filter_map(name,).collect();
+      let weight = face.weight().to_number();
+      let style = match face.style() {
+         ttf_parser::Style::Normal => FontStyle::Normal,
+         ttf_parser::Style::Italic => FontStyle::Italic,
+         ttf_parser::Style::Oblique => FontStyle::Oblique,
+      };
+      Some(FontFace { family, weight: (weight, weight,), style, unicode_range: vec![(0, 0x10FFFF,)], parsed, index, names, },)
+   }
+
+   ///The parsed face, for shaping, and through 'Deref' its 'ttf_parser' tables.
+   pub fn face(&self,) -> &rustybuzz::Face<'_,> { &self.parsed.face }
+
+   ///Can the face draw 'ch'? It must be in the face's 'unicode-range' and have a glyph.
+   pub fn covers(&self, ch: char,) -> bool {
+      let in_range = self.unicode_range.iter().any(|&(lo, hi,)| (lo..=hi).contains(&(ch as u32),),);
+      in_range && self.face().glyph_index(ch,).is_some()
+   }
+
+   ///How far 'ch' moves the pen at a font size of 'size' px, or 'None' if the
+   /// face has no glyph for it.
+   pub fn advance(&self, ch: char, size: f64,) -> Option<f64,> {
+      let face = self.face();
+      let advance = face.glyph_hor_advance(face.glyph_index(ch,)?,)?;
+      Some(advance as f64 * size / face.units_per_em() as f64,)
+   }
+
+   ///The bold and oblique this face lacks for text of 'weight' and 'style'.
+   pub fn synthesis(&self, weight: u16, style: FontStyle,) -> Synthesis {
+      Synthesis {
+         bold:    weight >= 600 && self.weight.1 < 600,
+         oblique: style != FontStyle::Normal && self.style == FontStyle::Normal,
+      }
+   }
+
+   ///Ascent, descent and line gap at a font size of 'size' px. The descent is
+   /// positive below the baseline.
+   pub fn metrics(&self, size: f64,) -> (f64, f64, f64,) {
+      let face = self.face();
+      let scale = size / face.units_per_em() as f64;
+      (face.ascender() as f64 * scale, -face.descender() as f64 * scale, face.line_gap() as f64 * scale,)
+   }
+}
+
+///TrueType or OpenType data, shared by the faces of a collection, and one
+/// face parsed from it.
+struct ParsedFace {
+   ///Borrows from 'data', so comes first to be dropped first.
+   face:  rustybuzz::Face<'static,>,
+   _data: Arc<Vec<u8,>,>,
+}
+
+impl ParsedFace {
+   fn parse(data: Arc<Vec<u8,>,>, index: u32,) -> Option<ParsedFace,> {
+      //SAFETY: The bytes are on the heap behind the 'Arc', which never moves or
+      // changes them, and the face borrowing them is dropped before '_data'.
+      let bytes: &'static [u8] = unsafe { std::slice::from_raw_parts(data.as_ptr(), data.len(),) };
+      Some(ParsedFace { face: rustybuzz::Face::from_slice(bytes, index,)?, _data: data, },)
+   }
+}
+
+///Styles a face is drawn in without having them: bold by emboldening its
+/// outlines, and oblique by slanting them.
+#[derive(Clone, Copy, Default, PartialEq,)]
+pub struct Synthesis {
+   pub bold:    bool,
+   pub oblique: bool,
+}
+
+impl Synthesis {
+   ///How much synthetic bold thickens glyphs and widens their advance, in ems.
+   pub const BOLD_STRENGTH: f64 = 1.0 / 24.0;
+   ///Slant of synthetic oblique: the tangent of 14 degrees.
+   pub const OBLIQUE_SLANT: f64 = 0.25;
+}
+
+///The fonts text can use: faces from '@font-face' rules, and installed fonts.
+#[derive(Default,)]
+pub struct FontDatabase {
+   ///Faces from '@font-face' rules, which hide installed fonts of the same family.
+   web:     Vec<FontFace,>,
+   system:  Vec<FontFace,>,
+   ///Family each generic family, like 'serif', stands for.
+   generic: Vec<(String, String,),>,
+   ///Faces for characters no family has, so text can be drawn without any
+   /// fonts installed.
+   default: Vec<FontFace,>,
+}
+
+///Installed families the generic families stand for unless set otherwise, in
+/// order of preference.
+const GENERIC_FAMILIES: [(&str, &[&str],); 6] = [
+   ("serif", &["DejaVu Serif", "Liberation Serif", "Noto Serif", "Times New Roman",],),
+   ("sans-serif", &["DejaVu Sans", "Liberation Sans", "Noto Sans", "Arial",],),
+   ("monospace", &["DejaVu Sans Mono", "Liberation Mono", "Noto Sans Mono", "Courier New",],),
+   ("cursive", &["Comic Neue", "Comic Sans MS",],),
+   ("fantasy", &["Impact",],),
+   ("system-ui", &["DejaVu Sans", "Noto Sans", "Cantarell",],),
+];
+
+///Source Serif 4, under the SIL Open Font License (see 'fonts/SourceSerif4-LICENSE.md').
+const DEFAULT_FONT: &[u8] = include_bytes!("fonts/SourceSerif4-Regular.ttf.woff2");
+
+impl FontDatabase {
+   ///A database with only the bundled default face.
+   pub fn new() -> FontDatabase {
+      let default = FontFace::load(DEFAULT_FONT.to_vec(),).expect("the bundled font loads",);
+      FontDatabase { default, ..FontDatabase::default() }
+   }
+
+   ///Add the fonts in the font directories of 'loader' as installed fonts.
+   pub fn add_installed_fonts(&mut self, loader: &dyn ResourceLoader,) {
+      for dir in loader.font_dirs() {
+         self.load_system_fonts(&dir,);
+      }
+   }
+
+   ///Add the font files in 'dir' and its subdirectories as installed fonts.
+   pub fn load_system_fonts(&mut self, dir: &Path,) {
+      let Ok(entries,) = fs::read_dir(dir,) else { return };
+      for path in entries.filter_map(|e| e.ok(),).map(|e| e.path(),) {
+         let ext = path.extension().and_then(|e| e.to_str(),).map(str::to_ascii_lowercase,);
+         if path.is_dir() {
+            self.load_system_fonts(&path,);
+         } else if matches!(ext.as_deref(), Some("ttf" | "otf" | "ttc" | "otc" | "woff2")) {
+            if let Ok(data,) = fs::read(&path,) {
+               self.add_system_font(data,);
+            }
+         }
+      }
+   }
+
+   ///Add the faces of a font file as installed fonts. Returns whether the data could be read.
+   pub fn add_system_font(&mut self, data: Vec<u8,>,) -> bool {
+      match FontFace::load(data,) {
+         Some(faces,) => {
+            self.system.extend(faces,);
+            true
+         }
+         None => false,
+      }
+   }
+
+   ///Use 'family' for the generic family 'generic', such as 'sans-serif'.
+   pub fn set_generic_family(&mut self, generic: &str, family: &str,) {
+      self.generic.retain(|(g, _,)| g != generic,);
+      self.generic.push((generic.to_string(), family.to_string(),),);
+   }
+
+   ///Load the faces of the '@font-face' rules in 'stylesheets' that apply in 'env'.
+   pub fn add_stylesheets(&mut self, stylesheets: &[css::Stylesheet], env: &MediaEnvironment, loader: &dyn ResourceLoader,) {
+      for sheet in stylesheets {
+         self.add_rules(&sheet.rules, env, loader,);
+      }
+   }
+
+   fn add_rules(&mut self, rules: &[CssRule], env: &MediaEnvironment, loader: &dyn ResourceLoader,) {
+      for rule in rules {
+         match rule {
+            CssRule::FontFace(font_face,) => self.add_font_face(font_face, loader,),
+            CssRule::Media(media,) if media.queries.matches(env,) => self.add_rules(&media.rules, env, loader,),
+            CssRule::Supports(supports,) if supports.condition.matches() => self.add_rules(&supports.rules, env, loader,),
+            CssRule::Layer(layer,) => self.add_rules(&layer.rules, env, loader,),
+            _ => {}
+         }
+      }
+   }
+
+   ///Load the first source of 'rule' that works. Its descriptors replace the
+   /// file's own family, weight and style.
+   fn add_font_face(&mut self, rule: &FontFaceRule, loader: &dyn ResourceLoader,) {
+      for src in &rule.src {
+         let face = match src {
+            FontSource::Url(url, format,) => {
+               if format.as_ref().is_some_and(|f| !FORMATS.contains(&&**f,),) {
+                  continue;
+               }
+               loader.load(url,).and_then(FontFace::load,).and_then(|faces| faces.into_iter().next(),)
+            }
+            FontSource::Local(nam,) => {
+               self.system.iter().find(|face| face.names.iter().any(|n| n.eq_ignore_ascii_case(nam,),),).cloned()
+            }
+         };
+         if let Some(face,) = face {
+            self.web.push(FontFace {
+               family: rule.family.clone(),
+               weight: rule.weight.unwrap_or(face.weight,),
+               style: rule.style.unwrap_or(face.style,),
+               unicode_range: rule.unicode_range.clone(),
+               ..face
+            },);
+            return;
+         }
+      }
+   }
+
+   ///The face to draw 'ch' with, trying each of 'families' in turn, and within
+   /// a family the closest style and weight by the CSS font matching algorithm.
+   /// The bundled default face draws what no family can.
+   pub fn query(&self, families: &[String], weight: u16, style: FontStyle, ch: char,) -> Option<&FontFace,> {
+      families
+         .iter()
+         .find_map(|family| self.match_family(self.resolve_generic(family,), weight, style, ch,),)
+         .or_else(|| self.default.iter().find(|face| face.covers(ch,),),)
+   }
+
+   ///The family 'family' stands for if it's a generic family: the one set with
+   /// 'set_generic_family', or else the first of 'GENERIC_FAMILIES' installed.
+   fn resolve_generic<'s,>(&'s self, family: &'s str,) -> &'s str {
+      if let Some((_, f,),) = self.generic.iter().find(|(g, _,)| g.eq_ignore_ascii_case(family,),) {
+         return f;
+      }
+      let installed = |f: &&&str| self.system.iter().any(|face| face.family.eq_ignore_ascii_case(f,),);
+      GENERIC_FAMILIES
+         .iter()
+         .find(|(g, _,)| g.eq_ignore_ascii_case(family,),)
+         .and_then(|(_, families,)| families.iter().find(installed,),)
+         .map_or(family, |f| f,)
+   }
+
+   fn match_family(&self, family: &str, weight: u16, style: FontStyle, ch: char,) -> Option<&FontFace,> {
+      //Web fonts hide installed fonts of the same family.
+      let mut faces = self.web.iter().filter(|f| f.family.eq_ignore_ascii_case(family,),).collect::<Vec<_,>>();
+      if faces.is_empty() {
+         faces = self.system.iter().filter(|f| f.family.eq_ignore_ascii_case(family,),).collect();
+      }
+      //Later '@font-face' rules win over earlier ones with the same descriptors.
+      faces.reverse();
+      let best = faces.iter().map(|f| (style_rank(style, f.style,), weight_rank(weight, f.weight,),),).min()?;
+      //Of the faces that match best, which only differ in 'unicode-range', take one that has the character.
+      faces
+         .into_iter()
+         .filter(|f| (style_rank(style, f.style,), weight_rank(weight, f.weight,),) == best,)
+         .find(|f| f.covers(ch,),)
+   }
+}
+
+///How well a face's style fits the desired one. Lower is better.
+fn style_rank(desired: FontStyle, face: FontStyle,) -> usize {
+   use FontStyle::*;
+   let order = match desired {
+      Italic => [Italic, Oblique, Normal,],
+      Oblique => [Oblique, Italic, Normal,],
+      Normal => [Normal, Oblique, Italic,],
+   };
+   order.iter().position(|s| *s == face,).unwrap()
+}
+
+///How well a face's weight range fits the desired weight. Lower is better.
+/// For weights from 400 to 500, heavier faces up to 500 come first, then
+/// lighter ones, then heavier ones. Below 400 lighter faces come first, and
+/// above 500 heavier ones, each closest first.
+fn weight_rank(desired: u16, (lo, hi,): (u16, u16,),) -> (u8, u16,) {
+   let lighter = || (hi < desired).then(|| desired - hi,);
+   let heavier = || (lo > desired).then(|| lo - desired,);
+   if (lo..=hi).contains(&desired,) {
+      return (0, 0,);
+   }
+   match desired {
+      400..=500 => match (heavier(), lighter(),) {
+         (Some(d,), _,) if lo <= 500 => (1, d,),
+         (_, Some(d,),) => (2, d,),
+         (Some(d,), _,) => (3, d,),
+         _ => unreachable!(),
+      },
+      _ if desired < 400 => lighter().map(|d| (1, d,),).or_else(|| heavier().map(|d| (2, d,),),).unwrap(),
+      _ => heavier().map(|d| (1, d,),).or_else(|| lighter().map(|d| (2, d,),),).unwrap(),
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use {super::*, crate::layout};
+
+   ///Loads the bundled font from any URL.
+   struct Bundled;
+
+   impl ResourceLoader for Bundled {
+      fn load(&self, _url: &str,) -> Option<Vec<u8,>,> { Some(DEFAULT_FONT.to_vec(),) }
+   }
+
+   ///A database with the web fonts of 'sheet', all made from the bundled font.
+   fn database(sheet: &str,) -> FontDatabase {
+      let mut fonts = FontDatabase::new();
+      let env = MediaEnvironment::new(&layout::Dimensions::default(),);
+      fonts.add_stylesheets(&[css::parse(sheet.to_string(), css::Origin::Author,),], &env, &Bundled,);
+      fonts
+   }
+
+   ///The family, lowest weight and style of the face 'fonts' draws 'ch' with.
+   fn matched(
+      fonts: &FontDatabase,
+      families: &[&str],
+      weight: u16,
+      style: FontStyle,
+      ch: char,
+   ) -> Option<(String, u16, FontStyle,),> {
+      let families: Vec<String,> = families.iter().map(|f| f.to_string(),).collect();
+      fonts.query(&families, weight, style, ch,).map(|face| (face.family.clone(), face.weight.0, face.style,),)
+   }
+
+   ///The '@font-face' rules 'src' parses to.
+   fn font_faces(src: &str,) -> Vec<FontFaceRule,> {
+      let sheet = css::parse(src.to_string(), css::Origin::Author,);
+      sheet.rules.into_iter().filter_map(|rule| if let CssRule::FontFace(face,) = rule { Some(face,) } else { None },).collect()
+   }
+
+   #[test]
+   fn descriptor_without_colon_is_skipped() {
+      assert!(font_faces("@font-face { foo } p { color: red }").is_empty());
+      assert!(font_faces("@font-face { src }").is_empty());
+      let faces = font_faces("@font-face { foo; font-family: A; src: local(B) }",);
+      assert_eq!(faces.len(), 1);
+      assert_eq!(faces[0].family, "A");
+   }
+
+   #[test]
+   fn rule_without_block_is_skipped() {
+      let faces =
+         font_faces("@font-face; @font-face foo { font-family: A; src: local(B) } @font-face { font-family: C; src: local(D) }",);
+      assert_eq!(faces.len(), 1);
+      assert_eq!(faces[0].family, "C");
+      assert!(font_faces("@font-face").is_empty());
+      assert!(font_faces("@font-face { font-family: A; src: local(B)").is_empty());
+      let sheet = css::parse("@font-face foo { src: local(B) } p { color: red }".to_string(), css::Origin::Author,);
+      assert!(matches!(sheet.rules[..], [CssRule::Style(_)]));
+   }
+
+   #[test]
+   fn families_are_tried_in_turn() {
+      let fonts = database(
+         "@font-face { font-family: A; src: url(a); unicode-range: U+0-7F } @font-face { font-family: B; src: url(b) }",
+      );
+      let family = |families: &[&str], ch| matched(&fonts, families, 400, FontStyle::Normal, ch,).map(|(family, ..,)| family,);
+      assert_eq!(family(&["Missing", "A", "B"], 'a',).as_deref(), Some("A"));
+      //Out of the first family's 'unicode-range'.
+      assert_eq!(family(&["Missing", "A", "B"], '\u{e9}',).as_deref(), Some("B"));
+      //The bundled face draws what no family can, and nothing draws what it can't.
+      assert!(family(&["Missing"], 'a',).is_some_and(|f| f != "A" && f != "B"));
+      assert_eq!(family(&["A", "B"], '\u{5d0}',), None);
+   }
+
+   #[test]
+   fn generic_families_stand_for_a_family() {
+      let mut fonts = database("@font-face { font-family: B; src: url(b) }",);
+      fonts.set_generic_family("serif", "B",);
+      assert!(matched(&fonts, &["serif"], 400, FontStyle::Normal, 'a',).is_some_and(|(f, ..,)| f == "B"));
+   }
+
+   #[test]
+   fn closest_weight_wins() {
+      let fonts = database(
+         "@font-face { font-family: A; src: url(a); font-weight: 300 } \
+          @font-face { font-family: A; src: url(a); font-weight: 500 } \
+          @font-face { font-family: A; src: url(a); font-weight: 700 }",
+      );
+      let weight = |weight| matched(&fonts, &["A",], weight, FontStyle::Normal, 'a',).unwrap().1;
+      assert_eq!([400, 450, 350, 600, 900].map(weight,), [500, 500, 300, 700, 700]);
+      let fonts = database("@font-face { font-family: A; src: url(a); font-weight: 300 } @font-face { font-family: A; src: url(a); font-weight: 600 }",);
+      assert_eq!(matched(&fonts, &["A"], 400, FontStyle::Normal, 'a',).unwrap().1, 300);
+   }
+
+   #[test]
+   fn closest_style_wins() {
+      let fonts =
+         database("@font-face { font-family: A; src: url(a); font-style: italic } @font-face { font-family: A; src: url(a) }",);
+      let style = |style| matched(&fonts, &["A",], 400, style, 'a',).unwrap().2;
+      assert!(style(FontStyle::Italic,) == FontStyle::Italic);
+      assert!(style(FontStyle::Oblique,) == FontStyle::Italic);
+      assert!(style(FontStyle::Normal,) == FontStyle::Normal);
+   }
+
+   #[test]
+   fn missing_bold_and_oblique_are_synthesized() {
+      let fonts = database("@font-face { font-family: A; src: url(a) }",);
+      let face = fonts.query(&["A".to_string(),], 700, FontStyle::Italic, 'a',).unwrap();
+      assert!(face.synthesis(700, FontStyle::Italic,) == Synthesis { bold: true, oblique: true, });
+      assert!(face.synthesis(500, FontStyle::Normal,) == Synthesis::default());
+   }
+}
@@ -4,6 +4,8 @@ use crate::{dom, Parser};
 ///Parse a HTML document and return the root element.
 pub fn parse(src: String,) -> dom::Node {
    let mut nodes = Parser { pos: 0, inp: src, }.parse_nodes();
+   //White space around the root element isn't part of the document.
+   nodes.retain(|nod| !matches!(&nod.node_type, dom::NodeType::Text(text) if text.trim().is_empty()),);
    //If the document contains a roo element, just return it. Otherwise, crete one
    if nodes.len() == 1 {
       nodes.swap_remove(0,)
@@ -14,12 +16,7 @@ pub fn parse(src: String,) -> dom::Node {
 
 impl Parser {
    ///Parse a tag or attribute name.
-   fn parse_tag_name(&mut self,) -> String {
-      self.cnsm_while(|c| match c {
-         'a'..='z' | 'A'..='Z' | '0'..='9' => true,
-         _ => false,
-      },)
-   }
+   fn parse_tag_name(&mut self,) -> String { self.cnsm_while(|c| matches!(c, 'a'..='z' | 'A'..='Z' | '0'..='9'),) }
 
    ///Parse a single node.
    fn parse_node(&mut self,) -> dom::Node {
@@ -80,8 +77,8 @@ impl Parser {
    ///Parse a sequence of sibling nodes.
    fn parse_nodes(&mut self,) -> Vec<dom::Node,> {
       let mut nodes = vec![];
+      //White space between nodes is kept as text, since it separates inline content.
       loop {
-         self.cnsm_whitespace();
          if self.eof() || self.starts_with("</",) {
             break;
          }
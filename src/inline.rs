@@ -0,0 +1,717 @@
+#![allow(unused)]
+use std::ops::Range;
+
+use {
+   unicode_bidi::{BidiInfo, Level},
+   unicode_linebreak::{linebreaks, BreakOpportunity},
+};
+
+use crate::{
+   dom,
+   float::FloatContext,
+   font::FontDatabase,
+   layout::{BoxType, Dimensions, EdgeSizes, LayoutBox, Rct},
+   properties::{ComputedValues, Direction, LineHeight, UnicodeBidi, WhiteSpace},
+   style,
+   text::{self, Glyph},
+};
+
+///A line of inline content in a block container.
+#[derive(Clone,)]
+pub struct LineBox<'a,> {
+   pub rct:       Rct,
+   ///Height of the baseline the text sits on, in document coordinates.
+   pub baseline:  f64,
+   pub fragments: Vec<Fragment<'a,>,>,
+   ///The parts of the inline elements with text on the line, in tree order.
+   pub boxes:     Vec<InlineFragment<'a,>,>,
+}
+
+///The part of a text node that falls on one line.
+#[derive(Clone,)]
+pub struct Fragment<'a,> {
+   ///The text node, whose computed values the text is drawn with.
+   pub styled: &'a style::StyledNode<'a,>,
+   pub text:   String,
+   ///The glyphs that draw 'text', placed from the left of 'rct', as line
+   /// layout shaped them.
+   pub glyphs: Vec<Glyph<'a,>,>,
+   ///From the top of the ascent to the bottom of the descent.
+   pub rct:    Rct,
+   ///Whether the text runs right to left, by its bidi embedding level.
+   pub rtl:    bool,
+}
+
+///The part of an inline element on one line, whose background and borders
+/// are painted there.
+#[derive(Clone,)]
+pub struct InlineFragment<'a,> {
+   pub styled:     &'a style::StyledNode<'a,>,
+   ///The content area around the text of the element on the line, and its
+   /// padding and borders. The sides where the element goes on from another
+   /// line have none.
+   pub dimensions: Dimensions,
+}
+
+///Inline content in document order, with white space processed.
+enum Item<'a,> {
+   ///Text of a text node, starting at 'start' in the paragraph.
+   Text {
+      styled: &'a style::StyledNode<'a,>,
+      start:  usize,
+      len:    usize,
+   },
+   ///Start or end of an inline element: the width of its margin, border and
+   /// padding on that side, and the bidi control characters its 'unicode-bidi'
+   /// stands for.
+   Start {
+      width:    f64,
+      controls: &'static str,
+   },
+   End {
+      width:    f64,
+      controls: &'static str,
+   },
+}
+
+///A piece of inline content that line breaking places as a whole.
+enum Atom<'a,> {
+   Text {
+      styled: &'a style::StyledNode<'a,>,
+      text:   String,
+      glyphs: Vec<Glyph<'a,>,>,
+      width:  f64,
+      level:  Level,
+   },
+   Edge {
+      width: f64,
+      level: Level,
+   },
+   ///A soft wrap opportunity, or a forced break if 'true'.
+   Break(bool,),
+}
+
+///Ascent, descent and line height of a text node, which place its text on a line.
+struct LineMetrics {
+   ascent:      f64,
+   descent:     f64,
+   line_height: f64,
+}
+
+impl<'a,> LayoutBox<'a,> {
+   ///Lay out the inline children of a block container in line boxes as wide as
+   /// its content, breaking lines at the soft wrap opportunities of UAX #14,
+   /// and set the content height to that of the lines stacked. Each line is
+   /// put in visual order by the Unicode Bidirectional Algorithm, and
+   /// shortened to fit beside 'floats'.
+   pub(crate) fn layout_inline(&mut self, fonts: &'a FontDatabase, floats: &FloatContext,) {
+      let cb_width = self.dimensions.content.width;
+      let mut items = vec![];
+      let mut para = String::new();
+      //Collapsible spaces at the start of the paragraph are removed.
+      let mut after_space = true;
+      for child in &self.children {
+         collect_items(child, cb_width, &mut items, &mut para, &mut after_space,);
+      }
+      let base = base_level(&self.children,);
+      let levels = bidi_levels(&items, &para, base,);
+      let atoms = atoms(&items, &para, &levels, base, fonts,);
+      //Lines are fitted beside floats at the height of the container's own font.
+      let line_height = self.children.iter().find_map(|child| match child.box_type {
+         BoxType::InlineNode(styled,) => Some(line_metrics(styled.computed(), fonts,).line_height,),
+         _ => None,
+      },);
+      let space = Space { content: &self.dimensions.content, floats, line_height: line_height.unwrap_or(0.0,), };
+      self.lines = break_lines(atoms, &space, base, fonts,);
+      for child in &self.children {
+         inline_fragments(child, cb_width, fonts, &mut self.lines,);
+      }
+      //Lines moved down past floats leave gaps, which count too.
+      let content = &mut self.dimensions.content;
+      content.height = self.lines.last().map_or(0.0, |line| line.rct.y + line.rct.height - content.y,);
+   }
+}
+
+///Add the items of 'layout_box' and its descendants, and their text to 'para'.
+/// 'after_space' tells whether the text so far ends in a collapsible space.
+fn collect_items<'a,>(
+   layout_box: &LayoutBox<'a,>,
+   cb_width: f64,
+   items: &mut Vec<Item<'a,>,>,
+   para: &mut String,
+   after_space: &mut bool,
+) {
+   //Blocks inside inline content are left to block layout.
+   let BoxType::InlineNode(styled,) = layout_box.box_type else { return };
+   let style = styled.computed();
+   match styled.node().node_type {
+      dom::NodeType::Text(ref text,) => {
+         let text = process_white_space(text, style.white_space, after_space,);
+         items.push(Item::Text { styled, start: para.len(), len: text.len(), },);
+         para.push_str(&text,);
+      }
+      dom::NodeType::Element(_,) => {
+         let (m, p,) = (&style.margin, &style.padding,);
+         let (open, close,) = bidi_controls(style,);
+         let width = m.left.to_px(cb_width,) + style.border_width.left + p.left.to_px(cb_width,);
+         items.push(Item::Start { width, controls: open, },);
+         for child in &layout_box.children {
+            collect_items(child, cb_width, items, para, after_space,);
+         }
+         let width = m.right.to_px(cb_width,) + style.border_width.right + p.right.to_px(cb_width,);
+         items.push(Item::End { width, controls: close, },);
+      }
+   }
+}
+
+///Add to 'lines' the parts of the inline elements in 'layout_box' and its
+/// descendants on each of them, as wide as the fragments of their text there
+/// and as tall as their font. Only the first part of an element has its left
+/// padding and border, and only the last its right.
+fn inline_fragments<'a,>(layout_box: &LayoutBox<'a,>, cb_width: f64, fonts: &FontDatabase, lines: &mut [LineBox<'a,>],) {
+   let BoxType::InlineNode(styled,) = layout_box.box_type else { return };
+   if let dom::NodeType::Element(_,) = styled.node().node_type {
+      let mut texts = vec![];
+      text_nodes(layout_box, &mut texts,);
+      //The line of each part, and the left and right of the text on it.
+      let parts: Vec<(usize, f64, f64,),> = lines
+         .iter()
+         .enumerate()
+         .filter_map(|(i, line,)| {
+            line
+               .fragments
+               .iter()
+               .filter(|f| texts.iter().any(|text| std::ptr::eq(*text, f.styled,),),)
+               .map(|f| (f.rct.x, f.rct.x + f.rct.width,),)
+               .reduce(|(l, r,), (fl, fr,)| (l.min(fl,), r.max(fr,),),)
+               .map(|(left, right,)| (i, left, right,),)
+         },)
+         .collect();
+      let style = styled.computed();
+      let m = line_metrics(style, fonts,);
+      let p = &style.padding;
+      let b = &style.border_width;
+      let (first, last,) = (parts.first().map(|part| part.0,), parts.last().map(|part| part.0,),);
+      for (i, left, right,) in parts {
+         let (start, end,) = (Some(i,) == first, Some(i,) == last,);
+         let side = |open: bool, width: f64| if open { width } else { 0.0 };
+         let line = &mut lines[i];
+         let dimensions = Dimensions {
+            content: Rct { x: left, y: line.baseline - m.ascent, width: right - left, height: m.ascent + m.descent, },
+            padding: EdgeSizes {
+               left:   side(start, p.left.to_px(cb_width,),),
+               right:  side(end, p.right.to_px(cb_width,),),
+               top:    p.top.to_px(cb_width,),
+               bottom: p.bottom.to_px(cb_width,),
+            },
+            border:  EdgeSizes { left: side(start, b.left,), right: side(end, b.right,), top: b.top, bottom: b.bottom, },
+            margin:  EdgeSizes::default(),
+         };
+         line.boxes.push(InlineFragment { styled, dimensions, },);
+      }
+   }
+   for child in &layout_box.children {
+      inline_fragments(child, cb_width, fonts, lines,);
+   }
+}
+
+///Add the text nodes in 'layout_box' and its descendants to 'texts'.
+fn text_nodes<'a,>(layout_box: &LayoutBox<'a,>, texts: &mut Vec<&'a style::StyledNode<'a,>,>,) {
+   let BoxType::InlineNode(styled,) = layout_box.box_type else { return };
+   match styled.node().node_type {
+      dom::NodeType::Text(_,) => texts.push(styled,),
+      dom::NodeType::Element(_,) => layout_box.children.iter().for_each(|child| text_nodes(child, texts,),),
+   }
+}
+
+///The control characters that open and close the content of an inline element
+/// with this 'unicode-bidi' and 'direction'.
+fn bidi_controls(style: &ComputedValues,) -> (&'static str, &'static str,) {
+   let rtl = style.direction == Direction::Rtl;
+   match style.unicode_bidi {
+      UnicodeBidi::Normal => ("", "",),
+      UnicodeBidi::Embed if rtl => ("\u{202B}", "\u{202C}",),
+      UnicodeBidi::Embed => ("\u{202A}", "\u{202C}",),
+      UnicodeBidi::Isolate if rtl => ("\u{2067}", "\u{2069}",),
+      UnicodeBidi::Isolate => ("\u{2066}", "\u{2069}",),
+      UnicodeBidi::BidiOverride if rtl => ("\u{202E}", "\u{202C}",),
+      UnicodeBidi::BidiOverride => ("\u{202D}", "\u{202C}",),
+      UnicodeBidi::IsolateOverride if rtl => ("\u{2067}\u{202E}", "\u{202C}\u{2069}",),
+      UnicodeBidi::IsolateOverride => ("\u{2066}\u{202D}", "\u{202C}\u{2069}",),
+      UnicodeBidi::Plaintext => ("\u{2068}", "\u{2069}",),
+   }
+}
+
+///The paragraph embedding level, from the 'direction' of the block container.
+/// Anonymous blocks have no style, so it is read from the first inline child,
+/// which inherits it.
+fn base_level(children: &[LayoutBox],) -> Level {
+   let direction = children.iter().find_map(|child| match child.box_type {
+      BoxType::InlineNode(styled,) => Some(styled.computed().direction,),
+      _ => None,
+   },);
+   match direction {
+      Some(Direction::Rtl,) => Level::rtl(),
+      _ => Level::ltr(),
+   }
+}
+
+///The embedding level of each byte of 'para', by the Unicode Bidirectional
+/// Algorithm run over the text with the bidi controls of its elements.
+fn bidi_levels(items: &[Item], para: &str, base: Level,) -> Vec<Level,> {
+   let mut text = String::new();
+   //Where each byte of 'para' is in 'text'.
+   let mut map = Vec::with_capacity(para.len(),);
+   for item in items {
+      match *item {
+         Item::Text { start, len, .. } => {
+            map.extend(text.len()..text.len() + len,);
+            text.push_str(&para[start..start + len],);
+         }
+         Item::Start { controls, .. } | Item::End { controls, .. } => text.push_str(controls,),
+      }
+   }
+   let info = BidiInfo::new(&text, Some(base,),);
+   map.into_iter().map(|i| info.levels[i],).collect()
+}
+
+///Collapse spaces, tabs and newlines as 'white_space' says. 'after_space' carries
+/// over from the text before, so spaces collapse across elements.
+fn process_white_space(text: &str, white_space: WhiteSpace, after_space: &mut bool,) -> String {
+   if matches!(white_space, WhiteSpace::Pre | WhiteSpace::PreWrap) {
+      *after_space = false;
+      return text.to_string();
+   }
+   let mut out = String::new();
+   for ch in text.chars() {
+      match ch {
+         //'pre-line' keeps newlines, and removes the spaces around them.
+         '\n' if white_space == WhiteSpace::PreLine => {
+            out.truncate(out.trim_end_matches(' ',).len(),);
+            out.push('\n',);
+            *after_space = true;
+         }
+         ' ' | '\t' | '\n' | '\r' => {
+            if !*after_space {
+               out.push(' ',);
+            }
+            *after_space = true;
+         }
+         _ => {
+            out.push(ch,);
+            *after_space = false;
+         }
+      }
+   }
+   out
+}
+
+///Does 'white_space' allow lines to wrap at soft wrap opportunities?
+fn wraps(white_space: WhiteSpace,) -> bool { !matches!(white_space, WhiteSpace::Pre | WhiteSpace::Nowrap) }
+
+///Split 'items' at the line break opportunities in 'para', and where the
+/// embedding level in 'levels' changes. A break belongs after the ends of
+/// elements at its position and before their starts.
+fn atoms<'a,>(items: &[Item<'a,>], para: &str, levels: &[Level], base: Level, fonts: &'a FontDatabase,) -> Vec<Atom<'a,>,> {
+   //The text item each byte of 'para' belongs to decides whether it wraps.
+   let style_at = |pos: usize| {
+      items.iter().find_map(|item| match *item {
+         Item::Text { styled, start, len, } if (start..start + len).contains(&pos,) => Some(styled.computed(),),
+         _ => None,
+      },)
+   };
+   let mut breaks = linebreaks(para,)
+      .filter(|&(pos, opportunity,)| {
+         //A break is governed by the text before it.
+         opportunity == BreakOpportunity::Mandatory
+            || para[..pos]
+               .chars()
+               .next_back()
+               .and_then(|ch| style_at(pos - ch.len_utf8(),),)
+               .is_some_and(|s| wraps(s.white_space,),)
+      },)
+      .map(|(pos, opportunity,)| (pos, opportunity == BreakOpportunity::Mandatory,),)
+      .peekable();
+   let mut atoms = vec![];
+   //End of the text so far.
+   let mut pos = 0;
+   for item in items {
+      match *item {
+         Item::Text { styled, start, len, } => {
+            pos = start + len;
+            let mut from = start;
+            while let Some(&(at, forced,),) = breaks.peek() {
+               if at >= start + len {
+                  break;
+               }
+               push_text(&mut atoms, styled, para, levels, from..at, fonts,);
+               from = at;
+               atoms.push(Atom::Break(forced,),);
+               breaks.next();
+            }
+            push_text(&mut atoms, styled, para, levels, from..start + len, fonts,);
+         }
+         Item::Start { width, .. } => {
+            //Breaks at this position come before the element.
+            while let Some(&(_, forced,),) = breaks.peek().filter(|&&(at, _,)| at <= pos,) {
+               atoms.push(Atom::Break(forced,),);
+               breaks.next();
+            }
+            atoms.push(Atom::Edge { width, level: levels.get(pos,).copied().unwrap_or(base,), },);
+         }
+         Item::End { width, .. } => {
+            let level = pos.checked_sub(1,).map_or(base, |last| levels[last],);
+            atoms.push(Atom::Edge { width, level, },)
+         }
+      }
+   }
+   atoms.extend(breaks.map(|(_, forced,)| Atom::Break(forced,),),);
+   atoms
+}
+
+///Add text atoms for 'range' of 'para', one per run of the same embedding level.
+fn push_text<'a,>(
+   atoms: &mut Vec<Atom<'a,>,>,
+   styled: &'a style::StyledNode<'a,>,
+   para: &str,
+   levels: &[Level],
+   range: Range<usize,>,
+   fonts: &'a FontDatabase,
+) {
+   let mut from = range.start;
+   for (i, _,) in para[range.clone()].char_indices() {
+      let at = range.start + i;
+      if levels[at] != levels[from] {
+         atoms.push(text_atom(styled, &para[from..at], levels[from], fonts,),);
+         from = at;
+      }
+   }
+   if range.end > from {
+      atoms.push(text_atom(styled, &para[from..range.end], levels[from], fonts,),);
+   }
+}
+
+///A text atom of 'text', without the newline that forces the break after it,
+/// shaped in the font of its node.
+fn text_atom<'a,>(styled: &'a style::StyledNode<'a,>, text: &str, level: Level, fonts: &'a FontDatabase,) -> Atom<'a,> {
+   let text = text.strip_suffix('\n',).unwrap_or(text,).to_string();
+   let (glyphs, width,) = text::glyphs(&text, styled.computed(), level.is_rtl(), fonts,);
+   Atom::Text { styled, text, glyphs, width, level, }
+}
+
+///Metrics of the font of 'style', from the face of its first family that has
+/// a space. Without one, the ascent and descent are 0.8 and 0.2 em.
+fn line_metrics(style: &ComputedValues, fonts: &FontDatabase,) -> LineMetrics {
+   let size = style.font_size;
+   let (ascent, descent, line_gap,) = fonts
+      .query(&style.font_family, style.font_weight, style.font_style, ' ',)
+      .map_or((0.8 * size, 0.2 * size, 0.0,), |face| face.metrics(size,),);
+   let line_height = match style.line_height {
+      LineHeight::Normal => ascent + descent + line_gap,
+      LineHeight::Number(n,) => n * size,
+      LineHeight::Px(px,) => px,
+   };
+   LineMetrics { ascent, descent, line_height, }
+}
+
+///Where lines go: the content box of their block container, less the floats
+/// beside each line.
+struct Space<'s,> {
+   content:     &'s Rct,
+   floats:      &'s FloatContext,
+   ///Height of the lines to fit beside floats.
+   line_height: f64,
+}
+
+impl Space<'_,> {
+   ///The room for a line at 'y', with the floats beside it left out.
+   fn line_at(&self, y: f64,) -> Rct {
+      let (left, right,) = self.floats.available(y, self.line_height, self.content.x, self.content.x + self.content.width,);
+      Rct { x: left, y, width: right - left, height: 0.0, }
+   }
+}
+
+///Fill lines as wide as 'space' allows with 'atoms', starting at the top of
+/// its content, and break them where the next atoms up to a break
+/// opportunity don't fit. A line with no room for them beside floats moves
+/// down past them. 'base' is the paragraph embedding level.
+fn break_lines<'a,>(atoms: Vec<Atom<'a,>,>, space: &Space, base: Level, fonts: &'a FontDatabase,) -> Vec<LineBox<'a,>,> {
+   let mut lines = vec![];
+   let mut line: Vec<Atom<'a,>,> = vec![];
+   let mut line_width = 0.0;
+   let mut y = space.content.y;
+   let mut room = space.line_at(y,);
+   let mut segment = vec![];
+   for atom in atoms {
+      let Atom::Break(forced,) = atom else {
+         segment.push(atom,);
+         continue;
+      };
+      //Spaces at the end of a line hang, so don't keep the segment from fitting.
+      let width: f64 = segment.iter().map(atom_width,).sum();
+      let hanging = segment.iter().rev().find_map(|atom| match atom {
+         Atom::Text { text, glyphs, .. } => Some(trailing_spaces_width(text, glyphs,),),
+         _ => None,
+      },);
+      let fitting = width - hanging.unwrap_or(0.0,);
+      if !line.is_empty() && line_width + fitting > room.width {
+         lines.push(place_line(std::mem::take(&mut line,), &room, &mut y, base, fonts,),);
+         room = space.line_at(y,);
+         line_width = 0.0;
+      }
+      while line.is_empty() && fitting > room.width {
+         match space.floats.next_bottom(y, space.line_height,) {
+            Some(bottom,) => {
+               y = bottom;
+               room = space.line_at(y,);
+            }
+            None => break,
+         }
+      }
+      line_width += width;
+      line.append(&mut segment,);
+      if forced {
+         lines.push(place_line(std::mem::take(&mut line,), &room, &mut y, base, fonts,),);
+         room = space.line_at(y,);
+         line_width = 0.0;
+      }
+   }
+   if !line.is_empty() || !segment.is_empty() {
+      line.append(&mut segment,);
+      lines.push(place_line(line, &room, &mut y, base, fonts,),);
+   }
+   lines
+}
+
+///Width of the glyphs of the spaces at the end of 'text'.
+fn trailing_spaces_width(text: &str, glyphs: &[Glyph],) -> f64 {
+   let end = text.trim_end_matches(' ',).len();
+   glyphs.iter().filter(|glyph| glyph.cluster >= end,).map(|glyph| glyph.advance,).sum()
+}
+
+fn atom_width(atom: &Atom,) -> f64 {
+   match *atom {
+      Atom::Text { width, .. } | Atom::Edge { width, .. } => width,
+      Atom::Break(_,) => 0.0,
+   }
+}
+
+///Place the atoms of a line in visual order from the start of 'content' at
+/// height 'y', and move 'y' below the line. Adjacent text of the same node and
+/// level makes one fragment, and the line is as tall as the line heights of
+/// its text need. Lines of a right-to-left paragraph start at the right.
+fn place_line<'a,>(
+   mut atoms: Vec<Atom<'a,>,>, content: &Rct, y: &mut f64, base: Level, fonts: &'a FontDatabase,
+) -> LineBox<'a,> {
+   atoms.retain(|atom| !matches!(atom, Atom::Break(_)),);
+   //Collapsible spaces at the end of the line are removed.
+   let last_text = atoms.iter_mut().rev().find_map(|atom| match atom {
+      Atom::Text { styled, text, glyphs, width, level, } => Some((styled.computed(), text, glyphs, width, level.is_rtl(),),),
+      _ => None,
+   },);
+   if let Some((style, text, glyphs, width, rtl,),) = last_text {
+      if !matches!(style.white_space, WhiteSpace::Pre | WhiteSpace::PreWrap) {
+         let spaces = trailing_spaces_width(text, glyphs,);
+         let end = text.trim_end_matches(' ',).len();
+         glyphs.retain(|glyph| glyph.cluster < end,);
+         //Right-to-left, the spaces were on the left.
+         if rtl {
+            glyphs.iter_mut().for_each(|glyph| glyph.x -= spaces,);
+         }
+         *width -= spaces;
+         text.truncate(end,);
+      }
+   }
+   let levels: Vec<Level,> = atoms
+      .iter()
+      .map(|atom| match *atom {
+         Atom::Text { level, .. } | Atom::Edge { level, .. } => level,
+         Atom::Break(_,) => unreachable!(),
+      },)
+      .collect();
+   let mut visual: Vec<Option<Atom<'a,>,>,> = atoms.into_iter().map(Some,).collect();
+   let mut fragments: Vec<Fragment<'a,>,> = vec![];
+   let mut x = content.x;
+   //Was the last atom text, which the next text of the same node joins?
+   let mut after_text = false;
+   for i in BidiInfo::reorder_visual(&levels,) {
+      match visual[i].take().expect("each atom is placed once",) {
+         Atom::Text { styled, text, mut glyphs, width, level, } => {
+            match fragments.last_mut() {
+               Some(last,) if after_text && std::ptr::eq(last.styled, styled,) && last.rtl == level.is_rtl() => {
+                  //Right-to-left text is placed from its end. Either way its
+                  // glyphs go on the right.
+                  match last.rtl {
+                     true => {
+                        last.glyphs.iter_mut().for_each(|glyph| glyph.cluster += text.len(),);
+                        last.text.insert_str(0, &text,);
+                     }
+                     false => {
+                        glyphs.iter_mut().for_each(|glyph| glyph.cluster += last.text.len(),);
+                        last.text.push_str(&text,);
+                     }
+                  }
+                  glyphs.iter_mut().for_each(|glyph| glyph.x += last.rct.width,);
+                  last.glyphs.append(&mut glyphs,);
+                  last.rct.width += width;
+               }
+               _ => {
+                  let rct = Rct { x, y: 0.0, width, height: 0.0, };
+                  fragments.push(Fragment { styled, text, glyphs, rct, rtl: level.is_rtl(), },)
+               }
+            }
+            x += width;
+            after_text = true;
+         }
+         Atom::Edge { width, .. } => {
+            x += width;
+            after_text = false;
+         }
+         Atom::Break(_,) => {}
+      }
+   }
+   if base.is_rtl() {
+      let shift = content.x + content.width - x;
+      for fragment in &mut fragments {
+         fragment.rct.x += shift;
+      }
+   }
+   //Align the baselines, each text's line height split evenly around its
+   // ascent and descent.
+   let metrics: Vec<LineMetrics,> = fragments.iter().map(|f| line_metrics(f.styled.computed(), fonts,),).collect();
+   let above_of = |m: &LineMetrics| (m.line_height - m.ascent - m.descent) / 2.0 + m.ascent;
+   let above = metrics.iter().map(above_of,).fold(0.0, f64::max,);
+   let below = metrics.iter().map(|m| m.line_height - above_of(m,),).fold(0.0, f64::max,);
+   let baseline = *y + above;
+   for (fragment, m,) in fragments.iter_mut().zip(&metrics,) {
+      fragment.rct.y = baseline - m.ascent;
+      fragment.rct.height = m.ascent + m.descent;
+   }
+   let rct = Rct { x: content.x, y: *y, width: content.width, height: above + below, };
+   *y += rct.height;
+   LineBox { rct, baseline, fragments, boxes: vec![], }
+}
+
+#[cfg(test)]
+mod tests {
+   use {
+      super::*,
+      crate::{css, html, layout, media},
+   };
+
+   ///A fragment's text, left edge, width, whether it runs right to left, and
+   /// the clusters and positions of its glyphs.
+   type Placed = (String, f64, f64, bool, Vec<(usize, f64,),>,);
+
+   ///'line' of each line of 'src' laid out with 'sheet' in an 800px wide
+   /// viewport.
+   fn map_lines<T,>(src: &str, sheet: &str, line: fn(&LineBox,) -> T,) -> Vec<T,> {
+      fn collect<T,>(layout_box: &LayoutBox, line: fn(&LineBox,) -> T, out: &mut Vec<T,>,) {
+         out.extend(layout_box.lines.iter().map(line,),);
+         layout_box.children.iter().for_each(|child| collect(child, line, out,),);
+      }
+      let root = html::parse(src.to_string(),);
+      let sheets = [css::parse(format!("body, p {{ margin: 0 }} {sheet}"), css::Origin::Author,),];
+      let mut viewport = Dimensions::default();
+      viewport.content.width = 800.0;
+      viewport.content.height = 600.0;
+      let env = media::MediaEnvironment::new(&viewport,);
+      let styled = style::style_tree(&root, &sheets, &env, &Default::default(), style::Traversal::Sequential,);
+      let fonts = FontDatabase::new();
+      let mut out = vec![];
+      collect(&layout::layout_tree(&styled, viewport, &fonts,), line, &mut out,);
+      out
+   }
+
+   ///The fragments of each line.
+   fn lines(src: &str, sheet: &str,) -> Vec<Vec<Placed,>,> {
+      map_lines(src, sheet, |line| {
+         let glyphs = |f: &Fragment| f.glyphs.iter().map(|g| (g.cluster, g.x,),).collect();
+         line.fragments.iter().map(|f| (f.text.clone(), f.rct.x, f.rct.width, f.rtl, glyphs(f,),),).collect()
+      },)
+   }
+
+   ///The text and direction of each fragment of each line.
+   fn texts(src: &str, sheet: &str,) -> Vec<Vec<(String, bool,),>,> {
+      lines(src, sheet,).into_iter().map(|line| line.into_iter().map(|(text, _, _, rtl, _,)| (text, rtl,),).collect(),).collect()
+   }
+
+   #[test]
+   fn right_to_left_runs_are_reordered() {
+      let line = lines("<p>ab \u{5d0}\u{5d1}\u{5d2} \u{5d3}\u{5d4} cd</p>", "",).remove(0,);
+      let texts: Vec<(&str, bool,),> = line.iter().map(|(text, _, _, rtl, _,)| (&**text, *rtl,),).collect();
+      assert_eq!(texts, [("ab ", false), ("\u{5d0}\u{5d1}\u{5d2} \u{5d3}\u{5d4}", true), (" cd", false)]);
+      //In visual order, side by side.
+      assert!(line.windows(2,).all(|pair| pair[0].1 + pair[0].2 == pair[1].1));
+   }
+
+   #[test]
+   fn right_to_left_paragraph_lines_start_at_the_right() {
+      let line = lines("<p>ab cd</p>", "p { direction: rtl }",).remove(0,);
+      assert_eq!(line.len(), 1);
+      let (text, x, width, rtl, _,) = &line[0];
+      assert_eq!((&**text, *rtl), ("ab cd", false));
+      assert_eq!(x + width, 800.0);
+   }
+
+   #[test]
+   fn fragments_keep_the_glyphs_line_layout_shaped() {
+      //Adjacent atoms of a node join, and so do their glyphs.
+      let line = lines("<p>ab cd</p>", "",).remove(0,);
+      let glyphs = &line[0].4;
+      assert_eq!(glyphs.iter().map(|g| g.0,).collect::<Vec<_,>>(), [0, 1, 2, 3, 4]);
+      assert!(glyphs.windows(2,).all(|pair| pair[0].1 < pair[1].1));
+   }
+
+   #[test]
+   fn trailing_spaces_are_dropped_from_the_glyphs() {
+      let sheet = "p { width: 30px } span { direction: rtl; unicode-bidi: bidi-override }";
+      let lines = lines("<p><span>abc def</span></p>", sheet,);
+      assert_eq!(lines.len(), 2);
+      let (text, _, width, rtl, glyphs,) = &lines[0][0];
+      assert_eq!((&**text, *rtl), ("abc", true));
+      //Right to left, the space was on the left of the glyphs.
+      assert_eq!(glyphs.iter().map(|g| g.0,).collect::<Vec<_,>>(), [2, 1, 0]);
+      assert_eq!(glyphs[0].1, 0.0);
+      assert!(glyphs[2].1 < *width);
+      assert_eq!(texts("<p>abc def</p>", "p { width: 30px }",), [[("abc".to_string(), false)], [("def".to_string(), false)]]);
+   }
+
+   ///Each line's text, which is left to right and of one node.
+   fn line_texts(src: &str, sheet: &str,) -> Vec<String,> {
+      texts(src, sheet,).into_iter().map(|line| line.into_iter().map(|(text, _,)| text,).collect(),).collect()
+   }
+
+   #[test]
+   fn lines_break_at_the_last_opportunity_that_fits() {
+      //About 16 characters of the default font fit on a line at 100px, not
+      // counting a trailing space.
+      assert_eq!(line_texts("<p>aaaa bbbb cccc dddd</p>", "p { font-size: 100px }",), ["aaaa bbbb cccc", "dddd"]);
+      assert_eq!(line_texts("<p>aaaaaaaaaaaaaaaaaaaa bb</p>", "p { font-size: 100px }",), ["aaaaaaaaaaaaaaaaaaaa", "bb"]);
+   }
+
+   #[test]
+   fn nowrap_and_pre_only_break_at_newlines() {
+      let src = "<p>aaaa bbbb cccc dddd</p>";
+      assert_eq!(line_texts(src, "p { font-size: 100px; white-space: nowrap }",), ["aaaa bbbb cccc dddd"]);
+      assert_eq!(line_texts("<p>ab\ncd  ef</p>", "p { white-space: pre }",), ["ab", "cd  ef"]);
+      assert_eq!(line_texts("<p>ab  \n  cd</p>", "p { white-space: pre-line }",), ["ab", "cd"]);
+   }
+
+   #[test]
+   fn white_space_collapses() {
+      let mut after_space = true;
+      assert_eq!(process_white_space("  a \n\t b  ", WhiteSpace::Normal, &mut after_space,), "a b ");
+      assert!(after_space);
+      //The space carries over to the next text.
+      assert_eq!(process_white_space(" c", WhiteSpace::Nowrap, &mut after_space,), "c");
+      assert_eq!(process_white_space("a  \n  b", WhiteSpace::PreLine, &mut true,), "a\nb");
+      assert_eq!(process_white_space(" a  b ", WhiteSpace::PreWrap, &mut true,), " a  b ");
+   }
+
+   #[test]
+   fn line_height_sets_the_height_of_lines() {
+      assert_eq!(map_lines("<p>ab</p>", "p { font-size: 10px; line-height: 3 }", |line| line.rct.height,), [30.0]);
+      assert_eq!(map_lines("<p>ab</p>", "p { font-size: 10px; line-height: 14px }", |line| line.rct.height,), [14.0]);
+   }
+}
@@ -1,5 +1,7 @@
 #![allow(unused)]
-use crate::{css, style};
+use std::fmt;
+
+use crate::{css, dom, float::FloatContext, font::FontDatabase, inline::LineBox, positioned, properties, style};
 
 ///CSS box model. All sizes are in px.
 #[derive(Default, Clone,)]
@@ -7,20 +9,20 @@ pub struct Dimensions {
    //Position of the content area  relative to the document origin:
    pub content: Rct,
    //Surrounding edges:
-   padding:     EdgeSizes,
+   pub padding: EdgeSizes,
    pub border:  EdgeSizes,
-   margin:      EdgeSizes,
+   pub margin:  EdgeSizes,
 }
 
 impl Dimensions {
    ///The area covered by the content area plus its padding.
-   fn padding_box(&self,) -> Rct { self.content.expanded_by(&self.padding,) }
+   pub(crate) fn padding_box(&self,) -> Rct { self.content.expanded_by(&self.padding,) }
 
    ///The area covered by the content area plus padding and borders.
    pub fn border_box(&self,) -> Rct { self.padding_box().expanded_by(&self.border,) }
 
    ///The area covered by the content area plus padding, borders, and margin.
-   fn margin_box(&self,) -> Rct { self.border_box().expanded_by(&self.margin,) }
+   pub(crate) fn margin_box(&self,) -> Rct { self.border_box().expanded_by(&self.margin,) }
 }
 
 ///Rectangular module
@@ -52,12 +54,48 @@ pub struct EdgeSizes {
    pub bottom: f64,
 }
 
+///Adjoining vertical margins that haven't been collapsed into one yet: the
+/// largest positive margin and the most negative one (CSS 2.1 §8.3.1).
+#[derive(Default, Clone, Copy,)]
+pub(crate) struct MarginStrut {
+   positive: f64,
+   negative: f64,
+}
+
+impl MarginStrut {
+   ///Join 'margin' to the adjoining margins.
+   fn add(&mut self, margin: f64,) {
+      if margin > 0.0 {
+         self.positive = self.positive.max(margin,);
+      } else {
+         self.negative = self.negative.min(margin,);
+      }
+   }
+
+   ///Width of the collapsed margin.
+   fn collapsed(&self,) -> f64 { self.positive + self.negative }
+}
+
+///How a box laid out in the flow joins the content of its parent.
+#[derive(Clone, Copy, PartialEq,)]
+pub(crate) enum Placement {
+   ///The box is empty, so the margins around it collapsed through it.
+   Empty,
+   ///The box has content, below the margins above it.
+   Placed,
+   ///The box has content, put below floats by clearance, which keeps its top
+   /// margin from collapsing with its parent's.
+   Cleared,
+}
+
 ///The layout tree is a collection of layoutboxes. It contains boxes as child
 #[derive(Clone,)]
 pub struct LayoutBox<'a,> {
    pub box_type:   BoxType<'a,>,
    pub dimensions: Dimensions,
    pub children:   Vec<LayoutBox<'a,>,>,
+   ///Lines of inline content, if the children are inline.
+   pub lines:      Vec<LineBox<'a,>,>,
 }
 
 ///A box can be a block node, an inline node, OR an anonymous block box
@@ -70,10 +108,12 @@ pub enum BoxType<'a,> {
 
 impl<'a,> LayoutBox<'a,> {
    ///Constructor
-   fn new(box_type: BoxType,) -> LayoutBox { LayoutBox { box_type, dimensions: Default::default(), children: vec![], } }
+   fn new(box_type: BoxType,) -> LayoutBox {
+      LayoutBox { box_type, dimensions: Default::default(), children: vec![], lines: vec![], }
+   }
 
    ///getter of style_node which is contained in box_type
-   fn get_style_node(&self,) -> &'a style::StyledNode<'a,> {
+   pub(crate) fn get_style_node(&self,) -> &'a style::StyledNode<'a,> {
       match &self.box_type {
          BoxType::BlockNode(nod,) | BoxType::InlineNode(nod,) => nod,
          BoxType::AnonymousBlock => panic!("AnonymousBlock has no style node",),
@@ -81,176 +121,935 @@ impl<'a,> LayoutBox<'a,> {
    }
 
    ///Where a new inline child should go.
-   fn get_inline_container(self,) -> LayoutBox<'a,> {
+   fn get_inline_container(&mut self,) -> &mut LayoutBox<'a,> {
       match self.box_type {
          BoxType::BlockNode(_,) => {
             //If we've just generated an anonymous block box, keep using it.
             //Otherwise, create a new one.
-            match self.children.last() {
-               Some(&LayoutBox { box_type: BoxType::AnonymousBlock, .. },) => self.children.last().unwrap().clone(),
-               _ => {
-                  let mut cl = self;
-                  cl.children.push(LayoutBox::new(BoxType::AnonymousBlock,),);
-                  cl.children.last().unwrap().clone()
-               }
+            if !matches!(self.children.last(), Some(LayoutBox { box_type: BoxType::AnonymousBlock, .. })) {
+               self.children.push(LayoutBox::new(BoxType::AnonymousBlock,),);
             }
+            self.children.last_mut().unwrap()
          }
          _ => self,
       }
    }
 
-   ///Layout a box and its descendants.
-   fn layout(&mut self, cntin_blck: &Dimensions,) {
+   ///Is this an anonymous block of nothing but white space that collapses
+   /// away? Such blocks generate no lines (CSS 2.1 §9.2.2.1).
+   fn is_collapsible_space(&self,) -> bool {
+      fn collapses(layout_box: &LayoutBox,) -> bool {
+         match layout_box.box_type {
+            BoxType::InlineNode(styled,) => match &styled.node().node_type {
+               dom::NodeType::Text(text,) => {
+                  use properties::WhiteSpace::*;
+                  let space = |spaces: &[char]| text.chars().all(|c| spaces.contains(&c,),);
+                  match styled.computed().white_space {
+                     Normal | Nowrap => space(&[' ', '\t', '\n', '\r',],),
+                     PreLine => space(&[' ', '\t',],),
+                     Pre | PreWrap => false,
+                  }
+               }
+               dom::NodeType::Element(_,) => false,
+            },
+            _ => false,
+         }
+      }
+      matches!(self.box_type, BoxType::AnonymousBlock) && self.children.iter().all(collapses,)
+   }
+
+   ///Layout a box and its descendants below the content of its containing
+   /// block. 'cb_height' is the containing block's height if it doesn't depend
+   /// on its content, for percentage heights. 'strut' holds the margins above
+   /// the box that haven't been placed yet, and is left holding those below
+   /// it. 'floats' are those of the block formatting context the box is in.
+   /// Returns how the box joins its parent's content.
+   fn layout(
+      &mut self,
+      cntin_blck: &Dimensions,
+      cb_height: Option<f64,>,
+      fonts: &'a FontDatabase,
+      strut: &mut MarginStrut,
+      floats: &mut FloatContext,
+   ) -> Placement {
       match self.box_type {
-         BoxType::BlockNode(_,) => self.layout_block(cntin_blck,),
-         BoxType::InlineNode(_,) => {}
-         BoxType::AnonymousBlock => {}
+         BoxType::BlockNode(_,) => {
+            let bfc_root = self.establishes_bfc();
+            self.layout_block(cntin_blck, cb_height, fonts, strut, floats, bfc_root,)
+         }
+         //Inline boxes are laid out by the inline formatting context they are in.
+         BoxType::InlineNode(_,) => Placement::Placed,
+         BoxType::AnonymousBlock => self.layout_anonymous_block(cntin_blck, fonts, strut, floats,),
       }
    }
 
-   ///Block's width depends on its parent, height depends on its children
-   fn layout_block(&mut self, cntin_blck: &Dimensions,) {
+   ///Does the box establish a block formatting context for its contents, which
+   /// keeps their floats in and those outside out (CSS 2.1 §9.4.1)?
+   fn establishes_bfc(&self,) -> bool {
+      let style = self.get_style_node().computed();
+      let clips = |overflow| !matches!(overflow, properties::Overflow::Visible | properties::Overflow::Clip);
+      style.float != properties::Float::None
+         || style.display == style::Display::FlowRoot
+         || clips(style.overflow_x,)
+         || clips(style.overflow_y,)
+   }
+
+   ///Block's width depends on its parent, height depends on its children. The
+   /// top margin collapses with the first child's and the bottom margin with
+   /// the last child's, unless borders, padding, a specified height,
+   /// clearance, or 'bfc_root' (a box that establishes a block formatting
+   /// context) keep them apart. A block formatting context root is beside the
+   /// floats around it rather than under them, and grows to contain its own.
+   pub(crate) fn layout_block(
+      &mut self,
+      cntin_blck: &Dimensions,
+      cb_height: Option<f64,>,
+      fonts: &'a FontDatabase,
+      strut: &mut MarginStrut,
+      floats: &mut FloatContext,
+      bfc_root: bool,
+   ) -> Placement {
+      let cursor = cntin_blck.content.y + cntin_blck.content.height;
+      let beside_floats;
+      let cntin_blck = match bfc_root {
+         true => {
+            let cb = &cntin_blck.content;
+            let (left, right,) = floats.available(cursor + strut.collapsed(), 0.0, cb.x, cb.x + cb.width,);
+            let content = Rct { x: left, width: right - left, ..cb.clone() };
+            beside_floats = Dimensions { content, ..cntin_blck.clone() };
+            &beside_floats
+         }
+         false => cntin_blck,
+      };
       //Calculate parent's width at first
-      self.calc_width(&cntin_blck,);
+      self.calc_width(cntin_blck,);
       self.calc_position(cntin_blck,);
-      self.layout_children();
+      let height = self.specified_height(cb_height,);
+      let style = self.get_style_node().computed();
+      let d = &mut self.dimensions;
+      strut.add(d.margin.top,);
+      //Clearance puts the border box below the floats it clears, where the
+      // margins above it don't reach.
+      let clearance = floats.clearance(style.clear,).filter(|&below| below > cursor + strut.collapsed(),);
+      let collapse_top = !bfc_root && clearance.is_none() && d.border.top == 0.0 && d.padding.top == 0.0;
+      let collapse_bottom = !bfc_root && d.border.bottom == 0.0 && d.padding.bottom == 0.0 && height.is_none();
+      //The children's margins join the pending ones, until the first child
+      // with content places them and the top of the box with it.
+      let mut inner = MarginStrut::default();
+      d.content.height = 0.0;
+      if collapse_top {
+         d.content.y = cursor;
+         inner = std::mem::take(strut,);
+      } else {
+         d.content.y = clearance.unwrap_or(cursor + strut.collapsed(),) + d.border.top + d.padding.top;
+         *strut = MarginStrut::default();
+      }
+      let mut own_floats = FloatContext::default();
+      let floats = if bfc_root { &mut own_floats } else { floats };
+      let placed = self.layout_children(height, fonts, &mut inner, floats, !collapse_top,);
+      let d = &mut self.dimensions;
+      if let (Some(bottom,), true, None,) = (own_floats.bottom(), bfc_root, height,) {
+         d.content.height = d.content.height.max(bottom - d.content.y,);
+      }
+      if !placed {
+         if collapse_bottom {
+            //An empty box: its top and bottom margins and those of its children all collapse.
+            d.content.y = cursor + inner.collapsed();
+            *strut = inner;
+            strut.add(d.margin.bottom,);
+            return Placement::Empty;
+         }
+         d.content.y = cursor + inner.collapsed();
+         inner = MarginStrut::default();
+      }
+      if collapse_bottom {
+         //The last child's bottom margin collapses with this box's.
+         *strut = inner;
+      } else {
+         d.content.height += inner.collapsed();
+      }
+      strut.add(d.margin.bottom,);
       //Calculate parent's height at last
-      self.calc_height();
+      self.calc_height(height,);
+      if clearance.is_some() {
+         Placement::Cleared
+      } else {
+         Placement::Placed
+      }
+   }
+
+   ///An anonymous block fills the width of its containing block, has no
+   /// margins, borders or padding, and is as tall as its lines, which are
+   /// shortened around 'floats'. Without any lines, it's empty.
+   fn layout_anonymous_block(
+      &mut self,
+      cntin_blck: &Dimensions,
+      fonts: &'a FontDatabase,
+      strut: &mut MarginStrut,
+      floats: &FloatContext,
+   ) -> Placement {
+      let d = &mut self.dimensions;
+      d.content.x = cntin_blck.content.x;
+      d.content.y = cntin_blck.content.y + cntin_blck.content.height + strut.collapsed();
+      d.content.width = cntin_blck.content.width;
+      self.layout_inline(fonts, floats,);
+      let (x, y,) = (self.dimensions.content.x, self.dimensions.content.y,);
+      for child in &mut self.children {
+         child.set_static_position(x, y,);
+      }
+      if self.lines.is_empty() {
+         return Placement::Empty;
+      }
+      *strut = MarginStrut::default();
+      Placement::Placed
    }
 
    ///Calculate width of block
    fn calc_width(&mut self, cntin_blck: &Dimensions,) {
-      use css::{
-         Unit,
-         Value::{Keyword, Length},
-      };
-      let style = self.get_style_node();
+      use properties::Length::{self, Auto, Px};
+      let style = self.get_style_node().computed();
       //'width' has initial value 'auto'
-      let auto = Keyword("auto".to_string(),);
-      let mut width = style.val("width",).unwrap_or(auto.clone(),);
-
-      //margin, border, padding have init value 0.
-      let zero = Length(0.0, Unit::Px,);
+      let mut width = style.width.clone();
 
-      let mut margin_left = style.lookup("margin-left", "margin", &zero,);
-      let mut margin_right = style.lookup("margin-right", "margin", &zero,);
-      let border_left = style.lookup("border-left-width", "border-width", &zero,);
-      let border_right = style.lookup("border-right-width", "border-width", &zero,);
-      let padding_left = style.lookup("padding-left", "padding", &zero,);
-      let padding_right = style.lookup("padding-right", "padding", &zero,);
+      //Percentages refer to the width of the containing block.
+      let cb_width = cntin_blck.content.width;
+      let mut margin_left = style.margin.left.clone();
+      let mut margin_right = style.margin.right.clone();
+      let border_left = Px(style.border_width.left,);
+      let border_right = Px(style.border_width.right,);
+      let padding_left = style.padding.left.clone();
+      let padding_right = style.padding.right.clone();
 
       let total = [&margin_left, &margin_right, &border_left, &border_right, &padding_left, &padding_right, &width,]
          .iter()
-         .map(|v| v.to_px(),)
+         .map(|v| v.to_px(cb_width,),)
          .sum::<f64>();
       //if width!=auto & total is wider than container, treat auto margins as 0.
-      if width != auto && total > cntin_blck.content.width {
-         if margin_left == auto {
-            margin_left = Length(0.0, Unit::Px,);
+      if width != Auto && total > cb_width {
+         if margin_left == Auto {
+            margin_left = Px(0.0,);
          }
-         if margin_right == auto {
-            margin_right = Length(0.0, Unit::Px,);
+         if margin_right == Auto {
+            margin_right = Px(0.0,);
          }
       }
       //if 'flow' is +, it's underflow. 'flow' is -, it's overflow.
-      let flow = cntin_blck.content.width - total;
-      match (width == auto, margin_left == auto, margin_right == auto,) {
+      let flow = cb_width - total;
+      match (width == Auto, margin_left == Auto, margin_right == Auto,) {
          //If the values are overconstrained, calculate margin_right.
-         (false, false, false,) => margin_right = Length(margin_right.to_px() + flow, Unit::Px,),
+         (false, false, false,) => margin_right = Px(margin_right.to_px(cb_width,) + flow,),
          //If exactly one size is auto, its used value follows from the equality.
-         (false, false, true,) => margin_right = Length(flow, Unit::Px,),
-         (false, true, false,) => margin_left = Length(flow, Unit::Px,),
+         (false, false, true,) => margin_right = Px(flow,),
+         (false, true, false,) => margin_left = Px(flow,),
          //If width is set to auto, any other auto values become 0.
          (true, ..,) => {
-            if margin_left == auto {
-               margin_left = Length(0.0, Unit::Px,);
+            if margin_left == Auto {
+               margin_left = Px(0.0,);
             }
-            if margin_right == auto {
-               margin_right = Length(0.0, Unit::Px,);
+            if margin_right == Auto {
+               margin_right = Px(0.0,);
             }
             if flow >= 0.0 {
-               width = Length(flow, Unit::Px,);
+               width = Px(flow,);
             } else {
-               width = Length(0.0, Unit::Px,);
-               margin_right = Length(margin_right.to_px() + flow, Unit::Px,);
+               width = Px(0.0,);
+               margin_right = Px(margin_right.to_px(cb_width,) + flow,);
             }
          }
          //If margin_left and margin_right are both auto, their used values are equal.
          (false, true, true,) => {
-            margin_left = Length(flow / 2.0, Unit::Px,);
-            margin_right = Length(flow / 2.0, Unit::Px,);
+            margin_left = Px(flow / 2.0,);
+            margin_right = Px(flow / 2.0,);
          }
       }
+
+      let d = &mut self.dimensions;
+      d.content.width = width.to_px(cb_width,);
+      d.margin.left = margin_left.to_px(cb_width,);
+      d.margin.right = margin_right.to_px(cb_width,);
+      d.border.left = border_left.to_px(cb_width,);
+      d.border.right = border_right.to_px(cb_width,);
+      d.padding.left = padding_left.to_px(cb_width,);
+      d.padding.right = padding_right.to_px(cb_width,);
    }
 
+   ///Calculate the vertical margins, borders and padding, and the horizontal
+   /// position. The vertical position depends on the collapsing of margins.
    fn calc_position(&mut self, cntin_blck: &Dimensions,) {
-      let style = self.get_style_node();
+      let style = self.get_style_node().computed();
       let d = &mut self.dimensions;
-
-      //margin, border, and padding have init value 0.
-      let zero = css::Value::Length(0.0, css::Unit::Px,);
+      //Vertical margins and padding are percentages of the containing block's width too.
+      let cb_width = cntin_blck.content.width;
 
       //If margin_top or margin_bottom is 'auto', the used value is zero.
-      d.margin.top = style.lookup("margin-top", "margin", &zero,).to_px();
-      d.margin.bottom = style.lookup("margin-bottom", "margin", &zero,).to_px();
-      d.border.top = style.lookup("border-top-width", "border-width", &zero,).to_px();
-      d.border.bottom = style.lookup("border-bottom-width", "border-width", &zero,).to_px();
-      d.padding.top = style.lookup("padding-top", "padding", &zero,).to_px();
-      d.padding.bottom = style.lookup("padding-bottom", "padding", &zero,).to_px();
+      d.margin.top = style.margin.top.to_px(cb_width,);
+      d.margin.bottom = style.margin.bottom.to_px(cb_width,);
+      d.border.top = style.border_width.top;
+      d.border.bottom = style.border_width.bottom;
+      d.padding.top = style.padding.top.to_px(cb_width,);
+      d.padding.bottom = style.padding.bottom.to_px(cb_width,);
 
       d.content.x = cntin_blck.content.x + d.margin.left + d.border.left + d.padding.left;
-      d.content.y = cntin_blck.content.height + cntin_blck.content.y + d.margin.top + d.border.top + d.padding.top;
    }
 
-   fn layout_children(&mut self,) {
+   ///Lay out the children one below the other, with the margins between them
+   /// collapsed through 'strut'. Until 'placed', the top of the content isn't
+   /// fixed yet, and the first child with content fixes it. Floats are taken
+   /// out of the flow and added to 'floats'. Returns whether any child had
+   /// content.
+   fn layout_children(
+      &mut self,
+      height: Option<f64,>,
+      fonts: &'a FontDatabase,
+      strut: &mut MarginStrut,
+      floats: &mut FloatContext,
+      mut placed: bool,
+   ) -> bool {
       let d = &mut self.dimensions;
       for child in &mut self.children {
-         child.layout(d,);
+         if child.is_float() {
+            child.layout_float(d, height, fonts, strut, floats,);
+            continue;
+         }
+         if child.is_absolute() {
+            child.set_static_position(d.content.x, d.content.y + d.content.height + strut.collapsed(),);
+            continue;
+         }
+         //Where the content starts if the child's margin doesn't join the ones above it.
+         let top = d.content.y + strut.collapsed();
+         match child.layout(d, height, fonts, strut, floats,) {
+            Placement::Empty => continue,
+            Placement::Cleared if !placed => {
+               d.content.y = top;
+               placed = true;
+            }
+            _ => {}
+         }
+         let border_box = child.dimensions.border_box();
+         if !placed {
+            d.content.y = border_box.y;
+            placed = true;
+         }
          //Track the height so each child is laid out below the previous content.
-         d.content.height = d.content.height + child.dimensions.margin_box().height;
+         d.content.height = border_box.y + border_box.height - d.content.y;
+      }
+      placed
+   }
+
+   ///Is the box absolutely positioned, and so left to 'layout_positioned'?
+   pub(crate) fn is_absolute(&self,) -> bool {
+      match self.box_type {
+         BoxType::BlockNode(styled,) => styled.computed().position.is_absolute(),
+         _ => false,
+      }
+   }
+
+   ///Put the absolutely positioned boxes in or at this box, which is in the
+   /// flow at ('x', 'y'), there. Until positioned layout, they stay at that
+   /// static position, the top left of their margin box.
+   fn set_static_position(&mut self, x: f64, y: f64,) {
+      match self.box_type {
+         BoxType::BlockNode(_,) if self.is_absolute() => {
+            self.dimensions = Dimensions { content: Rct { x, y, width: 0.0, height: 0.0, }, ..Default::default() }
+         }
+         BoxType::InlineNode(_,) => {
+            for child in &mut self.children {
+               child.set_static_position(x, y,);
+            }
+         }
+         _ => {}
+      }
+   }
+
+   fn is_float(&self,) -> bool {
+      match self.box_type {
+         BoxType::BlockNode(styled,) => styled.computed().float != properties::Float::None,
+         _ => false,
+      }
+   }
+
+   ///Lay out a float, and place it in 'floats' at the top of the content of
+   /// its containing block so far, below the margins in 'strut'. An 'auto'
+   /// width shrinks to fit the content, within the containing block.
+   fn layout_float(
+      &mut self,
+      cntin_blck: &Dimensions,
+      cb_height: Option<f64,>,
+      fonts: &'a FontDatabase,
+      strut: &MarginStrut,
+      floats: &mut FloatContext,
+   ) {
+      let style = self.get_style_node().computed();
+      let cb = &cntin_blck.content;
+      //Lay it out on its own first, where the floats it will avoid don't narrow it.
+      let layout = |layout_box: &mut LayoutBox<'a,>, cntin_blck: &Dimensions| {
+         let (mut strut, mut outside,) = (MarginStrut::default(), FloatContext::default(),);
+         layout_box.layout_block(cntin_blck, cb_height, fonts, &mut strut, &mut outside, true,);
+      };
+      layout(self, cntin_blck,);
+      if style.width == properties::Length::Auto {
+         let d = &self.dimensions;
+         let edges = d.margin_box().width - d.content.width;
+         let fit = Dimensions {
+            content: Rct { width: self.content_extent().min(d.content.width,) + edges, ..cb.clone() },
+            ..cntin_blck.clone()
+         };
+         layout(self, &fit,);
+      }
+      //The margins don't stretch to fill the containing block, and 'auto' ones are 0.
+      let d = &mut self.dimensions;
+      d.margin.left = style.margin.left.to_px(cb.width,);
+      d.margin.right = style.margin.right.to_px(cb.width,);
+      let margin_box = d.margin_box();
+      let mut y = cb.y + cb.height + strut.collapsed();
+      if let Some(below,) = floats.clearance(style.clear,) {
+         y = y.max(below,);
+      }
+      let (x, y,) = floats.place(style.float, margin_box.width, margin_box.height, y, cb.x, cb.x + cb.width,);
+      self.translate(x - margin_box.x, y - margin_box.y,);
+   }
+
+   ///How wide the laid-out content is, from the left of the content box to
+   /// the right of the furthest line, child margin box, or float.
+   pub(crate) fn content_extent(&self,) -> f64 {
+      let d = &self.dimensions;
+      let lines = self.lines.iter().flat_map(|line| &line.fragments,).map(|f| f.rct.x + f.rct.width,);
+      let children = self.children.iter().map(|child| {
+         let c = &child.dimensions;
+         match child.box_type {
+            BoxType::AnonymousBlock => c.content.x + child.content_extent(),
+            BoxType::BlockNode(styled,) => {
+               let style = styled.computed();
+               let right = match style.width == properties::Length::Auto && !child.is_float() {
+                  true => c.content.x + child.content_extent() + c.padding.right + c.border.right,
+                  false => c.border_box().x + c.border_box().width,
+               };
+               right + style.margin.right.to_px(d.content.width,)
+            }
+            BoxType::InlineNode(_,) => d.content.x,
+         }
+      },);
+      lines.chain(children,).map(|right| right - d.content.x,).fold(0.0, f64::max,)
+   }
+
+   ///Move the box and everything in it by ('dx', 'dy').
+   pub(crate) fn translate(&mut self, dx: f64, dy: f64,) {
+      self.dimensions.content.x += dx;
+      self.dimensions.content.y += dy;
+      for line in &mut self.lines {
+         line.rct.x += dx;
+         line.rct.y += dy;
+         line.baseline += dy;
+         for fragment in &mut line.fragments {
+            fragment.rct.x += dx;
+            fragment.rct.y += dy;
+         }
+         for part in &mut line.boxes {
+            part.dimensions.content.x += dx;
+            part.dimensions.content.y += dy;
+         }
+      }
+      for child in &mut self.children {
+         child.translate(dx, dy,);
+      }
+   }
+
+   ///The used height if it doesn't depend on the content. Percentages refer to
+   /// the containing block's height, and act as 'auto' when that isn't known.
+   fn specified_height(&self, cb_height: Option<f64,>,) -> Option<f64,> {
+      match &self.get_style_node().computed().height {
+         properties::Length::Auto => None,
+         h if h.has_percentage() => cb_height.map(|cb| h.to_px(cb,),),
+         h => Some(h.to_px(0.0,),),
       }
    }
 
    ///If the height is set to an explicit lenght, use that exact lenght.
    /// Otherwise, just keep the value set by 'layout_block_children'.
-   fn calc_height(&mut self,) {
-      if let Some(css::Value::Length(h, css::Unit::Px,),) = self.get_style_node().val("height",) {
+   fn calc_height(&mut self, height: Option<f64,>,) {
+      if let Some(h,) = height {
          self.dimensions.content.height = h;
       }
    }
 }
 
 ///Build the tree of LayoutBoxes, but don't perform any layout calculations
-/// yet.
+/// yet. Consecutive inline children of a block container share one anonymous
+/// block, and inline boxes with blocks in them are split around the blocks.
 fn build_layout_tree<'a,>(style_node: &'a style::StyledNode<'a,>,) -> LayoutBox<'a,> {
    use {style::Display::*, BoxType::*};
    //Create the root box.
    let mut root = LayoutBox::new(match style_node.display() {
-      Block => BlockNode(style_node,),
+      Block | FlowRoot => BlockNode(style_node,),
       Inline => InlineNode(style_node,),
       Non => panic!("Root node has display: none."),
    },);
    //Create the descendant boxes.
    for child in &style_node.children {
-      root = match child.display() {
-         Block | Inline => {
-            let mut cl = root.clone();
-            let mut ret = cl.get_inline_container();
-            ret.children.push(build_layout_tree(&child,),);
-            ret
+      match (&root.box_type, child.display(),) {
+         (_, Non,) => {}
+         (BlockNode(_,), Inline,) => {
+            for part in split_inline(build_layout_tree(child,),) {
+               match part.box_type {
+                  BlockNode(_,) => root.children.push(part,),
+                  _ => root.get_inline_container().children.push(part,),
+               }
+            }
          }
-         _ => root,
-      };
+         //An absolutely positioned box takes no room in the flow, so it doesn't end the inline content it's in.
+         (BlockNode(_,), _,)
+            if child.computed().position.is_absolute()
+               && matches!(root.children.last(), Some(LayoutBox { box_type: AnonymousBlock, .. })) =>
+         {
+            root.get_inline_container().children.push(build_layout_tree(child,),)
+         }
+         //Blocks in inline boxes stay there until the enclosing block container splits them out.
+         _ => root.children.push(build_layout_tree(child,),),
+      }
    }
+   root.children.retain(|child| !child.is_collapsible_space(),);
    root
 }
 
-///Transform a style tree into a layout tree
-pub fn layout_tree<'a,>(node: &'a style::StyledNode<'a,>, mut cntin_blck: Dimensions,) -> LayoutBox<'a,> {
+///Break an inline box around the blocks in it (CSS 2.1 §9.2.1.1). Returns the
+/// blocks, and between them pieces of the inline box holding the inline
+/// content around them. Pieces with no content are left out.
+fn split_inline(inline: LayoutBox,) -> Vec<LayoutBox,> {
+   if !inline.children.iter().any(has_block,) {
+      return vec![inline];
+   }
+   let piece = || LayoutBox::new(inline.box_type.clone(),);
+   let mut parts = vec![piece()];
+   for child in inline.children {
+      let child_parts = match child.box_type {
+         BoxType::InlineNode(_,) => split_inline(child,),
+         _ => vec![child],
+      };
+      for part in child_parts {
+         match part.box_type {
+            BoxType::BlockNode(_,) => parts.extend([part, piece(),],),
+            _ => parts.last_mut().unwrap().children.push(part,),
+         }
+      }
+   }
+   parts.retain(|part| !matches!(part.box_type, BoxType::InlineNode(_)) || !part.children.is_empty(),);
+   parts
+}
+
+///Does an inline box have blocks in the flow in it, at any depth?
+fn has_block(layout_box: &LayoutBox,) -> bool {
+   match layout_box.box_type {
+      BoxType::BlockNode(_,) => !layout_box.is_absolute(),
+      _ => layout_box.children.iter().any(has_block,),
+   }
+}
+
+///The box tree, one box per line indented by depth, as 'block <p>',
+/// 'inline "text"' or 'anonymous'.
+impl fmt::Display for LayoutBox<'_,> {
+   fn fmt(&self, f: &mut fmt::Formatter,) -> fmt::Result {
+      let depth = f.width().unwrap_or(0,);
+      let node = |styled: &style::StyledNode| match &styled.node().node_type {
+         dom::NodeType::Element(elem,) => format!("<{}>", elem.tag_name),
+         dom::NodeType::Text(text,) => format!("{text:?}"),
+      };
+      match self.box_type {
+         BoxType::BlockNode(styled,) => writeln!(f, "{:depth$}block {}", "", node(styled),)?,
+         BoxType::InlineNode(styled,) => writeln!(f, "{:depth$}inline {}", "", node(styled),)?,
+         BoxType::AnonymousBlock => writeln!(f, "{:depth$}anonymous", "",)?,
+      }
+      for child in &self.children {
+         write!(f, "{:1$}", child, depth + 2,)?;
+      }
+      Ok((),)
+   }
+}
+
+///Transform a style tree into a layout tree. Text is measured with the faces
+/// in 'fonts'. The whole tree is laid out again each time, whatever
+/// 'StyledNode::changed' says: a caller that restyled without changing any
+/// node's style, or the shape of the DOM, can keep its old layout instead.
+pub fn layout_tree<'a,>(node: &'a style::StyledNode<'a,>, cntin_blck: Dimensions, fonts: &'a FontDatabase,) -> LayoutBox<'a,> {
+   layout_tree_scrolled(node, cntin_blck, (0.0, 0.0,), fonts,)
+}
+
+///Transform a style tree into a layout tree, with the viewport 'cntin_blck'
+/// scrolled by 'scroll' from the top left of the document. Fixed boxes are
+/// placed in the scrolled viewport, and sticky boxes kept in it.
+pub fn layout_tree_scrolled<'a,>(
+   node: &'a style::StyledNode<'a,>,
+   mut cntin_blck: Dimensions,
+   scroll: (f64, f64,),
+   fonts: &'a FontDatabase,
+) -> LayoutBox<'a,> {
+   //Boxes with no positioned ancestor are placed in the initial containing
+   // block, which is the viewport at the start of the document.
+   let initial = cntin_blck.content.clone();
+   let viewport = Rct { x: initial.x + scroll.0, y: initial.y + scroll.1, ..initial.clone() };
+   //Percentage heights of the root refer to the viewport.
+   let viewport_height = cntin_blck.content.height;
    //The layout algorithm expects the container height to start at 0
    cntin_blck.content.height = 0.0;
 
    let mut root_box = build_layout_tree(node,);
-   root_box.layout(&cntin_blck,);
+   let (mut strut, mut floats,) = (MarginStrut::default(), FloatContext::default(),);
+   match root_box.box_type {
+      //The root establishes a block formatting context, so its margins don't collapse with its children's.
+      BoxType::BlockNode(_,) => root_box.layout_block(&cntin_blck, Some(viewport_height,), fonts, &mut strut, &mut floats, true,),
+      _ => root_box.layout(&cntin_blck, Some(viewport_height,), fonts, &mut strut, &mut floats,),
+   };
+   positioned::layout_positioned(&mut root_box, &initial, &viewport, fonts,);
    root_box
 }
+
+///Content sizes of the laid-out boxes with a 'container-type', for evaluating
+/// '@container' rules in a second styling pass.
+pub fn container_sizes(root: &LayoutBox,) -> style::ContainerSizes {
+   let mut sizes = style::ContainerSizes::new();
+   collect_container_sizes(root, &mut sizes,);
+   sizes
+}
+
+fn collect_container_sizes(layout_box: &LayoutBox, sizes: &mut style::ContainerSizes,) {
+   if let BoxType::BlockNode(nod,) | BoxType::InlineNode(nod,) = layout_box.box_type {
+      if nod.computed().container_type != properties::ContainerType::Normal {
+         let content = &layout_box.dimensions.content;
+         sizes.insert(nod.node().id(), (content.width, content.height,),);
+      }
+   }
+   for child in &layout_box.children {
+      collect_container_sizes(child, sizes,);
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use {
+      super::*,
+      crate::{css, html, media},
+      std::collections::HashMap,
+   };
+
+   ///The border boxes of the elements with an id in the body 'src', laid out
+   /// with 'sheet' in an 800 by 600 viewport.
+   fn border_boxes(src: &str, sheet: &str,) -> HashMap<String, Rct,> { scrolled_border_boxes(src, sheet, (0.0, 0.0,),) }
+
+   ///'border_boxes' with the viewport scrolled by 'scroll'.
+   fn scrolled_border_boxes(src: &str, sheet: &str, scroll: (f64, f64,),) -> HashMap<String, Rct,> {
+      fn collect(layout_box: &LayoutBox, out: &mut HashMap<String, Rct,>,) {
+         if let BoxType::BlockNode(styled,) = layout_box.box_type {
+            if let dom::NodeType::Element(ref elem,) = styled.node().node_type {
+               if let Some(id,) = elem.id() {
+                  out.insert(id.clone(), layout_box.dimensions.border_box(),);
+               }
+            }
+         }
+         layout_box.children.iter().for_each(|child| collect(child, out,),);
+      }
+      let root = html::parse(format!("<html><body>{src}</body></html>"),);
+      let sheets = [css::parse(format!("body {{ margin: 0 }} {sheet}"), css::Origin::Author,),];
+      let mut viewport = Dimensions::default();
+      viewport.content.width = 800.0;
+      viewport.content.height = 600.0;
+      let env = media::MediaEnvironment::new(&viewport,);
+      let styled = style::style_tree(&root, &sheets, &env, &Default::default(), style::Traversal::Sequential,);
+      let fonts = FontDatabase::new();
+      let mut out = HashMap::new();
+      collect(&layout_tree_scrolled(&styled, viewport, scroll, &fonts,), &mut out,);
+      out
+   }
+
+   ///The left edge, top and width of each line in the body 'src', laid out with
+   /// 'sheet' in an 800 by 600 viewport.
+   fn lines(src: &str, sheet: &str,) -> Vec<(f64, f64, f64,),> {
+      fn collect(layout_box: &LayoutBox, out: &mut Vec<(f64, f64, f64,),>,) {
+         out.extend(layout_box.lines.iter().map(|line| (line.rct.x, line.rct.y, line.rct.width,),),);
+         layout_box.children.iter().for_each(|child| collect(child, out,),);
+      }
+      let root = html::parse(format!("<html><body>{src}</body></html>"),);
+      let sheets = [css::parse(format!("body, p {{ margin: 0 }} {sheet}"), css::Origin::Author,),];
+      let mut viewport = Dimensions::default();
+      viewport.content.width = 800.0;
+      viewport.content.height = 600.0;
+      let env = media::MediaEnvironment::new(&viewport,);
+      let styled = style::style_tree(&root, &sheets, &env, &Default::default(), style::Traversal::Sequential,);
+      let fonts = FontDatabase::new();
+      let mut out = vec![];
+      collect(&layout_tree(&styled, viewport, &fonts,), &mut out,);
+      out
+   }
+
+   ///The top and height of the border box of each of 'ids'.
+   fn tops(src: &str, sheet: &str, ids: &[&str],) -> Vec<(f64, f64,),> {
+      let boxes = border_boxes(src, sheet,);
+      ids.iter().map(|id| (boxes[*id].y, boxes[*id].height,),).collect()
+   }
+
+   ///The box tree of the document 'src', built without layout.
+   fn box_tree(src: &str,) -> String {
+      let root = html::parse(src.to_string(),);
+      let env = media::MediaEnvironment::new(&Dimensions::default(),);
+      let styled = style::style_tree(&root, &[], &env, &Default::default(), style::Traversal::Sequential,);
+      build_layout_tree(&styled,).to_string()
+   }
+
+   #[test]
+   fn inline_run_between_blocks_shares_one_anonymous_block() {
+      assert_eq!(
+         box_tree("<div><p>a</p>b <em>c</em><p>d</p></div>",),
+         concat!(
+            "block <div>\n",
+            "  block <p>\n",
+            "    anonymous\n",
+            "      inline \"a\"\n",
+            "  anonymous\n",
+            "    inline \"b \"\n",
+            "    inline <em>\n",
+            "      inline \"c\"\n",
+            "  block <p>\n",
+            "    anonymous\n",
+            "      inline \"d\"\n",
+         ),
+      );
+   }
+
+   #[test]
+   fn block_in_inline_splits_the_inline() {
+      assert_eq!(
+         box_tree("<div><span>a<p>b</p>c</span></div>",),
+         concat!(
+            "block <div>\n",
+            "  anonymous\n",
+            "    inline <span>\n",
+            "      inline \"a\"\n",
+            "  block <p>\n",
+            "    anonymous\n",
+            "      inline \"b\"\n",
+            "  anonymous\n",
+            "    inline <span>\n",
+            "      inline \"c\"\n",
+         ),
+      );
+   }
+
+   #[test]
+   fn whitespace_between_blocks_makes_no_box() {
+      assert_eq!(
+         box_tree("<div><p>a</p> \n <p>b</p></div>",),
+         concat!(
+            "block <div>\n",
+            "  block <p>\n",
+            "    anonymous\n",
+            "      inline \"a\"\n",
+            "  block <p>\n",
+            "    anonymous\n",
+            "      inline \"b\"\n",
+         ),
+      );
+   }
+
+   #[test]
+   fn sibling_margins_collapse() {
+      let src = "<div id=\"a\"></div><div id=\"b\"></div>";
+      let tops = |sheet: &str| tops(src, &format!("div {{ height: 10px }} {sheet}"), &["a", "b",],);
+      assert_eq!(tops("#a { margin-bottom: 20px } #b { margin-top: 30px }",), [(0.0, 10.0), (40.0, 10.0)]);
+      //The most negative margin is taken from the largest positive one.
+      assert_eq!(tops("#a { margin-bottom: 20px } #b { margin-top: -5px }",), [(0.0, 10.0), (25.0, 10.0)]);
+      assert_eq!(tops("#a { margin: 0 0 -5px } #b { margin-top: -10px }",), [(0.0, 10.0), (0.0, 10.0)]);
+   }
+
+   #[test]
+   fn first_child_margin_collapses_with_its_parent() {
+      let src = "<div id=\"p\"><div id=\"c\"></div></div>";
+      let tops = |sheet: &str| {
+         tops(src, &format!("#p {{ margin-top: 10px }} #c {{ margin-top: 30px; height: 10px }} {sheet}"), &["p", "c",],)
+      };
+      assert_eq!(tops("",), [(30.0, 10.0), (30.0, 10.0)]);
+      //Padding, a border or a block formatting context keep them apart.
+      assert_eq!(tops("#p { padding-top: 1px }",), [(10.0, 41.0), (41.0, 10.0)]);
+      assert_eq!(tops("#p { border-top: 1px solid }",), [(10.0, 41.0), (41.0, 10.0)]);
+      assert_eq!(tops("#p { overflow: hidden }",), [(10.0, 40.0), (40.0, 10.0)]);
+      assert_eq!(tops("#p { display: flow-root }",), [(10.0, 40.0), (40.0, 10.0)]);
+   }
+
+   #[test]
+   fn last_child_margin_collapses_with_its_parent() {
+      let src = "<div id=\"p\"><div id=\"c\"></div></div><div id=\"s\"></div>";
+      let tops = |sheet: &str| {
+         tops(src, &format!("#c {{ margin-bottom: 30px; height: 10px }} #s {{ height: 5px }} {sheet}"), &["p", "s",],)
+      };
+      assert_eq!(tops("#p { margin-bottom: 10px }",), [(0.0, 10.0), (40.0, 5.0)]);
+      assert_eq!(tops("#p { padding-bottom: 1px }",), [(0.0, 41.0), (41.0, 5.0)]);
+      //With a height, the parent's bottom margin no longer adjoins its child's.
+      assert_eq!(tops("#p { height: 20px }",), [(0.0, 20.0), (20.0, 5.0)]);
+   }
+
+   #[test]
+   fn margins_collapse_through_empty_blocks() {
+      let src = "<div id=\"a\"></div><div id=\"e\"></div><div id=\"b\"></div>";
+      let sheet = "#a, #b { height: 10px } #a { margin-bottom: 10px } #e { margin: 20px 0 15px } #b { margin-top: 5px }";
+      assert_eq!(tops(src, sheet, &["a", "b"],), [(0.0, 10.0), (30.0, 10.0)]);
+      //Padding makes it not empty.
+      let sheet = format!("{sheet} #e {{ padding-top: 1px }}");
+      assert_eq!(tops(src, &sheet, &["a", "e", "b"],), [(0.0, 10.0), (30.0, 1.0), (46.0, 10.0)]);
+   }
+
+   #[test]
+   fn clearance_stops_margins_collapsing() {
+      let src = "<div id=\"p\"><div id=\"f\"></div><div id=\"c\"></div></div>";
+      let sheet = |margin: &str| {
+         format!("#f {{ float: left; width: 10px; height: 50px }} #c {{ clear: left; height: 10px; margin-top: {margin} }}")
+      };
+      //The cleared box is put below the float, and its parent starts above it.
+      assert_eq!(tops(src, &sheet("10px",), &["p", "f", "c"],), [(0.0, 60.0), (0.0, 50.0), (50.0, 10.0)]);
+      //A margin that already puts it below the float needs no clearance.
+      assert_eq!(tops(src, &sheet("70px",), &["p", "c"],), [(70.0, 10.0), (70.0, 10.0)]);
+   }
+
+   ///The left edge, top and width of the border box of each of 'ids'.
+   fn places(src: &str, sheet: &str, ids: &[&str],) -> Vec<(f64, f64, f64,),> {
+      let boxes = border_boxes(src, sheet,);
+      ids.iter().map(|id| (boxes[*id].x, boxes[*id].y, boxes[*id].width,),).collect()
+   }
+
+   #[test]
+   fn floats_go_to_their_side_and_down_when_full() {
+      let src = "<div id=\"a\"></div><div id=\"b\"></div><div id=\"r\"></div><div id=\"c\"></div>";
+      let sheet = "div { height: 50px; width: 300px; float: left } #r { float: right; width: 150px } #c { height: 20px }";
+      assert_eq!(places(src, sheet, &["a", "b", "r", "c"],), [
+         (0.0, 0.0, 300.0),
+         (300.0, 0.0, 300.0),
+         (650.0, 0.0, 150.0),
+         (0.0, 50.0, 300.0)
+      ]);
+      //A float with 'auto' width shrinks to fit its content.
+      assert_eq!(
+         places("<div id=\"f\"><div id=\"i\"></div></div>", "#f { float: right } #i { width: 40px; height: 5px }", &["f"],),
+         [(760.0, 0.0, 40.0)]
+      );
+   }
+
+   #[test]
+   fn lines_are_shortened_beside_floats() {
+      let src = "<div id=\"f\"></div><p>a a a a a a a a</p>";
+      let lines =
+         lines(src, "#f { float: left; width: 100px; height: 30px } p { width: 110px; font-size: 10px; line-height: 20px }",);
+      //The first two lines are beside the float, the rest under it.
+      assert_eq!(lines[..3], [(100.0, 0.0, 10.0), (100.0, 20.0, 10.0), (0.0, 40.0, 110.0)]);
+   }
+
+   #[test]
+   fn line_that_fits_nowhere_beside_floats_moves_below_them() {
+      let src = "<div id=\"f\"></div><p>aaaaaaaa</p>";
+      let lines = lines(src, "#f { float: left; width: 780px; height: 30px } p { font-size: 10px; line-height: 20px }",);
+      assert_eq!(lines, [(0.0, 30.0, 800.0)]);
+   }
+
+   #[test]
+   fn clear_moves_below_floats_of_its_side() {
+      let src = "<div id=\"f\"></div><div id=\"c\"></div>";
+      let sheet = |clear: &str| format!("#f {{ float: left; width: 10px; height: 50px }} #c {{ height: 10px; clear: {clear} }}");
+      assert_eq!(tops(src, &sheet("left",), &["c"],), [(50.0, 10.0)]);
+      assert_eq!(tops(src, &sheet("both",), &["c"],), [(50.0, 10.0)]);
+      assert_eq!(tops(src, &sheet("right",), &["c"],), [(0.0, 10.0)]);
+   }
+
+   #[test]
+   fn block_formatting_context_roots_contain_their_floats() {
+      let src = "<div id=\"p\"><div id=\"f\"></div></div><div id=\"n\"></div>";
+      let sheet = |root: &str| format!("#f {{ float: left; width: 10px; height: 50px }} #n {{ height: 10px }} {root}");
+      assert_eq!(tops(src, &sheet("#p { overflow: hidden }",), &["p", "n"],), [(0.0, 50.0), (50.0, 10.0)]);
+      assert_eq!(tops(src, &sheet("#p { display: flow-root }",), &["p", "n"],), [(0.0, 50.0), (50.0, 10.0)]);
+      //Otherwise the float hangs out of its parent.
+      assert_eq!(tops(src, &sheet("",), &["p", "n"],), [(0.0, 0.0), (0.0, 10.0)]);
+   }
+
+   #[test]
+   fn block_formatting_context_roots_go_beside_floats() {
+      let src = "<div id=\"f\"></div><div id=\"b\"></div><div id=\"n\"></div>";
+      let sheet = "#f { float: left; width: 100px; height: 50px } #b { overflow: hidden; height: 10px } #n { height: 10px }";
+      assert_eq!(places(src, sheet, &["b", "n"],), [(100.0, 0.0, 700.0), (0.0, 10.0, 800.0)]);
+   }
+
+   ///The border box of each of 'ids', as its left edge, top, width and height.
+   fn rects(boxes: &HashMap<String, Rct,>, ids: &[&str],) -> Vec<(f64, f64, f64, f64,),> {
+      ids.iter().map(|id| (boxes[*id].x, boxes[*id].y, boxes[*id].width, boxes[*id].height,),).collect()
+   }
+
+   #[test]
+   fn relative_boxes_are_offset_from_where_they_were() {
+      let src = "<div id=\"r\"></div><div id=\"n\"></div>";
+      let sheet = |offsets: &str| format!("div {{ height: 10px }} #r {{ position: relative; {offsets} }}");
+      let boxes = border_boxes(src, &sheet("top: 5px; left: 20px",),);
+      //The boxes after it stay where they were.
+      assert_eq!(rects(&boxes, &["r", "n"],), [(20.0, 5.0, 800.0, 10.0), (0.0, 10.0, 800.0, 10.0)]);
+      let boxes = border_boxes(src, &sheet("bottom: 5px; right: 20px",),);
+      assert_eq!(rects(&boxes, &["r"],), [(-20.0, -5.0, 800.0, 10.0)]);
+      //'top' wins over 'bottom', and 'left' over 'right'.
+      let boxes = border_boxes(src, &sheet("top: 1px; bottom: 5px; left: 2px; right: 20px",),);
+      assert_eq!(rects(&boxes, &["r"],), [(2.0, 1.0, 800.0, 10.0)]);
+   }
+
+   #[test]
+   fn absolute_boxes_are_placed_in_the_padding_box_of_their_positioned_ancestor() {
+      let src = "<div id=\"s\"></div><div id=\"p\"><div><div id=\"a\"></div></div></div>";
+      let sheet = |p: &str| {
+         format!(
+            "#s {{ height: 50px }} #p {{ {p}; margin-left: 30px; padding: 10px; border: 5px solid; width: 200px; height: 100px }} \
+             #a {{ position: absolute; top: 0; right: 0; width: 10px; height: 10px }}"
+         )
+      };
+      let boxes = border_boxes(src, &sheet("position: relative",),);
+      assert_eq!(rects(&boxes, &["a"],), [(245.0, 55.0, 10.0, 10.0)]);
+      //Without one, the initial containing block.
+      let boxes = border_boxes(src, &sheet("position: static",),);
+      assert_eq!(rects(&boxes, &["a"],), [(790.0, 0.0, 10.0, 10.0)]);
+      //They don't take up room in the flow.
+      assert_eq!(boxes["p"].height, 130.0);
+   }
+
+   #[test]
+   fn fixed_boxes_are_placed_in_the_viewport() {
+      let src = "<div id=\"s\"></div><div id=\"p\"><div id=\"f\"></div></div>";
+      let sheet = "#s { height: 1000px } #p { position: relative } #f { position: fixed; bottom: 0; left: 10px; width: 20px; height: 10px }";
+      assert_eq!(rects(&border_boxes(src, sheet,), &["f"],), [(10.0, 590.0, 20.0, 10.0)]);
+      assert_eq!(rects(&scrolled_border_boxes(src, sheet, (5.0, 300.0,),), &["f"],), [(15.0, 890.0, 20.0, 10.0)]);
+   }
+
+   #[test]
+   fn sticky_boxes_stay_in_the_scrolled_viewport_and_their_containing_block() {
+      let src = "<div id=\"s\"></div><div id=\"p\"><div id=\"k\"></div></div><div id=\"e\"></div>";
+      let sheet =
+         "#s { height: 100px } #p { height: 200px } #k { position: sticky; top: 5px; height: 10px } #e { height: 2000px }";
+      let top = |scroll| scrolled_border_boxes(src, sheet, (0.0, scroll,),)["k"].y;
+      //In the flow until the viewport's top comes within 'top' of it, then stuck.
+      assert_eq!([0.0, 90.0, 150.0, 280.0, 400.0].map(top,), [100.0, 100.0, 155.0, 285.0, 290.0]);
+      //It keeps its place in the flow.
+      assert_eq!(scrolled_border_boxes(src, sheet, (0.0, 150.0,),)["e"].y, 300.0);
+   }
+
+   #[test]
+   fn over_constrained_absolute_width_ignores_the_end_inset() {
+      let src = "<div id=\"a\"></div>";
+      let sheet = |more: &str| {
+         format!("#a {{ position: absolute; left: 10px; right: 10px; width: 100px; height: 10px; margin: 0 5px; {more} }}")
+      };
+      assert_eq!(rects(&border_boxes(src, &sheet("",),), &["a"],), [(15.0, 0.0, 100.0, 10.0)]);
+      //Right to left, 'left' is the one ignored.
+      assert_eq!(rects(&border_boxes(src, &sheet("direction: rtl",),), &["a"],), [(685.0, 0.0, 100.0, 10.0)]);
+      //Auto margins share what's left.
+      assert_eq!(rects(&border_boxes(src, &sheet("margin: 0 auto",),), &["a"],), [(350.0, 0.0, 100.0, 10.0)]);
+      //An auto width fills it.
+      let sheet = "#a { position: absolute; left: 10px; right: 30px; height: 10px }";
+      assert_eq!(rects(&border_boxes(src, sheet,), &["a"],), [(10.0, 0.0, 760.0, 10.0)]);
+   }
+
+   #[test]
+   fn over_constrained_absolute_height_ignores_bottom() {
+      let src = "<div id=\"a\"></div>";
+      let sheet = |more: &str| format!("#a {{ position: absolute; top: 10px; bottom: 10px; width: 10px; {more} }}");
+      assert_eq!(rects(&border_boxes(src, &sheet("height: 100px",),), &["a"],), [(0.0, 10.0, 10.0, 100.0)]);
+      assert_eq!(rects(&border_boxes(src, &sheet("",),), &["a"],), [(0.0, 10.0, 10.0, 580.0)]);
+      assert_eq!(rects(&border_boxes(src, &sheet("height: 100px; margin: auto 0",),), &["a"],), [(0.0, 250.0, 10.0, 100.0)]);
+   }
+}
@@ -1,50 +1,6 @@
-mod css;
-mod dom;
-mod html;
-mod layout;
-mod painting;
-mod style;
-
 use std::fs;
 
-use image;
-
-struct Parser {
-   pos: usize,
-   inp: String,
-}
-
-impl Parser {
-   ///Read the current character without consuming it.
-   pub fn next_char(&self,) -> char { self.inp[self.pos..].chars().next().unwrap() }
-
-   ///Do the next characters start with the given string?
-   pub fn starts_with(&self, s: &str,) -> bool { self.inp[self.pos..].starts_with(s,) }
-
-   ///Return true if all input is consumed.
-   pub fn eof(&self,) -> bool { self.pos >= self.inp.len() }
-
-   ///Return the current character, and advance self.pos to the next character.
-   pub fn cnsm_chr(&mut self,) -> char {
-      let mut itr = self.inp[self.pos..].char_indices();
-      let (_, cur_chr,) = itr.next().unwrap();
-      let (next_pos, _,) = itr.next().unwrap_or((1, ' ',),);
-      self.pos += next_pos;
-      cur_chr
-   }
-
-   ///Consume characters until 'test' returns false.
-   pub fn cnsm_while(&mut self, test: impl Fn(char,) -> bool,) -> String {
-      let mut rslt = String::new();
-      while !self.eof() && test(self.next_char(),) {
-         rslt.push(self.cnsm_chr(),);
-      }
-      rslt
-   }
-
-   ///Consume and discard zero or more whitespace characters.
-   pub fn cnsm_whitespace(&mut self,) { self.cnsm_while(char::is_whitespace,); }
-}
+use br_eng::{css, font, html, layout, media, painting, resource, style};
 
 fn main() {
    //read input files
@@ -56,9 +12,27 @@ fn main() {
    viewport.content.height = 600.0;
    //Parse and rendering
    let root_node = html::parse(html_file,);
-   let stylesheet = css::parse(css_file,);
-   let style_root = style::style_tree(&root_node, &stylesheet,);
-   let layout_root = layout::layout_tree(&style_root, viewport.clone(),);
+   let stylesheets = [css::parse(css_file, css::Origin::Author,),];
+   let env = media::MediaEnvironment::new(&viewport,);
+   //Fonts text can be drawn with: installed fonts, and those of '@font-face' rules
+   let loader = resource::FileLoader { base: "examples".into(), font_dirs: resource::system_font_dirs(), };
+   let mut fonts = font::FontDatabase::new();
+   fonts.add_installed_fonts(&loader,);
+   fonts.add_stylesheets(&stylesheets, &env, &loader,);
+   //'@container' rules depend on the layout of their containers, so style and
+   // lay out again until the container sizes settle. Without size containment
+   // they may never settle, so stop after a few passes.
+   let mut containers = style::ContainerSizes::new();
+   let mut style_root = style::style_tree(&root_node, &stylesheets, &env, &containers, style::Traversal::Sequential,);
+   for _ in 0..4 {
+      let sizes = layout::container_sizes(&layout::layout_tree(&style_root, viewport.clone(), &fonts,),);
+      if sizes == containers {
+         break;
+      }
+      containers = sizes;
+      style_root = style::style_tree(&root_node, &stylesheets, &env, &containers, style::Traversal::Sequential,);
+   }
+   let layout_root = layout::layout_tree(&style_root, viewport.clone(), &fonts,);
    //Create output file
    let board = painting::paint(&layout_root, viewport.content,);
    let (w, h,) = (board.width as u32, board.height as u32,);
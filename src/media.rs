@@ -0,0 +1,697 @@
+#![allow(unused)]
+use std::fmt;
+
+use crate::{layout, properties::MEDIUM_FONT_SIZE, Parser};
+
+///The device a page is rendered for, which '@media' rules are evaluated against.
+#[derive(Clone,)]
+pub struct MediaEnvironment {
+   ///Size of the viewport in px.
+   pub width:        f64,
+   pub height:       f64,
+   ///Device pixels per px.
+   pub resolution:   f64,
+   pub color_scheme: ColorScheme,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq,)]
+pub enum ColorScheme {
+   Light,
+   Dark,
+}
+
+impl MediaEnvironment {
+   ///A light-themed screen at one device pixel per px, the size of 'viewport'.
+   pub fn new(viewport: &layout::Dimensions,) -> MediaEnvironment {
+      MediaEnvironment {
+         width:        viewport.content.width,
+         height:       viewport.content.height,
+         resolution:   1.0,
+         color_scheme: ColorScheme::Light,
+      }
+   }
+}
+
+///A comma-separated list of media queries, which matches if any of them does,
+/// or if it's empty.
+pub struct MediaQueryList(pub Vec<MediaQuery,>,);
+
+///'[not|only]? <media-type> [and <condition>]?' or '<condition>'.
+pub struct MediaQuery {
+   pub negated:    bool,
+   ///'None' stands for 'all'.
+   pub media_type: Option<String,>,
+   pub condition:  Option<MediaCondition,>,
+}
+
+pub enum MediaCondition {
+   Feature(MediaFeature,),
+   Not(Box<MediaCondition,>,),
+   And(Vec<MediaCondition,>,),
+   Or(Vec<MediaCondition,>,),
+   ///Something in parentheses that isn't a known feature, kept as written
+   /// without the parentheses. It is neither true nor false.
+   Unknown(String,),
+}
+
+///A test of one media feature, e.g. '(min-width: 600px)' or '(400px < width <= 800px)'.
+pub struct MediaFeature {
+   pub name:        String,
+   ///Comparisons with the feature's value on the left. A feature without any,
+   /// like '(orientation)', tests whether the feature is non-zero or not 'none'.
+   pub comparisons: Vec<(Comparison, MediaValue,),>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq,)]
+pub enum Comparison {
+   Lt,
+   Le,
+   Eq,
+   Ge,
+   Gt,
+}
+
+impl Comparison {
+   ///The comparison with its operands swapped, e.g. '<' for '>'.
+   fn flip(self,) -> Comparison {
+      match self {
+         Comparison::Lt => Comparison::Gt,
+         Comparison::Le => Comparison::Ge,
+         Comparison::Eq => Comparison::Eq,
+         Comparison::Ge => Comparison::Le,
+         Comparison::Gt => Comparison::Lt,
+      }
+   }
+
+   fn test(self, a: f64, b: f64,) -> bool {
+      match self {
+         Comparison::Lt => a < b,
+         Comparison::Le => a <= b,
+         Comparison::Eq => a == b,
+         Comparison::Ge => a >= b,
+         Comparison::Gt => a > b,
+      }
+   }
+}
+
+///The value a feature is compared with. Lengths are in px and resolutions in dppx.
+#[derive(Clone, PartialEq,)]
+pub enum MediaValue {
+   Length(f64,),
+   Resolution(f64,),
+   Number(f64,),
+   Ident(String,),
+}
+
+impl MediaQueryList {
+   pub fn matches(&self, env: &MediaEnvironment,) -> bool { self.0.is_empty() || self.0.iter().any(|query| query.matches(env,),) }
+}
+
+impl MediaQuery {
+   ///'not all', which an invalid query becomes.
+   fn not_all() -> MediaQuery { MediaQuery { negated: true, media_type: Some("all".to_string(),), condition: None, } }
+
+   pub fn matches(&self, env: &MediaEnvironment,) -> bool {
+      let type_matches = matches!(self.media_type.as_deref(), None | Some("all" | "screen"));
+      let matches = match self.condition {
+         _ if !type_matches => Some(false,),
+         Some(ref c,) => c.eval(&|nam| env.feature(nam,),),
+         None => Some(true,),
+      };
+      //An unknown result counts as false, even under 'not'.
+      matches.map(|b| b != self.negated,).unwrap_or(false,)
+   }
+}
+
+impl MediaEnvironment {
+   fn feature(&self, nam: &str,) -> Option<Feature,> {
+      match nam {
+         "width" => Some(Feature::Length(self.width,),),
+         "height" => Some(Feature::Length(self.height,),),
+         "resolution" => Some(Feature::Resolution(self.resolution,),),
+         "orientation" => Some(Feature::Keyword(orientation(self.width, self.height,),),),
+         "prefers-color-scheme" => Some(Feature::Keyword(match self.color_scheme {
+            ColorScheme::Light => "light",
+            ColorScheme::Dark => "dark",
+         },),),
+         _ => None,
+      }
+   }
+}
+
+fn orientation(width: f64, height: f64,) -> &'static str {
+   if height >= width {
+      "portrait"
+   } else {
+      "landscape"
+   }
+}
+
+impl fmt::Display for MediaQueryList {
+   fn fmt(&self, f: &mut fmt::Formatter,) -> fmt::Result {
+      for (i, query,) in self.0.iter().enumerate() {
+         if i > 0 {
+            f.write_str(", ",)?;
+         }
+         write!(f, "{query}")?;
+      }
+      Ok((),)
+   }
+}
+
+impl fmt::Display for MediaQuery {
+   fn fmt(&self, f: &mut fmt::Formatter,) -> fmt::Result {
+      if self.negated {
+         f.write_str("not ",)?;
+      }
+      match (&self.media_type, &self.condition,) {
+         //'or' needs parentheses after a media type.
+         (Some(ty,), Some(c @ MediaCondition::Or(_,),),) => write!(f, "{ty} and ({c})"),
+         (Some(ty,), Some(c,),) => write!(f, "{ty} and {c}"),
+         (Some(ty,), None,) => f.write_str(ty,),
+         (None, Some(c,),) => write!(f, "{c}"),
+         (None, None,) => f.write_str("all",),
+      }
+   }
+}
+
+impl fmt::Display for MediaCondition {
+   fn fmt(&self, f: &mut fmt::Formatter,) -> fmt::Result {
+      match self {
+         MediaCondition::Feature(feature,) => write!(f, "({feature})"),
+         MediaCondition::Not(c,) => {
+            f.write_str("not ",)?;
+            c.fmt_in_parens(f,)
+         }
+         MediaCondition::And(cs,) | MediaCondition::Or(cs,) => {
+            let joiner = if matches!(self, MediaCondition::And(_)) { " and " } else { " or " };
+            for (i, c,) in cs.iter().enumerate() {
+               if i > 0 {
+                  f.write_str(joiner,)?;
+               }
+               c.fmt_in_parens(f,)?;
+            }
+            Ok((),)
+         }
+         MediaCondition::Unknown(css,) => write!(f, "({css})"),
+      }
+   }
+}
+
+impl MediaCondition {
+   ///Write the condition as an operand of 'not', 'and' or 'or'.
+   fn fmt_in_parens(&self, f: &mut fmt::Formatter,) -> fmt::Result {
+      match self {
+         MediaCondition::Not(_,) | MediaCondition::And(_,) | MediaCondition::Or(_,) => write!(f, "({self})"),
+         _ => write!(f, "{self}"),
+      }
+   }
+}
+
+impl fmt::Display for MediaFeature {
+   ///'min-' and 'max-' features are written in range syntax, e.g. 'width >= 600px'.
+   fn fmt(&self, f: &mut fmt::Formatter,) -> fmt::Result {
+      match &self.comparisons[..] {
+         [] => f.write_str(&self.name,),
+         [(Comparison::Eq, val,)] => write!(f, "{}: {val}", self.name),
+         [(cmp, val,)] => write!(f, "{} {cmp} {val}", self.name),
+         [(first, lo,), (second, hi,), ..] => write!(f, "{lo} {} {} {second} {hi}", first.flip(), self.name),
+      }
+   }
+}
+
+impl fmt::Display for Comparison {
+   fn fmt(&self, f: &mut fmt::Formatter,) -> fmt::Result {
+      f.write_str(match self {
+         Comparison::Lt => "<",
+         Comparison::Le => "<=",
+         Comparison::Eq => "=",
+         Comparison::Ge => ">=",
+         Comparison::Gt => ">",
+      },)
+   }
+}
+
+impl fmt::Display for MediaValue {
+   fn fmt(&self, f: &mut fmt::Formatter,) -> fmt::Result {
+      match self {
+         MediaValue::Length(px,) => write!(f, "{px}px"),
+         MediaValue::Resolution(dppx,) => write!(f, "{dppx}dppx"),
+         MediaValue::Number(n,) => write!(f, "{n}"),
+         MediaValue::Ident(kw,) => f.write_str(kw,),
+      }
+   }
+}
+
+///The value of a feature where a condition is evaluated.
+enum Feature {
+   Length(f64,),
+   Resolution(f64,),
+   Keyword(&'static str,),
+}
+
+impl MediaCondition {
+   ///Evaluate with three-valued logic, where 'None' is unknown. 'feature' looks
+   /// up the value of a feature by name.
+   fn eval(&self, feature: &dyn Fn(&str,) -> Option<Feature,>,) -> Option<bool,> {
+      match self {
+         MediaCondition::Feature(f,) => f.eval(feature(&f.name,)?,),
+         MediaCondition::Not(c,) => c.eval(feature,).map(|b| !b,),
+         MediaCondition::And(cs,) => {
+            let results: Vec<_,> = cs.iter().map(|c| c.eval(feature,),).collect();
+            if results.contains(&Some(false,),) {
+               Some(false,)
+            } else if results.contains(&None,) {
+               None
+            } else {
+               Some(true,)
+            }
+         }
+         MediaCondition::Or(cs,) => {
+            let results: Vec<_,> = cs.iter().map(|c| c.eval(feature,),).collect();
+            if results.contains(&Some(true,),) {
+               Some(true,)
+            } else if results.contains(&None,) {
+               None
+            } else {
+               Some(false,)
+            }
+         }
+         MediaCondition::Unknown(_,) => None,
+      }
+   }
+
+   ///Evaluate as a container query against a container's content size. A size
+   /// is 'None' if the container doesn't allow querying it, or isn't laid out yet.
+   pub fn matches_container(&self, width: Option<f64,>, height: Option<f64,>,) -> bool {
+      let feature = |nam: &str| match nam {
+         "width" | "inline-size" => width.map(Feature::Length,),
+         "height" | "block-size" => height.map(Feature::Length,),
+         "orientation" => Some(Feature::Keyword(orientation(width?, height?,),),),
+         _ => None,
+      };
+      self.eval(&feature,) == Some(true,)
+   }
+}
+
+impl MediaFeature {
+   ///'None' for values of the wrong type.
+   fn eval(&self, actual: Feature,) -> Option<bool,> {
+      match actual {
+         Feature::Length(px,) => self.compare(px, |v| matches!(v, MediaValue::Length(_)),),
+         Feature::Resolution(dppx,) => self.compare(dppx, |v| matches!(v, MediaValue::Resolution(_)),),
+         Feature::Keyword(kw,) => self.keyword(kw,),
+      }
+   }
+
+   ///Compare a numeric feature with every value, all of which must pass 'valid'.
+   fn compare(&self, actual: f64, valid: fn(&MediaValue,) -> bool,) -> Option<bool,> {
+      if self.comparisons.is_empty() {
+         return Some(actual != 0.0,);
+      }
+      let mut result = true;
+      for (cmp, val,) in &self.comparisons {
+         let (MediaValue::Length(x,) | MediaValue::Resolution(x,) | MediaValue::Number(x,)) = *val else {
+            return None;
+         };
+         if !valid(val,) && *val != MediaValue::Number(0.0,) {
+            return None;
+         }
+         result &= cmp.test(actual, x,);
+      }
+      Some(result,)
+   }
+
+   ///Test a discrete feature, which only allows '(name: keyword)' and '(name)'.
+   fn keyword(&self, actual: &str,) -> Option<bool,> {
+      match &self.comparisons[..] {
+         [] => Some(true,),
+         [(Comparison::Eq, MediaValue::Ident(kw,),)] => Some(*kw == actual,),
+         _ => None,
+      }
+   }
+}
+
+impl Parser {
+   ///Parse a media query list up to the '{' of an '@media' rule, or a ';' or
+   /// '}' that ends it early. An invalid query becomes 'not all', and parsing
+   /// goes on after the next ',' (Media Queries §3.2).
+   pub fn parse_media_query_list(&mut self,) -> MediaQueryList {
+      let mut queries = vec![];
+      self.cnsm_whitespace();
+      if self.eof() || matches!(self.next_char(), '{' | ';' | '}') {
+         return MediaQueryList(queries,);
+      }
+      loop {
+         self.cnsm_whitespace();
+         let query = self.parse_media_query().filter(|_| {
+            self.cnsm_whitespace();
+            self.eof() || matches!(self.next_char(), ',' | '{' | ';' | '}')
+         },);
+         queries.push(query.unwrap_or_else(|| {
+            self.skip_media_query();
+            MediaQuery::not_all()
+         },),);
+         if self.eof() || self.next_char() != ',' {
+            break;
+         }
+         self.cnsm_chr();
+      }
+      MediaQueryList(queries,)
+   }
+
+   ///Parse one query of a list. 'None' if it's invalid.
+   fn parse_media_query(&mut self,) -> Option<MediaQuery,> {
+      if self.eof() {
+         return None;
+      }
+      let not_condition = self.starts_with_keyword("not",) && self.inp[self.pos + 3..].trim_start().starts_with('(',);
+      if self.next_char() == '(' || not_condition {
+         return Some(MediaQuery { negated: false, media_type: None, condition: Some(self.parse_media_condition(true,)?,), },);
+      }
+      let mut kw = self.parse_media_keyword();
+      let negated = kw == "not";
+      if negated || kw == "only" {
+         self.cnsm_whitespace();
+         kw = self.parse_media_keyword();
+      }
+      if matches!(&*kw, "" | "not" | "only" | "and" | "or") {
+         return None;
+      }
+      self.cnsm_whitespace();
+      let mut condition = None;
+      if self.starts_with_keyword("and",) {
+         self.parse_media_keyword();
+         self.cnsm_whitespace();
+         //'or' isn't allowed after a media type without parentheses.
+         condition = Some(self.parse_media_condition(false,)?,);
+      }
+      Some(MediaQuery { negated, media_type: Some(kw,), condition, },)
+   }
+
+   ///Skip the rest of an invalid query, up to the ',' after it, or the end of
+   /// the list.
+   fn skip_media_query(&mut self,) {
+      let mut depth = 0;
+      while !self.eof() {
+         match self.next_char() {
+            ',' if depth == 0 => return,
+            '{' | ';' | '}' => return,
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            _ => {}
+         }
+         self.cnsm_chr();
+      }
+   }
+
+   ///Parse 'not <in-parens>' or '<in-parens>' joined by all 'and' or all 'or'.
+   /// 'None' if it's invalid, e.g. mixes 'and' and 'or'.
+   pub fn parse_media_condition(&mut self, allow_or: bool,) -> Option<MediaCondition,> {
+      if self.starts_with_keyword("not",) {
+         self.parse_media_keyword();
+         self.cnsm_whitespace();
+         return Some(MediaCondition::Not(Box::new(self.parse_media_in_parens()?,),),);
+      }
+      let mut conditions = vec![self.parse_media_in_parens()?];
+      let mut joiner = None;
+      loop {
+         let start = self.pos;
+         self.cnsm_whitespace();
+         let kw = if self.starts_with_keyword("and",) {
+            "and"
+         } else if self.starts_with_keyword("or",) {
+            "or"
+         } else {
+            self.pos = start;
+            break;
+         };
+         if kw == "or" && !allow_or {
+            self.pos = start;
+            break;
+         }
+         if joiner.is_some_and(|j| j != kw,) {
+            return None;
+         }
+         joiner = Some(kw,);
+         self.parse_media_keyword();
+         self.cnsm_whitespace();
+         conditions.push(self.parse_media_in_parens()?,);
+      }
+      match joiner {
+         None => conditions.pop(),
+         Some("and",) => Some(MediaCondition::And(conditions,),),
+         Some(_,) => Some(MediaCondition::Or(conditions,),),
+      }
+   }
+
+   ///Parse '(<condition>)', '(<feature>)', or any other parenthesized text as
+   /// unknown. 'None' if there's no '(' or it isn't closed.
+   fn parse_media_in_parens(&mut self,) -> Option<MediaCondition,> {
+      if self.eof() || self.next_char() != '(' {
+         return None;
+      }
+      self.cnsm_chr();
+      self.cnsm_whitespace();
+      let start = self.pos;
+      let condition = match !self.eof() && (self.next_char() == '(' || self.starts_with_keyword("not",)) {
+         true => self.parse_media_condition(true,),
+         false => self.parse_media_feature().map(MediaCondition::Feature,),
+      };
+      self.cnsm_whitespace();
+      let condition = match condition {
+         Some(condition,) if !self.eof() && self.next_char() == ')' => condition,
+         _ => {
+            self.pos = start;
+            if !self.skip_media_block() {
+               return None;
+            }
+            MediaCondition::Unknown(self.inp[start..self.pos].trim_end().to_string(),)
+         }
+      };
+      self.cnsm_chr();
+      Some(condition,)
+   }
+
+   ///Parse '<name>', '<name>: <value>', or the range forms '<name> <op> <value>',
+   /// '<value> <op> <name>' and '<value> <op> <name> <op> <value>'. 'min-' and
+   /// 'max-' prefixes become range comparisons.
+   fn parse_media_feature(&mut self,) -> Option<MediaFeature,> {
+      let first = self.parse_media_value()?;
+      self.cnsm_whitespace();
+      if self.eof() {
+         return None;
+      }
+      if let MediaValue::Ident(nam,) = first {
+         let nam = nam.to_ascii_lowercase();
+         if self.next_char() == ')' {
+            return Some(MediaFeature { name: nam, comparisons: vec![], },);
+         }
+         if self.next_char() == ':' {
+            self.cnsm_chr();
+            self.cnsm_whitespace();
+            let val = self.parse_media_value()?;
+            let (cmp, name,) = match nam.strip_prefix("min-",) {
+               Some(name,) => (Comparison::Ge, name,),
+               None => match nam.strip_prefix("max-",) {
+                  Some(name,) => (Comparison::Le, name,),
+                  None => (Comparison::Eq, &*nam,),
+               },
+            };
+            return Some(MediaFeature { name: name.to_string(), comparisons: vec![(cmp, val,)], },);
+         }
+         let cmp = self.parse_comparison()?;
+         self.cnsm_whitespace();
+         let val = self.parse_media_value()?;
+         return Some(MediaFeature { name: nam, comparisons: vec![(cmp, val,)], },);
+      }
+      //'<value> <op> <name> [<op> <value>]?'
+      let cmp = self.parse_comparison()?;
+      self.cnsm_whitespace();
+      let Some(MediaValue::Ident(nam,),) = self.parse_media_value() else { return None };
+      let mut comparisons = vec![(cmp.flip(), first,)];
+      self.cnsm_whitespace();
+      if !self.eof() && self.next_char() != ')' {
+         let second = self.parse_comparison()?;
+         //Both operators must point the same way, as in 'a < width <= b'.
+         let same_way = matches!(
+            (cmp, second),
+            (Comparison::Lt | Comparison::Le, Comparison::Lt | Comparison::Le)
+               | (Comparison::Gt | Comparison::Ge, Comparison::Gt | Comparison::Ge)
+         );
+         if !same_way {
+            return None;
+         }
+         self.cnsm_whitespace();
+         comparisons.push((second, self.parse_media_value()?,),);
+      }
+      Some(MediaFeature { name: nam.to_ascii_lowercase(), comparisons, },)
+   }
+
+   fn parse_comparison(&mut self,) -> Option<Comparison,> {
+      if self.eof() {
+         return None;
+      }
+      let cmp = match self.cnsm_chr() {
+         '=' => return Some(Comparison::Eq,),
+         '<' => Comparison::Lt,
+         '>' => Comparison::Gt,
+         _ => return None,
+      };
+      if !self.eof() && self.next_char() == '=' {
+         self.cnsm_chr();
+         return Some(if cmp == Comparison::Lt { Comparison::Le } else { Comparison::Ge },);
+      }
+      Some(cmp,)
+   }
+
+   ///Parse an identifier, or a number with an optional length or resolution unit.
+   fn parse_media_value(&mut self,) -> Option<MediaValue,> {
+      if self.eof() {
+         return None;
+      }
+      let c = self.next_char();
+      if c.is_ascii_digit() || c == '.' || c == '-' && self.inp[self.pos + 1..].starts_with(|c: char| c.is_ascii_digit(),) {
+         let num = self.cnsm_while(|c| c.is_ascii_digit() || c == '.' || c == '-',).parse::<f64>().ok()?;
+         let unit = self.parse_media_keyword();
+         return match &*unit {
+            "" => Some(MediaValue::Number(num,),),
+            "px" => Some(MediaValue::Length(num,),),
+            "pt" => Some(MediaValue::Length(num * 4.0 / 3.0,),),
+            //Relative units in media queries refer to the initial font size.
+            "em" | "rem" => Some(MediaValue::Length(num * MEDIUM_FONT_SIZE,),),
+            "dppx" | "x" => Some(MediaValue::Resolution(num,),),
+            "dpi" => Some(MediaValue::Resolution(num / 96.0,),),
+            "dpcm" => Some(MediaValue::Resolution(num * 2.54 / 96.0,),),
+            _ => None,
+         };
+      }
+      let kw = self.parse_media_keyword();
+      (!kw.is_empty()).then(|| MediaValue::Ident(kw.to_ascii_lowercase(),),)
+   }
+
+   pub fn parse_media_keyword(&mut self,) -> String {
+      self.cnsm_while(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_',).to_ascii_lowercase()
+   }
+
+   ///Does the input start with the keyword 'kw', case-insensitively and not
+   /// followed by more of an identifier?
+   pub fn starts_with_keyword(&self, kw: &str,) -> bool {
+      let rest = &self.inp[self.pos..];
+      rest.get(..kw.len(),).is_some_and(|start| start.eq_ignore_ascii_case(kw,),)
+         && !rest[kw.len()..].starts_with(|c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_',)
+   }
+
+   ///Skip to the ')' closing the current parenthesized block. Returns false if
+   /// it isn't closed before the end of the query.
+   fn skip_media_block(&mut self,) -> bool {
+      let mut depth = 0;
+      while !self.eof() {
+         match self.next_char() {
+            '(' => depth += 1,
+            ')' if depth == 0 => return true,
+            ')' => depth -= 1,
+            '{' | ';' | '}' => return false,
+            _ => {}
+         }
+         self.cnsm_chr();
+      }
+      false
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use {
+      super::*,
+      crate::css::{self, CssRule},
+   };
+
+   ///The query list 'src', as in '@media <src> {'.
+   fn queries(src: &str,) -> MediaQueryList { Parser { pos: 0, inp: format!("{src} {{"), }.parse_media_query_list() }
+
+   ///Which queries of the list 'src' are 'not all', as invalid queries become.
+   fn not_all(src: &str,) -> Vec<bool,> {
+      let is_not_all = |q: &MediaQuery| q.negated && q.media_type.as_deref() == Some("all",) && q.condition.is_none();
+      queries(src,).0.iter().map(is_not_all,).collect()
+   }
+
+   ///An 800 by 600 light screen at 1dppx.
+   fn screen() -> MediaEnvironment {
+      MediaEnvironment { width: 800.0, height: 600.0, resolution: 1.0, color_scheme: ColorScheme::Light, }
+   }
+
+   #[test]
+   fn valid_queries_parse() {
+      assert_eq!(not_all("screen, not print, only screen and (orientation: landscape)"), [false, false, false]);
+      assert_eq!(not_all("(width >= 600px), (400px < width <= 800px), (min-width: 40em)"), [false, false, false]);
+      assert_eq!(not_all("(hover) and (not (width > 1px)), screen and ((width < 1px) or (height > 1px))"), [false, false]);
+      assert_eq!(not_all("(width: 1px 2px), (foo: (bar, baz))"), [false, false]);
+      assert!(queries("").0.is_empty());
+   }
+
+   #[test]
+   fn malformed_queries_are_not_all() {
+      assert_eq!(not_all("screen, "), [false, true]);
+      assert_eq!(not_all("(a) and (b) or (c)"), [true]);
+      assert_eq!(not_all("ñot all"), [true]);
+      assert_eq!(not_all("screen and (width) or (height), print"), [true, false]);
+      assert_eq!(not_all("and, not, only screen"), [true, true, false]);
+      assert_eq!(not_all("(width >= 600px"), [true]);
+      assert_eq!(not_all("screen print, (width) (height)"), [true, true]);
+   }
+
+   #[test]
+   fn malformed_media_rules_never_apply() {
+      for src in
+         ["@media print; p { color: red }", "@media (width >= 600px { p {} } p { color: red }", "@media", "@media ñot all {}",]
+      {
+         let sheet = css::parse(src.to_string(), css::Origin::Author,);
+         for rule in &sheet.rules {
+            if let CssRule::Media(media,) = rule {
+               assert!(!media.queries.matches(&screen(),), "{src}");
+            }
+         }
+      }
+      let sheet = css::parse("@media print; p { color: red }".to_string(), css::Origin::Author,);
+      assert!(matches!(sheet.rules[..], [CssRule::Style(_)]));
+   }
+
+   #[test]
+   fn queries_match_the_environment() {
+      let env = screen();
+      let matches = |src: &str| queries(src,).matches(&env,);
+      assert!(matches("all") && matches("screen") && matches("only screen") && !matches("print"));
+      assert!(matches("not print") && !matches("not screen"));
+      assert!(matches("(min-width: 800px)") && !matches("(min-width: 801px)"));
+      assert!(matches("(400px < width <= 800px)") && !matches("(width < 800px)"));
+      assert!(matches("(orientation: landscape)") && !matches("(orientation: portrait)"));
+      assert!(matches("(min-width: 50em)") && !matches("(max-height: 30em)"));
+      assert!(matches("(width < 1px) or (height = 600px)"));
+      assert!(matches("print, (width)") && matches(""));
+      assert!(!matches("not all") && !matches("print, ") && matches("screen, "));
+   }
+
+   #[test]
+   fn unknown_features_are_false_even_negated() {
+      let env = screen();
+      assert!(!queries("(hover)",).matches(&env,));
+      assert!(!queries("not (hover)",).matches(&env,));
+      assert!(!queries("(width: red)",).matches(&env,));
+      assert!(queries("(hover) or (width)",).matches(&env,));
+   }
+
+   #[test]
+   fn resolution_and_color_scheme() {
+      let dark = MediaEnvironment { resolution: 2.0, color_scheme: ColorScheme::Dark, ..screen() };
+      for (src, light, dark_hidpi,) in [
+         ("(prefers-color-scheme: dark)", false, true,),
+         ("(prefers-color-scheme: light)", true, false,),
+         ("(min-resolution: 2dppx)", false, true,),
+         ("(resolution: 96dpi)", true, false,),
+      ] {
+         assert_eq!(queries(src,).matches(&screen(),), light, "{src}");
+         assert_eq!(queries(src,).matches(&dark,), dark_hidpi, "{src}");
+      }
+   }
+}
@@ -1,12 +1,80 @@
 #![allow(unused)]
-use crate::{css, layout};
+use crate::{
+   css, dom,
+   inline::LineBox,
+   layout::{self, BoxType, Dimensions, LayoutBox, Rct},
+   properties::{ComputedValues, Float, Position},
+   style::StyledNode,
+   text::{self, Glyph},
+   transform::{self, Matrix},
+};
 
-type DisplayList = Vec<DisplayCommand,>;
+type DisplayList<'f,> = Vec<DisplayCommand<'f,>,>;
 
 ///Commands to display layout
-enum DisplayCommand {
+enum DisplayCommand<'f,> {
    SolidColor(css::Color, layout::Rct,),
-   //insert more commands here
+   ///Glyphs of one font size and color, with the position of the baseline they
+   /// are placed from.
+   Text {
+      glyphs:   Vec<Glyph<'f,>,>,
+      x:        f64,
+      baseline: f64,
+      size:     f64,
+      color:    css::Color,
+   },
+   ///A stacking context painted on a layer of its own, then composited with
+   /// 'opacity' through 'transform'.
+   Layer {
+      commands:  DisplayList<'f,>,
+      opacity:   f64,
+      transform: Option<Matrix,>,
+   },
+}
+
+impl DisplayCommand<'_,> {
+   ///The area the command paints, before the transform of the layer it's in.
+   /// 'None' if it paints nothing.
+   fn bounds(&self,) -> Option<Rct,> {
+      match self {
+         DisplayCommand::SolidColor(_, rct,) => Some(rct.clone(),),
+         DisplayCommand::Text { glyphs, x, baseline, size, .. } => glyphs
+            .iter()
+            .filter_map(
+               |glyph| text::glyph_bounds(glyph.face, glyph.id, glyph.synthesis, *size, x + glyph.x, baseline + glyph.y,),
+            )
+            .reduce(|a, b| union(&a, &b,),),
+         DisplayCommand::Layer { commands, transform, .. } => {
+            let bounds = list_bounds(commands,)?;
+            Some(match transform {
+               Some(m,) => transformed(&bounds, m,),
+               None => bounds,
+            },)
+         }
+      }
+   }
+}
+
+///The area the commands of 'list' paint. 'None' if they paint nothing.
+fn list_bounds(list: &[DisplayCommand],) -> Option<Rct,> {
+   list.iter().filter_map(DisplayCommand::bounds,).reduce(|a, b| union(&a, &b,),)
+}
+
+///The smallest rectangle around 'a' and 'b'.
+fn union(a: &Rct, b: &Rct,) -> Rct {
+   let (x, y,) = (a.x.min(b.x,), a.y.min(b.y,),);
+   let (right, bottom,) = ((a.x + a.width).max(b.x + b.width,), (a.y + a.height).max(b.y + b.height,),);
+   Rct { x, y, width: right - x, height: bottom - y, }
+}
+
+///The smallest rectangle around 'rct' mapped through 'm'.
+fn transformed(rct: &Rct, m: &Matrix,) -> Rct {
+   let corners =
+      [(rct.x, rct.y,), (rct.x + rct.width, rct.y,), (rct.x, rct.y + rct.height,), (rct.x + rct.width, rct.y + rct.height,),];
+   let points: Vec<(f64, f64,),> = corners.iter().map(|&(x, y,)| m.apply(x, y,),).collect();
+   let (x, y,) = points.iter().fold((f64::INFINITY, f64::INFINITY,), |(x, y,), p| (x.min(p.0,), y.min(p.1,),),);
+   let (right, bottom,) = points.iter().fold((f64::NEG_INFINITY, f64::NEG_INFINITY,), |(r, b,), p| (r.max(p.0,), b.max(p.1,),),);
+   Rct { x, y, width: right - x, height: bottom - y, }
 }
 
 ///Store pixels which is converted from DisplayCommand
@@ -14,112 +82,425 @@ pub struct Canvas {
    pub pixels: Vec<css::Color,>,
    pub width:  usize,
    pub height: usize,
+   ///Position of the top left pixel.
+   pub left:   i64,
+   pub top:    i64,
 }
 
 impl Canvas {
    ///Constructor. Create a blank Canvas
    fn new(width: usize, height: usize,) -> Canvas {
       let white = css::Color { r: 255, g: 255, b: 255, a: 255, };
-      Canvas { pixels: vec![white; height * width], width, height, }
+      Canvas { pixels: vec![white; height * width], width, height, left: 0, top: 0, }
+   }
+
+   ///A transparent canvas over the whole pixels 'bounds' covers, for a layer.
+   fn layer(bounds: &Rct,) -> Canvas {
+      let clear = css::Color { r: 0, g: 0, b: 0, a: 0, };
+      let (left, top,) = (bounds.x.floor(), bounds.y.floor(),);
+      let width = ((bounds.x + bounds.width).ceil() - left).max(0.0,) as usize;
+      let height = ((bounds.y + bounds.height).ceil() - top).max(0.0,) as usize;
+      Canvas { pixels: vec![clear; height * width], width, height, left: left as i64, top: top as i64, }
+   }
+
+   ///The area of the canvas.
+   fn bounds(&self,) -> Rct {
+      Rct { x: self.left as f64, y: self.top as f64, width: self.width as f64, height: self.height as f64, }
    }
 
-   ///Here, we just paint a rectangler
    fn paint_item(&mut self, item: &DisplayCommand,) {
-      match &item {
-         &DisplayCommand::SolidColor(color, rct,) => {
-            let x0 = rct.x.clamp(0.0, self.width as f64,) as usize;
-            let y0 = rct.y.clamp(0.0, self.height as f64,) as usize;
-            let x1 = (rct.x + rct.width).clamp(0.0, self.width as f64,) as usize;
-            let y1 = (rct.y + rct.height).clamp(0.0, self.height as f64,) as usize;
-            for y in y0..y1 {
-               for x in x0..x1 {
-                  self.pixels[x + y * self.width] = color.clone();
+      match item {
+         DisplayCommand::SolidColor(color, rct,) => self.paint_rect(color, rct,),
+         DisplayCommand::Text { glyphs, x, baseline, size, color, } => {
+            for glyph in glyphs {
+               if let Some(bitmap,) =
+                  text::rasterize(glyph.face, glyph.id, glyph.synthesis, *size, x + glyph.x, baseline + glyph.y,)
+               {
+                  self.paint_glyph(&bitmap, color,);
                }
             }
          }
-         _ => {}
+         DisplayCommand::Layer { commands, opacity, transform, } => {
+            let Some(mut bounds,) = list_bounds(commands,) else { return };
+            //Without a transform, only what falls on the canvas can be seen.
+            if transform.is_none() {
+               bounds = intersection(&bounds, &self.bounds(),);
+            }
+            let mut layer = Canvas::layer(&bounds,);
+            for item in commands {
+               layer.paint_item(item,);
+            }
+            self.composite(&layer, *opacity as f32, transform.as_ref(),);
+         }
+      }
+   }
+
+   ///Here, we just paint a rectangler
+   fn paint_rect(&mut self, color: &css::Color, rct: &layout::Rct,) {
+      let (x, y,) = (rct.x - self.left as f64, rct.y - self.top as f64,);
+      let x0 = x.clamp(0.0, self.width as f64,) as usize;
+      let y0 = y.clamp(0.0, self.height as f64,) as usize;
+      let x1 = (x + rct.width).clamp(0.0, self.width as f64,) as usize;
+      let y1 = (y + rct.height).clamp(0.0, self.height as f64,) as usize;
+      for y in y0..y1 {
+         for x in x0..x1 {
+            blend(&mut self.pixels[x + y * self.width], color, 1.0,);
+         }
+      }
+   }
+
+   ///Composite 'color' over the pixels a glyph covers, in proportion to its coverage.
+   fn paint_glyph(&mut self, bitmap: &text::GlyphBitmap, color: &css::Color,) {
+      for row in 0..bitmap.height {
+         for col in 0..bitmap.width {
+            let (x, y,) = (bitmap.left + col as i64 - self.left, bitmap.top + row as i64 - self.top,);
+            if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+               continue;
+            }
+            let coverage = bitmap.coverage[row * bitmap.width + col];
+            blend(&mut self.pixels[x as usize + y as usize * self.width], color, coverage,);
+         }
+      }
+   }
+
+   ///Composite 'layer' over the canvas with 'opacity', mapping each pixel of
+   /// the layer through 'transform'. Each pixel of the canvas the layer falls
+   /// on takes the layer pixel its center maps back to.
+   fn composite(&mut self, layer: &Canvas, opacity: f32, transform: Option<&Matrix,>,) {
+      let (m, inverse,) = match transform {
+         Some(m,) => match m.inverse() {
+            Some(inverse,) => (*m, inverse,),
+            //A transform that flattens the layer leaves nothing to see.
+            None => return,
+         },
+         None => (Matrix::IDENTITY, Matrix::IDENTITY,),
+      };
+      let area = intersection(&transformed(&layer.bounds(), &m,), &self.bounds(),);
+      let x0 = (area.x.floor() as i64 - self.left).max(0,) as usize;
+      let y0 = (area.y.floor() as i64 - self.top).max(0,) as usize;
+      let x1 = ((area.x + area.width).ceil() as i64 - self.left).clamp(0, self.width as i64,) as usize;
+      let y1 = ((area.y + area.height).ceil() as i64 - self.top).clamp(0, self.height as i64,) as usize;
+      for y in y0..y1 {
+         for x in x0..x1 {
+            let page = |px: usize, origin: i64| (px as i64 + origin) as f64 + 0.5;
+            let (lx, ly,) = inverse.apply(page(x, self.left,), page(y, self.top,),);
+            let (lx, ly,) = ((lx - layer.left as f64).floor(), (ly - layer.top as f64).floor(),);
+            if lx < 0.0 || ly < 0.0 || lx >= layer.width as f64 || ly >= layer.height as f64 {
+               continue;
+            }
+            let src = &layer.pixels[lx as usize + ly as usize * layer.width];
+            blend(&mut self.pixels[x + y * self.width], src, opacity,);
+         }
       }
    }
 }
 
-///Constructor of DisplayList. With init, draw empty layout box
-fn build_display_list(layout_root: &layout::LayoutBox,) -> DisplayList {
+///The area 'a' and 'b' share, empty if they don't meet.
+fn intersection(a: &Rct, b: &Rct,) -> Rct {
+   let (x, y,) = (a.x.max(b.x,), a.y.max(b.y,),);
+   let (right, bottom,) = ((a.x + a.width).min(b.x + b.width,), (a.y + a.height).min(b.y + b.height,),);
+   Rct { x, y, width: (right - x).max(0.0,), height: (bottom - y).max(0.0,), }
+}
+
+///Composite 'src' over 'dst', the alpha of 'src' scaled by 'coverage'.
+fn blend(dst: &mut css::Color, src: &css::Color, coverage: f32,) {
+   let src_alpha = src.a as f32 / 255.0 * coverage;
+   let dst_alpha = dst.a as f32 / 255.0 * (1.0 - src_alpha);
+   let alpha = src_alpha + dst_alpha;
+   if alpha == 0.0 {
+      return;
+   }
+   let over = |src: u8, dst: u8| ((src as f32 * src_alpha + dst as f32 * dst_alpha) / alpha).round() as u8;
+   *dst =
+      css::Color { r: over(src.r, dst.r,), g: over(src.g, dst.g,), b: over(src.b, dst.b,), a: (alpha * 255.0).round() as u8, };
+}
+
+///Constructor of DisplayList. The root element is the root stacking context.
+fn build_display_list<'f,>(layout_root: &LayoutBox<'f,>,) -> DisplayList<'f,> {
    let mut list = Vec::new();
-   render_layout_box(&mut list, layout_root,);
+   render_stacking_context(&mut list, layout_root, &layout_root.lines,);
    list
 }
 
-///Render background, borders, texts, etc
-fn render_layout_box(list: &mut DisplayList, layout_box: &layout::LayoutBox,) {
-   render_bg(list, layout_box,);
-   render_borders(list, layout_box,);
-   //text rendering is still unsupported
+///How a block or inline box takes part in painting.
+#[derive(PartialEq,)]
+enum Paint {
+   ///It's painted with the content of the stacking context it's in.
+   Flow,
+   ///A positioned box with 'z-index: auto', painted as a unit after the
+   /// content of its stacking context. Its positioned descendants aren't part
+   /// of it.
+   Positioned,
+   ///It establishes a stacking context, painted at its 'z-index'.
+   StackingContext(i32,),
+}
+
+fn paint_kind(layout_box: &LayoutBox,) -> Paint {
+   let (styled, inline,) = match layout_box.box_type {
+      BoxType::BlockNode(styled,) => (styled, false,),
+      BoxType::InlineNode(styled,) if is_element(styled,) => (styled, true,),
+      _ => return Paint::Flow,
+   };
+   let style = styled.computed();
+   let positioned = style.position != Position::Static;
+   match style.z_index {
+      Some(z,) if positioned => Paint::StackingContext(z,),
+      _ if style.opacity < 1.0 || has_transform(style, inline,) => Paint::StackingContext(0,),
+      _ if matches!(style.position, Position::Fixed | Position::Sticky) => Paint::StackingContext(0,),
+      _ if positioned => Paint::Positioned,
+      _ => Paint::Flow,
+   }
+}
+
+fn is_element(styled: &StyledNode,) -> bool { matches!(styled.node().node_type, dom::NodeType::Element(_)) }
+
+///Does 'style' transform its box? Inline boxes aren't transformable.
+fn has_transform(style: &ComputedValues, inline: bool,) -> bool { !inline && !style.transform.is_empty() }
+
+///A box painted at some 'z-index' of a stacking context, with the lines its
+/// inline content is on: those of its block container for an inline box.
+struct Layer<'l, 'a,> {
+   z:          i32,
+   layout_box: &'l LayoutBox<'a,>,
+   lines:      &'l [LineBox<'a,>],
+}
+
+///Paint a stacking context in the order of CSS 2.1 Appendix E: its own
+/// background and borders, the stacking contexts in it with negative
+/// 'z-index', its content in the flow, then the positioned boxes and stacking
+/// contexts of 'z-index' 0 in tree order and those of positive 'z-index'.
+fn render_stacking_context<'f,>(list: &mut DisplayList<'f,>, layout_box: &LayoutBox<'f,>, lines: &[LineBox<'f,>],) {
+   let mut layers = Vec::new();
+   collect_layers(layout_box, lines, &mut layers,);
+   //The sort is stable, leaving boxes of the same 'z-index' in tree order.
+   layers.sort_by_key(|layer| layer.z,);
+   let mut commands = Vec::new();
+   render_decorations(&mut commands, layout_box, lines,);
+   let (negative, rest,) = layers.split_at(layers.iter().position(|layer| layer.z >= 0,).unwrap_or(layers.len(),),);
+   for layer in negative {
+      render_layer(&mut commands, layer,);
+   }
+   render_flow(&mut commands, layout_box, lines,);
+   for layer in rest {
+      render_layer(&mut commands, layer,);
+   }
+   let Some(style,) = get_style(layout_box,) else { return list.extend(commands,) };
+   let inline = matches!(layout_box.box_type, BoxType::InlineNode(_));
+   let transform =
+      has_transform(style, inline,).then(|| transform::transform_matrix(&style.transform, &layout_box.dimensions.border_box(),),);
+   match (style.opacity < 1.0, transform,) {
+      (false, None,) => list.extend(commands,),
+      (_, transform,) => list.push(DisplayCommand::Layer { commands, opacity: style.opacity, transform, },),
+   }
+}
+
+///Collect the boxes painted at some 'z-index' of the stacking context of
+/// 'layout_box', whose inline content is on 'lines': the stacking contexts in
+/// it, and the positioned boxes, whose own descendants of that kind belong to
+/// it too.
+fn collect_layers<'l, 'a,>(layout_box: &'l LayoutBox<'a,>, lines: &'l [LineBox<'a,>], layers: &mut Vec<Layer<'l, 'a,>,>,) {
    for child in &layout_box.children {
-      render_layout_box(list, child,)
+      //Inline boxes are on the lines of their block container.
+      let lines = match child.box_type {
+         BoxType::InlineNode(_,) => lines,
+         _ => &child.lines,
+      };
+      match paint_kind(child,) {
+         Paint::StackingContext(z,) => layers.push(Layer { z, layout_box: child, lines, },),
+         Paint::Positioned => {
+            layers.push(Layer { z: 0, layout_box: child, lines, },);
+            collect_layers(child, lines, layers,);
+         }
+         Paint::Flow => collect_layers(child, lines, layers,),
+      }
+   }
+}
+
+fn render_layer<'f,>(list: &mut DisplayList<'f,>, layer: &Layer<'_, 'f,>,) {
+   match paint_kind(layer.layout_box,) {
+      Paint::StackingContext(_,) => render_stacking_context(list, layer.layout_box, layer.lines,),
+      _ => render_atomically(list, layer.layout_box, layer.lines,),
+   }
+}
+
+///Paint a box and its content in the flow as if it were a stacking context,
+/// as floats and positioned boxes with 'z-index: auto' are.
+fn render_atomically<'f,>(list: &mut DisplayList<'f,>, layout_box: &LayoutBox<'f,>, lines: &[LineBox<'f,>],) {
+   render_decorations(list, layout_box, lines,);
+   render_flow(list, layout_box, lines,);
+}
+
+///Paint the content of 'layout_box' in the flow: the backgrounds and borders
+/// of the blocks, then the floats, then the inline content, which is on
+/// 'lines'.
+fn render_flow<'f,>(list: &mut DisplayList<'f,>, layout_box: &LayoutBox<'f,>, lines: &[LineBox<'f,>],) {
+   render_blocks(list, layout_box,);
+   render_floats(list, layout_box,);
+   render_text(list, layout_box, lines,);
+   render_inline_content(list, layout_box,);
+}
+
+///The children of a box that are painted with its content in the flow, and
+/// whether each is a float.
+fn flow_children<'l, 'a,>(layout_box: &'l LayoutBox<'a,>,) -> impl Iterator<Item = (&'l LayoutBox<'a,>, bool,),> {
+   layout_box
+      .children
+      .iter()
+      .filter(|child| paint_kind(child,) == Paint::Flow,)
+      .map(|child| (child, get_style(child,).is_some_and(|style| style.float != Float::None,),),)
+}
+
+///The block children in the flow that aren't floats. Inline children are
+/// painted on the lines.
+fn flow_blocks<'l, 'a,>(layout_box: &'l LayoutBox<'a,>,) -> impl Iterator<Item = &'l LayoutBox<'a,>,> {
+   flow_children(layout_box,)
+      .filter(|(child, float,)| !float && !matches!(child.box_type, BoxType::InlineNode(_)),)
+      .map(|(child, _,)| child,)
+}
+
+fn render_blocks(list: &mut DisplayList, layout_box: &LayoutBox,) {
+   for child in flow_blocks(layout_box,) {
+      render_decorations(list, child, &[],);
+      render_blocks(list, child,);
+   }
+}
+
+fn render_floats<'f,>(list: &mut DisplayList<'f,>, layout_box: &LayoutBox<'f,>,) {
+   for (child, float,) in flow_children(layout_box,) {
+      match float {
+         true => render_atomically(list, child, &child.lines,),
+         false => render_floats(list, child,),
+      }
+   }
+}
+
+fn render_inline_content<'f,>(list: &mut DisplayList<'f,>, layout_box: &LayoutBox<'f,>,) {
+   for child in flow_blocks(layout_box,) {
+      render_text(list, child, &child.lines,);
+      render_inline_content(list, child,);
+   }
+}
+
+///Add the inline boxes in 'layout_box' and their descendants to 'nodes',
+/// leaving out those painted on their own and what's in them.
+fn inline_nodes<'a,>(layout_box: &LayoutBox<'a,>, nodes: &mut Vec<&'a StyledNode<'a,>,>,) {
+   for child in &layout_box.children {
+      match child.box_type {
+         BoxType::InlineNode(styled,) if paint_kind(child,) == Paint::Flow => {
+            nodes.push(styled,);
+            inline_nodes(child, nodes,);
+         }
+         _ => {}
+      }
+   }
+}
+
+///Render the inline content of 'layout_box' that is painted with it, line by
+/// line: the backgrounds and borders of the inline elements, then the text,
+/// each fragment in its own color.
+fn render_text<'f,>(list: &mut DisplayList<'f,>, layout_box: &LayoutBox<'f,>, lines: &[LineBox<'f,>],) {
+   let mut nodes = Vec::new();
+   inline_nodes(layout_box, &mut nodes,);
+   let painted = |styled: &StyledNode| nodes.iter().any(|node| std::ptr::eq(*node, styled,),);
+   for line in lines {
+      for part in line.boxes.iter().filter(|part| painted(part.styled,),) {
+         render_bg(list, part.styled.computed(), &part.dimensions,);
+         render_borders(list, part.styled.computed(), &part.dimensions,);
+      }
+      for fragment in line.fragments.iter().filter(|fragment| painted(fragment.styled,),) {
+         let style = fragment.styled.computed();
+         if style.color.a == 0 {
+            continue;
+         }
+         list.push(DisplayCommand::Text {
+            glyphs:   fragment.glyphs.clone(),
+            x:        fragment.rct.x,
+            baseline: line.baseline,
+            size:     style.font_size,
+            color:    style.color.clone(),
+         },);
+      }
+   }
+}
+
+///Render the background and borders of a box. Those of an inline box are
+/// those of its parts on 'lines'.
+fn render_decorations(list: &mut DisplayList, layout_box: &LayoutBox, lines: &[LineBox],) {
+   match layout_box.box_type {
+      BoxType::InlineNode(styled,) => {
+         for part in lines.iter().flat_map(|line| &line.boxes,).filter(|part| std::ptr::eq(part.styled, styled,),) {
+            render_bg(list, styled.computed(), &part.dimensions,);
+            render_borders(list, styled.computed(), &part.dimensions,);
+         }
+      }
+      BoxType::BlockNode(styled,) => {
+         render_bg(list, styled.computed(), &layout_box.dimensions,);
+         render_borders(list, styled.computed(), &layout_box.dimensions,);
+      }
+      BoxType::AnonymousBlock => {}
    }
 }
 
 ///Render background. If bg color isn't specified, transparent
-fn render_bg(list: &mut DisplayList, layout_box: &layout::LayoutBox,) {
-   get_color(layout_box, "background",)
-      .map(|clr| list.push(DisplayCommand::SolidColor(clr, layout_box.dimensions.border_box(),),),);
+fn render_bg(list: &mut DisplayList, style: &ComputedValues, d: &Dimensions,) {
+   push_color(list, &style.background_color, d.border_box(),);
 }
 
-///If AnonymousBlock or not specified color, return None. Else, return
-/// specified color
-fn get_color(layout_box: &layout::LayoutBox, nam: &str,) -> Option<css::Color,> {
+///If AnonymousBlock, return None. Else, return its computed values
+fn get_style<'a,>(layout_box: &layout::LayoutBox<'a,>,) -> Option<&'a ComputedValues,> {
    match layout_box.box_type {
-      layout::BoxType::BlockNode(style,) | layout::BoxType::InlineNode(style,) => match style.val(nam,) {
-         Some(css::Value::ColorValue(clr,),) => Some(clr,),
-         _ => None,
-      },
+      layout::BoxType::BlockNode(style,) | layout::BoxType::InlineNode(style,) => Some(style.computed(),),
       layout::BoxType::AnonymousBlock => None,
    }
 }
 
-///Render borders
-fn render_borders(list: &mut DisplayList, layout_box: &layout::LayoutBox,) {
-   let clr = match get_color(layout_box, "border-color",) {
-      Some(clr,) => clr,
-      _ => return, //bail out if no border-color is specified
-   };
-   let d = &layout_box.dimensions;
+///Fill 'rct' with 'clr', skipping fully transparent colors and empty rectangles.
+fn push_color(list: &mut DisplayList, clr: &css::Color, rct: layout::Rct,) {
+   if clr.a > 0 && rct.width > 0.0 && rct.height > 0.0 {
+      list.push(DisplayCommand::SolidColor(clr.clone(), rct,),);
+   }
+}
+
+///Render borders. Each side is painted in its own 'border-*-color'.
+fn render_borders(list: &mut DisplayList, style: &ComputedValues, d: &Dimensions,) {
+   let clr = &style.border_color;
    let border_box = d.border_box();
 
    //Left border
-   list.push(DisplayCommand::SolidColor(clr.clone(), layout::Rct {
+   push_color(list, &clr.left, layout::Rct {
       x:      border_box.x,
       y:      border_box.y,
       width:  d.border.left,
       height: border_box.height,
-   },),);
+   },);
 
    //Right border
-   list.push(DisplayCommand::SolidColor(clr.clone(), layout::Rct {
+   push_color(list, &clr.right, layout::Rct {
       x:      border_box.x + border_box.width - d.border.right,
       y:      border_box.y,
       width:  d.border.right,
       height: border_box.height,
-   },),);
+   },);
 
    //Top border
-   list.push(DisplayCommand::SolidColor(clr.clone(), layout::Rct {
+   push_color(list, &clr.top, layout::Rct {
       x:      border_box.x,
       y:      border_box.y,
       width:  border_box.width,
       height: d.border.top,
-   },),);
+   },);
 
    //Bottom border
-   list.push(DisplayCommand::SolidColor(clr.clone(), layout::Rct {
+   push_color(list, &clr.bottom, layout::Rct {
       x:      border_box.x,
       y:      border_box.y + border_box.height - d.border.bottom,
       width:  border_box.width,
       height: d.border.bottom,
-   },),);
+   },);
 }
 
-///Paint a tree of Layout Boxes to an array of pixels.
+///Paint a tree of Layout Boxes to an array of pixels, drawing text with the
+/// glyphs line layout shaped.
 pub fn paint(layout_root: &layout::LayoutBox, bounds: layout::Rct,) -> Canvas {
    let disp_lst = build_display_list(layout_root,);
    let mut canvas = Canvas::new(bounds.width as usize, bounds.height as usize,);
@@ -128,3 +509,165 @@ pub fn paint(layout_root: &layout::LayoutBox, bounds: layout::Rct,) -> Canvas {
    }
    canvas
 }
+
+#[cfg(test)]
+mod tests {
+   use {
+      super::*,
+      crate::{font::FontDatabase, html, media, style},
+   };
+
+   ///'f' applied to the layout of 'src' styled with 'sheet' in a 'width' by
+   /// 'height' viewport.
+   fn laid_out<T,>(src: &str, sheet: &str, width: f64, height: f64, f: impl FnOnce(&LayoutBox, &Rct,) -> T,) -> T {
+      let root = html::parse(src.to_string(),);
+      let sheets = [css::parse(format!("body, p {{ margin: 0 }} {sheet}"), css::Origin::Author,),];
+      let mut viewport = layout::Dimensions::default();
+      viewport.content.width = width;
+      viewport.content.height = height;
+      let env = media::MediaEnvironment::new(&viewport,);
+      let styled = style::style_tree(&root, &sheets, &env, &Default::default(), style::Traversal::Sequential,);
+      let fonts = FontDatabase::new();
+      let layout_root = layout::layout_tree(&styled, viewport.clone(), &fonts,);
+      f(&layout_root, &viewport.content,)
+   }
+
+   ///'src' styled with 'sheet' and painted in a 'width' by 'height' viewport.
+   fn painted(src: &str, sheet: &str, width: f64, height: f64,) -> Canvas {
+      laid_out(src, sheet, width, height, |root, viewport| paint(root, viewport.clone(),),)
+   }
+
+   ///The display list of the body 'src' styled with 'sheet', in an 800px wide
+   /// viewport: what each command paints, and in what color. Layers give the
+   /// area they cover, and end with "end".
+   fn commands(src: &str, sheet: &str,) -> Vec<String,> {
+      fn describe(list: &DisplayList, out: &mut Vec<String,>,) {
+         let hex = |c: &css::Color| format!("#{:02x}{:02x}{:02x}", c.r, c.g, c.b);
+         for item in list {
+            match item {
+               DisplayCommand::SolidColor(color, _,) => out.push(format!("rect {}", hex(color)),),
+               DisplayCommand::Text { color, .. } => out.push(format!("text {}", hex(color)),),
+               DisplayCommand::Layer { commands, .. } => {
+                  let Rct { x, y, width, height, } = item.bounds().unwrap();
+                  out.push(format!("layer {x} {y} {width} {height}"),);
+                  describe(commands, out,);
+                  out.push("end".to_string(),);
+               }
+            }
+         }
+      }
+      let src = format!("<html><body>{src}</body></html>");
+      laid_out(&src, sheet, 800.0, 600.0, |root, _| {
+         let mut out = vec![];
+         describe(&build_display_list(root,), &mut out,);
+         out
+      },)
+   }
+
+   ///The rectangles painted in 'color' in the display list of the body 'src'.
+   fn rects(src: &str, sheet: &str, color: css::Color,) -> Vec<(f64, f64, f64, f64,),> {
+      let src = format!("<html><body>{src}</body></html>");
+      laid_out(&src, sheet, 800.0, 600.0, |root, _| {
+         build_display_list(root,)
+            .iter()
+            .filter_map(|item| match item {
+               DisplayCommand::SolidColor(c, rct,) if *c == color => Some((rct.x, rct.y, rct.width, rct.height,),),
+               _ => None,
+            },)
+            .collect()
+      },)
+   }
+
+   fn pixel(canvas: &Canvas, x: usize, y: usize,) -> &css::Color { &canvas.pixels[x + y * canvas.width] }
+
+   #[test]
+   fn glyphs_are_rasterized_in_the_text_color() {
+      let canvas = painted("<p>Hello</p>", "p { font-size: 40px; line-height: 50px; color: #ff0000 }", 200.0, 100.0,);
+      let white = css::Color { r: 255, g: 255, b: 255, a: 255, };
+      let inked: Vec<(usize, usize,),> = (0..canvas.height)
+         .flat_map(|y| (0..canvas.width).map(move |x| (x, y,),),)
+         .filter(|&(x, y,)| *pixel(&canvas, x, y,) != white,)
+         .collect();
+      assert!(!inked.is_empty());
+      //Only on the line, and only red over white, some of it anti-aliased.
+      assert!(inked.iter().all(|&(_, y,)| y < 50));
+      assert!(inked.iter().map(|&(x, y,)| pixel(&canvas, x, y,),).all(|c| c.r == 255 && c.g == c.b));
+      assert!(inked.iter().any(|&(x, y,)| *pixel(&canvas, x, y,) == css::Color { r: 255, g: 0, b: 0, a: 255, }));
+      assert!(inked.iter().any(|&(x, y,)| (1..255).contains(&pixel(&canvas, x, y,).g,)));
+   }
+
+   #[test]
+   fn transparent_text_is_not_painted() {
+      let canvas = painted("<p>Hello</p>", "p { font-size: 40px; color: transparent }", 200.0, 100.0,);
+      assert!(canvas.pixels.iter().all(|c| *c == css::Color { r: 255, g: 255, b: 255, a: 255, }));
+   }
+
+   #[test]
+   fn negative_z_index_is_painted_below_the_flow() {
+      let sheet = "#a { height: 10px; background: #ff0000 } \
+                   #b { height: 10px; background: #0000ff; position: relative; z-index: -1 }";
+      assert_eq!(commands("<div id=\"a\"></div><div id=\"b\"></div>", sheet,), ["rect #0000ff", "rect #ff0000"]);
+   }
+
+   #[test]
+   fn floats_are_painted_between_blocks_and_text() {
+      let sheet = "#a { background: #ff0000 } #b { height: 10px; background: #0000ff } \
+                   #f { float: left; width: 10px; height: 10px; background: #00ff00 } body { color: #000000 }";
+      let src = "<div id=\"a\"><div id=\"f\"></div>text</div><div id=\"b\"></div>";
+      assert_eq!(commands(src, sheet,), ["rect #ff0000", "rect #0000ff", "rect #00ff00", "text #000000"]);
+   }
+
+   #[test]
+   fn inline_boxes_are_painted_with_their_text() {
+      let sheet = "body { color: #000000 } span { background: #ff0000; border: 2px solid #0000ff }";
+      let border = vec!["rect #0000ff"; 4];
+      let expected = [vec!["rect #ff0000"], border, vec!["text #000000", "text #000000"],].concat();
+      assert_eq!(commands("<p>a <span>b</span></p>", sheet,), expected);
+   }
+
+   #[test]
+   fn inline_backgrounds_cover_their_text_on_each_line() {
+      let sheet = "p { width: 30px; font-size: 10px; line-height: 20px } span { background: #ff0000; padding: 0 3px }";
+      let red = css::Color { r: 255, g: 0, b: 0, a: 255, };
+      let parts = rects("<p><span>abc def</span></p>", sheet, red.clone(),);
+      assert_eq!(parts.len(), 2);
+      //The padding is on the left of the first line only, so the text of the
+      // second starts at its left.
+      let (first, last,) = (parts[0], parts[1],);
+      assert_eq!(first.0, 0.0);
+      assert_eq!(last.0, 0.0);
+      assert!(first.1 < last.1);
+      //As tall as the font, not the line.
+      assert!(first.3 < 20.0);
+      let canvas = painted("<p><span>abc def</span></p>", sheet, 100.0, 100.0,);
+      assert!(*pixel(&canvas, 1, first.1 as usize + 1,) == red);
+   }
+
+   #[test]
+   fn positioned_boxes_are_painted_over_the_flow_in_tree_order() {
+      let sheet = "div { height: 10px } #d { position: relative; z-index: 1; background: #ffff00 } \
+                   #a { position: relative; background: #ff0000 } #b { background: #0000ff } \
+                   #c { position: relative; z-index: 0; background: #00ff00 }";
+      let src = "<div id=\"d\"></div><div id=\"a\"></div><div id=\"b\"></div><div id=\"c\"></div>";
+      assert_eq!(commands(src, sheet,), ["rect #0000ff", "rect #ff0000", "rect #00ff00", "rect #ffff00"]);
+   }
+
+   #[test]
+   fn positioned_inline_boxes_are_stacking_contexts() {
+      let sheet = |z: i32| {
+         format!("p {{ color: #000000; background: #0000ff }} #s {{ position: relative; z-index: {z}; color: #ff0000; background: #00ff00 }}")
+      };
+      let src = "<p><span id=\"s\">a</span> b</p>";
+      assert_eq!(commands(src, &sheet(1,),), ["rect #0000ff", "text #000000", "rect #00ff00", "text #ff0000"]);
+      assert_eq!(commands(src, &sheet(-1,),), ["rect #00ff00", "text #ff0000", "rect #0000ff", "text #000000"]);
+   }
+
+   #[test]
+   fn layers_cover_only_their_content() {
+      let sheet = "div { margin: 10px; width: 20px; height: 20px; background: #ff0000; opacity: 0.5 }";
+      assert_eq!(commands("<div></div>", sheet,), ["layer 10 10 20 20", "rect #ff0000", "end"]);
+      let canvas = painted("<html><body><div></div></body></html>", sheet, 100.0, 100.0,);
+      assert!(*pixel(&canvas, 15, 15,) == css::Color { r: 255, g: 128, b: 128, a: 255, });
+      assert!(*pixel(&canvas, 5, 5,) == css::Color { r: 255, g: 255, b: 255, a: 255, });
+   }
+}
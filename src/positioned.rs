@@ -0,0 +1,259 @@
+#![allow(unused)]
+use crate::{
+   float::FloatContext,
+   font::FontDatabase,
+   layout::{BoxType, Dimensions, LayoutBox, MarginStrut, Rct},
+   properties::{ComputedValues, Direction, Length, Position},
+   style::StyledNode,
+};
+
+///Place the positioned boxes of a laid-out tree. Those with no positioned
+/// ancestor are placed in 'initial', the initial containing block, and fixed
+/// ones in 'viewport', the scrolled viewport.
+pub(crate) fn layout_positioned<'a,>(root: &mut LayoutBox<'a,>, initial: &Rct, viewport: &Rct, fonts: &'a FontDatabase,) {
+   place(root, initial, initial, viewport, fonts,)
+}
+
+///Place 'layout_box' if it's positioned, 'content' being the content box of
+/// its parent and 'cb' the padding box of the nearest positioned ancestor, and
+/// then the positioned boxes in it.
+fn place<'a,>(layout_box: &mut LayoutBox<'a,>, content: &Rct, cb: &Rct, viewport: &Rct, fonts: &'a FontDatabase,) {
+   let BoxType::BlockNode(styled,) = layout_box.box_type else {
+      return layout_box.place_children(cb, viewport, fonts,);
+   };
+   let style = styled.computed();
+   match style.position {
+      Position::Static => {}
+      Position::Relative => {
+         let (dx, dy,) = relative_offset(style, content,);
+         layout_box.translate(dx, dy,)
+      }
+      Position::Sticky => {
+         let (dx, dy,) = layout_box.sticky_offset(content, viewport,);
+         layout_box.translate(dx, dy,)
+      }
+      Position::Absolute => layout_box.layout_absolute(cb, fonts,),
+      Position::Fixed => layout_box.layout_absolute(viewport, fonts,),
+   }
+   //A positioned box is the containing block of the absolutely positioned boxes in it.
+   match style.position {
+      Position::Static => layout_box.place_children(cb, viewport, fonts,),
+      _ => layout_box.place_children(&layout_box.dimensions.padding_box(), viewport, fonts,),
+   }
+}
+
+///How far a relatively positioned box moves from where the flow put it, with
+/// percentages resolved against 'cb'. 'top' wins over 'bottom', and the start
+/// side over the end one (CSS 2.1 §9.4.3).
+fn relative_offset(style: &ComputedValues, cb: &Rct,) -> (f64, f64,) {
+   let inset = &style.inset;
+   let dx = match (used(&inset.left, cb.width,), used(&inset.right, cb.width,),) {
+      (Some(_,), Some(right,),) if style.direction == Direction::Rtl => -right,
+      (Some(left,), _,) => left,
+      (None, Some(right,),) => -right,
+      (None, None,) => 0.0,
+   };
+   let dy = match (used(&inset.top, cb.height,), used(&inset.bottom, cb.height,),) {
+      (Some(top,), _,) => top,
+      (None, Some(bottom,),) => -bottom,
+      (None, None,) => 0.0,
+   };
+   (dx, dy,)
+}
+
+///The used value of an inset, margin or size in px. 'None' if it's 'auto'.
+fn used(length: &Length, basis: f64,) -> Option<f64,> { (*length != Length::Auto).then(|| length.to_px(basis,),) }
+
+impl<'a,> LayoutBox<'a,> {
+   ///Place the positioned boxes in this box. The text of relatively positioned
+   /// inline boxes moves with them.
+   fn place_children(&mut self, cb: &Rct, viewport: &Rct, fonts: &'a FontDatabase,) {
+      if let BoxType::AnonymousBlock = self.box_type {
+         self.offset_relative_inlines();
+      }
+      let content = self.dimensions.content.clone();
+      for child in &mut self.children {
+         place(child, &content, cb, viewport, fonts,);
+      }
+   }
+
+   ///Move the fragments of the text in relatively positioned inline boxes of
+   /// this anonymous block, and their parts on its lines, by the offsets of
+   /// those boxes.
+   fn offset_relative_inlines(&mut self,) {
+      let content = self.dimensions.content.clone();
+      let mut offsets = Vec::new();
+      for child in &self.children {
+         inline_offsets(child, &content, (0.0, 0.0,), &mut offsets,);
+      }
+      offsets.retain(|(_, offset,)| *offset != (0.0, 0.0,),);
+      if offsets.is_empty() {
+         return;
+      }
+      let offset = |node| offsets.iter().find(|(styled, _,)| std::ptr::eq(*styled, node,),).map(|(_, offset,)| *offset,);
+      for line in &mut self.lines {
+         for fragment in &mut line.fragments {
+            if let Some((dx, dy,),) = offset(fragment.styled,) {
+               fragment.rct.x += dx;
+               fragment.rct.y += dy;
+            }
+         }
+         for part in &mut line.boxes {
+            if let Some((dx, dy,),) = offset(part.styled,) {
+               part.dimensions.content.x += dx;
+               part.dimensions.content.y += dy;
+            }
+         }
+      }
+   }
+
+   ///How far a sticky box moves to stay in 'viewport', less its insets, without
+   /// leaving 'content', the content box of its parent (CSS Positioned Layout
+   /// §3.4). 'top' wins over 'bottom', and 'left' over 'right'.
+   fn sticky_offset(&self, content: &Rct, viewport: &Rct,) -> (f64, f64,) {
+      let style = self.get_style_node().computed();
+      let inset = &style.inset;
+      let (border, margin,) = (self.dimensions.border_box(), self.dimensions.margin_box(),);
+      let mut dx = 0.0_f64;
+      if let Some(right,) = used(&inset.right, viewport.width,) {
+         dx = dx.min(viewport.x + viewport.width - right - (border.x + border.width),);
+      }
+      if let Some(left,) = used(&inset.left, viewport.width,) {
+         dx = dx.max(viewport.x + left - border.x,);
+      }
+      let mut dy = 0.0_f64;
+      if let Some(bottom,) = used(&inset.bottom, viewport.height,) {
+         dy = dy.min(viewport.y + viewport.height - bottom - (border.y + border.height),);
+      }
+      if let Some(top,) = used(&inset.top, viewport.height,) {
+         dy = dy.max(viewport.y + top - border.y,);
+      }
+      //It moves no further than the edges of its parent, and never to get back into it.
+      let keep_in = |d: f64, near: f64, far: f64| if d > 0.0 { d.min(far.max(0.0,),) } else { d.max(near.min(0.0,),) };
+      (
+         keep_in(dx, content.x - margin.x, content.x + content.width - (margin.x + margin.width),),
+         keep_in(dy, content.y - margin.y, content.y + content.height - (margin.y + margin.height),),
+      )
+   }
+
+   ///Lay out an absolutely positioned box in 'cb', the padding box of its
+   /// containing block, by the constraints of CSS 2.1 §10.3.7 and §10.6.4.
+   /// The box is at its static position until then.
+   fn layout_absolute(&mut self, cb: &Rct, fonts: &'a FontDatabase,) {
+      let style = self.get_style_node().computed();
+      let (static_x, static_y,) = (self.dimensions.content.x, self.dimensions.content.y,);
+      let inset = &style.inset;
+      let border = &style.border_width;
+      let edges_x = border.left + border.right + style.padding.left.to_px(cb.width,) + style.padding.right.to_px(cb.width,);
+      let edges_y = border.top + border.bottom + style.padding.top.to_px(cb.width,) + style.padding.bottom.to_px(cb.width,);
+
+      //Horizontally. If neither 'left' nor 'right' is set, the box stays at its static position.
+      let (mut left, right,) = (used(&inset.left, cb.width,), used(&inset.right, cb.width,),);
+      if left.is_none() && right.is_none() {
+         left = Some(static_x - cb.x,);
+      }
+      let (mut margin_left, mut margin_right,) = (used(&style.margin.left, cb.width,), used(&style.margin.right, cb.width,),);
+      let width = used(&style.width, cb.width,);
+      let rtl = style.direction == Direction::Rtl;
+      if let (Some(l,), Some(w,), Some(r,),) = (left, width, right,) {
+         let free = cb.width - l - r - w - edges_x;
+         match (margin_left, margin_right,) {
+            //Auto margins share the free space, but never make the box start outside its containing block.
+            (None, None,) if free < 0.0 && rtl => (margin_left, margin_right,) = (Some(free,), Some(0.0,),),
+            (None, None,) if free < 0.0 => (margin_left, margin_right,) = (Some(0.0,), Some(free,),),
+            (None, None,) => (margin_left, margin_right,) = (Some(free / 2.0,), Some(free / 2.0,),),
+            (None, Some(mr,),) => margin_left = Some(free - mr,),
+            (Some(ml,), None,) => margin_right = Some(free - ml,),
+            //Overconstrained, the inset at the end of the line is ignored:
+            // 'right', or 'left' right to left.
+            (Some(_,), Some(_,),) if rtl => left = None,
+            (Some(_,), Some(_,),) => {}
+         }
+      }
+      let (margin_left, margin_right,) = (margin_left.unwrap_or(0.0,), margin_right.unwrap_or(0.0,),);
+      let available = cb.width - left.unwrap_or(0.0,) - right.unwrap_or(0.0,) - margin_left - margin_right - edges_x;
+
+      //The content is laid out as a block formatting context of its own, in a
+      // containing block as wide as its margin box.
+      let layout = |layout_box: &mut LayoutBox<'a,>, width: f64| {
+         let cntin_blck = Dimensions {
+            content: Rct { x: cb.x, y: cb.y, width: margin_left + edges_x + width + margin_right, height: 0.0, },
+            ..Default::default()
+         };
+         let (mut strut, mut floats,) = (MarginStrut::default(), FloatContext::default(),);
+         layout_box.layout_block(&cntin_blck, Some(cb.height,), fonts, &mut strut, &mut floats, true,);
+      };
+      let width = match (width, left, right,) {
+         (Some(w,), ..,) => w,
+         (None, Some(_,), Some(_,),) => available.max(0.0,),
+         //Otherwise it shrinks to fit its content, which is wider than the
+         // space available only if it can't be broken any narrower.
+         (None, ..,) => {
+            layout(self, available.max(0.0,),);
+            self.content_extent()
+         }
+      };
+      layout(self, width,);
+      let x = match left {
+         Some(l,) => cb.x + l,
+         None => cb.x + cb.width - right.unwrap_or(0.0,) - (margin_left + edges_x + width + margin_right),
+      };
+
+      //Vertically, once the content height is known.
+      let (mut top, bottom,) = (used(&inset.top, cb.height,), used(&inset.bottom, cb.height,),);
+      if top.is_none() && bottom.is_none() {
+         top = Some(static_y - cb.y,);
+      }
+      let (mut margin_top, mut margin_bottom,) = (used(&style.margin.top, cb.width,), used(&style.margin.bottom, cb.width,),);
+      let height = used(&style.height, cb.height,);
+      if let (Some(t,), Some(h,), Some(b,),) = (top, height, bottom,) {
+         let free = cb.height - t - b - h - edges_y;
+         match (margin_top, margin_bottom,) {
+            (None, None,) => (margin_top, margin_bottom,) = (Some(free / 2.0,), Some(free / 2.0,),),
+            (None, Some(mb,),) => margin_top = Some(free - mb,),
+            (Some(mt,), None,) => margin_bottom = Some(free - mt,),
+            //Overconstrained, 'bottom' is ignored.
+            (Some(_,), Some(_,),) => {}
+         }
+      }
+      let (margin_top, margin_bottom,) = (margin_top.unwrap_or(0.0,), margin_bottom.unwrap_or(0.0,),);
+      let d = &mut self.dimensions;
+      if let (None, Some(t,), Some(b,),) = (height, top, bottom,) {
+         d.content.height = (cb.height - t - b - margin_top - margin_bottom - edges_y).max(0.0,);
+      }
+      let height = d.content.height;
+      let y = match top {
+         Some(t,) => cb.y + t,
+         None => cb.y + cb.height - bottom.unwrap_or(0.0,) - (margin_top + edges_y + height + margin_bottom),
+      };
+
+      d.margin.left = margin_left;
+      d.margin.right = margin_right;
+      d.margin.top = margin_top;
+      d.margin.bottom = margin_bottom;
+      let margin_box = d.margin_box();
+      self.translate(x - margin_box.x, y - margin_box.y,);
+   }
+}
+
+///Collect the offsets of the inline box 'layout_box' and the boxes in it, which
+/// is moved by 'offset' with the relatively positioned boxes around it.
+fn inline_offsets<'a,>(
+   layout_box: &LayoutBox<'a,>,
+   cb: &Rct,
+   offset: (f64, f64,),
+   offsets: &mut Vec<(&'a StyledNode<'a,>, (f64, f64,),),>,
+) {
+   let BoxType::InlineNode(styled,) = layout_box.box_type else { return };
+   let (dx, dy,) = match styled.computed().position {
+      Position::Relative => {
+         let (dx, dy,) = relative_offset(styled.computed(), cb,);
+         (offset.0 + dx, offset.1 + dy,)
+      }
+      _ => offset,
+   };
+   offsets.push((styled, (dx, dy,),),);
+   for child in &layout_box.children {
+      inline_offsets(child, cb, (dx, dy,), offsets,);
+   }
+}
@@ -0,0 +1,1026 @@
+#![allow(unused)]
+use std::{
+   collections::{HashMap, HashSet},
+   fmt,
+   sync::Arc,
+};
+
+use crate::{
+   calc::{Calc, CalcKind},
+   css::{self, Origin, Unit, Value},
+   style::{Display, PropertyMap},
+   transform::{Matrix, TransformFunction},
+};
+
+///Define 'LonghandId' with one variant per longhand property, along with its
+/// name, whether it is inherited, its initial value written as CSS, and a
+/// validator for its grammar.
+macro_rules! longhands {
+   ($($id:ident $nam:literal, inherited: $inherited:literal, initial: $initial:literal, valid: $valid:expr;)+) => {
+      ///A longhand property the engine knows about.
+      #[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord,)]
+      pub enum LonghandId {
+         $($id,)+
+      }
+
+      impl LonghandId {
+         pub const ALL: &'static [LonghandId] = &[$(LonghandId::$id,)+];
+
+         pub fn from_name(nam: &str,) -> Option<LonghandId,> {
+            match nam {
+               $($nam => Some(LonghandId::$id,),)+
+               _ => None,
+            }
+         }
+
+         pub fn name(self,) -> &'static str {
+            match self {
+               $(LonghandId::$id => $nam,)+
+            }
+         }
+
+         ///Whether the computed value flows to children when nothing is declared.
+         pub fn inherited(self,) -> bool {
+            match self {
+               $(LonghandId::$id => $inherited,)+
+            }
+         }
+
+         fn initial_src(self,) -> &'static str {
+            match self {
+               $(LonghandId::$id => $initial,)+
+            }
+         }
+
+         ///Whether 'val' is a valid value of the property.
+         pub fn is_valid(self, val: &Value,) -> bool {
+            let valid: fn(&Value,) -> bool = match self {
+               $(LonghandId::$id => $valid,)+
+            };
+            matches!(val.keyword(), Some(kw) if css::is_css_wide_keyword(kw)) || valid(val,)
+         }
+      }
+   };
+}
+
+///A property named in a declaration.
+#[derive(Clone, PartialEq, Eq, Hash,)]
+pub enum PropertyId {
+   Longhand(LonghandId,),
+   ///A custom property such as '--brand', whose value is raw CSS.
+   Custom(String,),
+}
+
+impl PropertyId {
+   ///The name as written in a declaration.
+   pub fn name(&self,) -> &str {
+      match self {
+         PropertyId::Longhand(id,) => id.name(),
+         PropertyId::Custom(nam,) => nam,
+      }
+   }
+}
+
+///Whether 'v' is a keyword of the keyword enum '$nam'.
+macro_rules! valid_keyword {
+   ($nam:ident) => {
+      |v| v.keyword().and_then($nam::from_keyword,).is_some()
+   };
+}
+longhands! {
+   Display "display", inherited: false, initial: "inline", valid: |v| display(v).is_some();
+   Width "width", inherited: false, initial: "auto", valid: valid_size;
+   Height "height", inherited: false, initial: "auto", valid: valid_size;
+   Position "position", inherited: false, initial: "static", valid: valid_keyword!(Position);
+   Float "float", inherited: false, initial: "none", valid: valid_keyword!(Float);
+   Clear "clear", inherited: false, initial: "none", valid: valid_keyword!(Clear);
+   OverflowX "overflow-x", inherited: false, initial: "visible", valid: valid_keyword!(Overflow);
+   OverflowY "overflow-y", inherited: false, initial: "visible", valid: valid_keyword!(Overflow);
+   MarginTop "margin-top", inherited: false, initial: "0", valid: valid_length;
+   MarginRight "margin-right", inherited: false, initial: "0", valid: valid_length;
+   MarginBottom "margin-bottom", inherited: false, initial: "0", valid: valid_length;
+   MarginLeft "margin-left", inherited: false, initial: "0", valid: valid_length;
+   PaddingTop "padding-top", inherited: false, initial: "0", valid: valid_padding;
+   PaddingRight "padding-right", inherited: false, initial: "0", valid: valid_padding;
+   PaddingBottom "padding-bottom", inherited: false, initial: "0", valid: valid_padding;
+   PaddingLeft "padding-left", inherited: false, initial: "0", valid: valid_padding;
+   BorderTopWidth "border-top-width", inherited: false, initial: "medium", valid: valid_line_width;
+   BorderRightWidth "border-right-width", inherited: false, initial: "medium", valid: valid_line_width;
+   BorderBottomWidth "border-bottom-width", inherited: false, initial: "medium", valid: valid_line_width;
+   BorderLeftWidth "border-left-width", inherited: false, initial: "medium", valid: valid_line_width;
+   BorderTopStyle "border-top-style", inherited: false, initial: "none", valid: valid_keyword!(BorderStyle);
+   BorderRightStyle "border-right-style", inherited: false, initial: "none", valid: valid_keyword!(BorderStyle);
+   BorderBottomStyle "border-bottom-style", inherited: false, initial: "none", valid: valid_keyword!(BorderStyle);
+   BorderLeftStyle "border-left-style", inherited: false, initial: "none", valid: valid_keyword!(BorderStyle);
+   BorderTopColor "border-top-color", inherited: false, initial: "currentcolor", valid: valid_color;
+   BorderRightColor "border-right-color", inherited: false, initial: "currentcolor", valid: valid_color;
+   BorderBottomColor "border-bottom-color", inherited: false, initial: "currentcolor", valid: valid_color;
+   BorderLeftColor "border-left-color", inherited: false, initial: "currentcolor", valid: valid_color;
+   Top "top", inherited: false, initial: "auto", valid: valid_length;
+   Right "right", inherited: false, initial: "auto", valid: valid_length;
+   Bottom "bottom", inherited: false, initial: "auto", valid: valid_length;
+   Left "left", inherited: false, initial: "auto", valid: valid_length;
+   ZIndex "z-index", inherited: false, initial: "auto", valid: |v| z_index(v).is_some();
+   Opacity "opacity", inherited: false, initial: "1", valid: |v| opacity(v).is_some();
+   Transform "transform", inherited: false, initial: "none",
+      valid: |v| transform(v, MEDIUM_FONT_SIZE, MEDIUM_FONT_SIZE).is_some();
+   BackgroundColor "background-color", inherited: false, initial: "transparent", valid: valid_color;
+   BackgroundImage "background-image", inherited: false, initial: "none", valid: |v| v.keyword() == Some("none",);
+   BackgroundRepeat "background-repeat", inherited: false, initial: "repeat", valid: valid_keyword!(BackgroundRepeat);
+   BackgroundAttachment "background-attachment", inherited: false, initial: "scroll",
+      valid: valid_keyword!(BackgroundAttachment);
+   Color "color", inherited: true, initial: "black", valid: valid_color;
+   FontFamily "font-family", inherited: true, initial: "serif", valid: |v| font_family(v).is_some();
+   FontSize "font-size", inherited: true, initial: "medium",
+      valid: |v| font_size(v, MEDIUM_FONT_SIZE, MEDIUM_FONT_SIZE).is_some_and(|px| px >= 0.0);
+   FontStyle "font-style", inherited: true, initial: "normal", valid: valid_keyword!(FontStyle);
+   FontVariant "font-variant", inherited: true, initial: "normal",
+      valid: |v| matches!(v.keyword(), Some("normal" | "small-caps"));
+   FontWeight "font-weight", inherited: true, initial: "normal", valid: |v| font_weight(v, 400).is_some();
+   FontStretch "font-stretch", inherited: true, initial: "normal", valid: valid_font_stretch;
+   LineHeight "line-height", inherited: true, initial: "normal",
+      valid: |v| line_height(v, MEDIUM_FONT_SIZE, MEDIUM_FONT_SIZE).is_some();
+   WhiteSpace "white-space", inherited: true, initial: "normal", valid: valid_keyword!(WhiteSpace);
+   Direction "direction", inherited: true, initial: "ltr", valid: valid_keyword!(Direction);
+   UnicodeBidi "unicode-bidi", inherited: false, initial: "normal", valid: valid_keyword!(UnicodeBidi);
+   FlexGrow "flex-grow", inherited: false, initial: "0", valid: valid_non_negative_number;
+   FlexShrink "flex-shrink", inherited: false, initial: "1", valid: valid_non_negative_number;
+   FlexBasis "flex-basis", inherited: false, initial: "auto",
+      valid: |v| v.keyword() == Some("content",) || valid_size(v,);
+   ContainerType "container-type", inherited: false, initial: "normal", valid: valid_keyword!(ContainerType);
+   ContainerName "container-name", inherited: false, initial: "none", valid: |v| container_name(v).is_some();
+}
+
+///A length, a percentage of some basis, or 'auto'.
+#[derive(Clone, PartialEq,)]
+pub enum Length {
+   Auto,
+   Px(f64,),
+   Percent(f64,),
+   ///A mix of px and percentages, like 'calc(100% - 10px)'.
+   Calc(Box<Calc,>,),
+}
+
+impl Length {
+   ///The used length in px, resolving percentages against 'basis'. 'auto' is 0.
+   pub fn to_px(&self, basis: f64,) -> f64 {
+      match self {
+         Length::Auto => 0.0,
+         Length::Px(px,) => *px,
+         Length::Percent(pct,) => basis * pct / 100.0,
+         Length::Calc(calc,) => calc.eval(0.0, 0.0, basis,),
+      }
+   }
+
+   ///Does the length depend on the size of the containing block?
+   pub fn has_percentage(&self,) -> bool {
+      match self {
+         Length::Percent(_,) => true,
+         Length::Calc(calc,) => calc.has_percentage(),
+         _ => false,
+      }
+   }
+}
+
+impl fmt::Display for Length {
+   fn fmt(&self, f: &mut fmt::Formatter,) -> fmt::Result {
+      match self {
+         Length::Auto => write!(f, "auto"),
+         Length::Px(px,) => write!(f, "{px}px"),
+         Length::Percent(pct,) => write!(f, "{pct}%"),
+         Length::Calc(calc,) => write!(f, "{calc}"),
+      }
+   }
+}
+
+///A value for each edge of a box.
+#[derive(Clone, Copy, PartialEq, Default,)]
+pub struct Sides<T,> {
+   pub top:    T,
+   pub right:  T,
+   pub bottom: T,
+   pub left:   T,
+}
+
+///Define a keyword-valued enum and how to read it from a css keyword.
+macro_rules! keyword_enum {
+   ($(#[$meta:meta])* $nam:ident { $($variant:ident = $kw:literal,)+ }) => {
+      $(#[$meta])*
+      #[derive(Clone, Copy, PartialEq, Eq,)]
+      pub enum $nam {
+         $($variant,)+
+      }
+
+      impl $nam {
+         pub fn from_keyword(kw: &str,) -> Option<$nam,> {
+            match kw {
+               $($kw => Some($nam::$variant,),)+
+               _ => None,
+            }
+         }
+
+         pub fn keyword(self,) -> &'static str {
+            match self {
+               $($nam::$variant => $kw,)+
+            }
+         }
+      }
+   };
+}
+
+keyword_enum!(BorderStyle {
+   None = "none",
+   Hidden = "hidden",
+   Dotted = "dotted",
+   Dashed = "dashed",
+   Solid = "solid",
+   Double = "double",
+   Groove = "groove",
+   Ridge = "ridge",
+   Inset = "inset",
+   Outset = "outset",
+});
+
+keyword_enum!(FontStyle { Normal = "normal", Italic = "italic", Oblique = "oblique", });
+
+keyword_enum!(WhiteSpace {
+   Normal = "normal",
+   Pre = "pre",
+   Nowrap = "nowrap",
+   PreWrap = "pre-wrap",
+   PreLine = "pre-line",
+});
+
+keyword_enum!(Position { Static = "static", Relative = "relative", Absolute = "absolute", Fixed = "fixed", Sticky = "sticky", });
+
+impl Position {
+   ///Is the box absolutely positioned, and so out of the flow (CSS 2.1 §9.6)?
+   pub fn is_absolute(self,) -> bool { matches!(self, Position::Absolute | Position::Fixed) }
+}
+
+keyword_enum!(Float { None = "none", Left = "left", Right = "right", });
+
+keyword_enum!(Clear { None = "none", Left = "left", Right = "right", Both = "both", });
+
+keyword_enum!(Overflow { Visible = "visible", Hidden = "hidden", Clip = "clip", Scroll = "scroll", Auto = "auto", });
+
+keyword_enum!(Direction { Ltr = "ltr", Rtl = "rtl", });
+
+keyword_enum!(
+   ///How an inline element takes part in the bidirectional algorithm.
+   UnicodeBidi {
+      Normal = "normal",
+      Embed = "embed",
+      Isolate = "isolate",
+      BidiOverride = "bidi-override",
+      IsolateOverride = "isolate-override",
+      Plaintext = "plaintext",
+   }
+);
+
+keyword_enum!(BackgroundRepeat {
+   Repeat = "repeat",
+   RepeatX = "repeat-x",
+   RepeatY = "repeat-y",
+   NoRepeat = "no-repeat",
+   Space = "space",
+   Round = "round",
+});
+
+keyword_enum!(BackgroundAttachment { Scroll = "scroll", Fixed = "fixed", Local = "local", });
+
+keyword_enum!(
+   ///Which sizes of an element '@container' rules of its descendants can query.
+   ContainerType { Normal = "normal", Size = "size", InlineSize = "inline-size", }
+);
+
+#[derive(Clone, Copy, PartialEq,)]
+pub enum LineHeight {
+   Normal,
+   ///A multiple of the element's font size.
+   Number(f64,),
+   Px(f64,),
+}
+
+///Computed values of one node, read by layout and painting. Lengths are in px
+/// except percentages, which need the containing block to resolve.
+#[derive(Clone, PartialEq,)]
+pub struct ComputedValues {
+   pub display:          Display,
+   pub width:            Length,
+   pub height:           Length,
+   pub position:         Position,
+   ///'top', 'right', 'bottom' and 'left', which offset positioned boxes.
+   pub inset:            Sides<Length,>,
+   ///'None' for 'auto'.
+   pub z_index:          Option<i32,>,
+   pub opacity:          f64,
+   ///The functions of 'transform' in the order written. Empty for 'none'.
+   pub transform:        Vec<TransformFunction,>,
+   pub float:            Float,
+   pub clear:            Clear,
+   pub overflow_x:       Overflow,
+   pub overflow_y:       Overflow,
+   pub margin:           Sides<Length,>,
+   pub padding:          Sides<Length,>,
+   pub border_width:     Sides<f64,>,
+   pub border_style:     Sides<BorderStyle,>,
+   pub border_color:     Sides<css::Color,>,
+   pub background_color: css::Color,
+   pub color:            css::Color,
+   pub font_family:      Vec<String,>,
+   pub font_size:        f64,
+   pub font_style:       FontStyle,
+   pub font_weight:      u16,
+   pub line_height:      LineHeight,
+   pub white_space:      WhiteSpace,
+   pub direction:        Direction,
+   pub unicode_bidi:     UnicodeBidi,
+   pub container_type:   ContainerType,
+   pub container_name:   Vec<String,>,
+   ///Values of custom properties after their own 'var()' substitution. An
+   /// absent property has the guaranteed-invalid initial value.
+   pub custom:           Arc<HashMap<String, String,>,>,
+}
+
+///Font size of the 'medium' keyword, and of the root element by default.
+pub const MEDIUM_FONT_SIZE: f64 = 16.0;
+
+impl ComputedValues {
+   ///Compute the values of a node from its declared values. 'parent' is 'None'
+   /// for the root, which inherits initial values. 'root_font_size' resolves 'rem'.
+   pub fn compute(declared: &PropertyMap, parent: Option<&ComputedValues,>, root_font_size: f64,) -> ComputedValues {
+      use LonghandId as L;
+      let custom = custom_properties(declared, parent,);
+      let cx = Cx { declared, parent, custom: &custom, };
+      //Font size and color come first, since 'em' and 'currentcolor' refer to them.
+      let font_size = cx.get(L::FontSize, |p| p.font_size, |v| font_size(v, cx.parent_font_size(), root_font_size,),);
+      let color = cx.get(L::Color, |p| p.color.clone(), |v| color(v, &cx.parent_color(),),);
+      let len = |v: &Value| length(v, font_size, root_font_size,);
+      let non_auto = |v: &Value| len(v,).filter(|l| *l != Length::Auto,);
+
+      let border_style = Sides {
+         top:    cx.get(L::BorderTopStyle, |p| p.border_style.top, keyword(BorderStyle::from_keyword,),),
+         right:  cx.get(L::BorderRightStyle, |p| p.border_style.right, keyword(BorderStyle::from_keyword,),),
+         bottom: cx.get(L::BorderBottomStyle, |p| p.border_style.bottom, keyword(BorderStyle::from_keyword,),),
+         left:   cx.get(L::BorderLeftStyle, |p| p.border_style.left, keyword(BorderStyle::from_keyword,),),
+      };
+      //A border without a style has no width.
+      let border_width = |nam, style: BorderStyle, parent: fn(&ComputedValues,) -> f64| match style {
+         BorderStyle::None | BorderStyle::Hidden => 0.0,
+         _ => cx.get(nam, parent, |v| line_width(v, font_size, root_font_size,),),
+      };
+
+      //Absolutely positioned boxes don't float, and they and floats are
+      // block-level (CSS 2.1 §9.7).
+      let position = cx.get(L::Position, |p| p.position, keyword(Position::from_keyword,),);
+      let float = match cx.get(L::Float, |p| p.float, keyword(Float::from_keyword,),) {
+         _ if position.is_absolute() => Float::None,
+         float => float,
+      };
+      let display = match cx.get(L::Display, |p| p.display, display,) {
+         Display::Inline if float != Float::None || position.is_absolute() => Display::Block,
+         display => display,
+      };
+      let overflow_x = cx.get(L::OverflowX, |p| p.overflow_x, keyword(Overflow::from_keyword,),);
+      let overflow_y = cx.get(L::OverflowY, |p| p.overflow_y, keyword(Overflow::from_keyword,),);
+      //If only one axis scrolls or hides, the other can't stay visible.
+      let overflow = |own: Overflow, other: Overflow| match (own, other,) {
+         (Overflow::Visible, Overflow::Hidden | Overflow::Scroll | Overflow::Auto,) => Overflow::Auto,
+         (Overflow::Clip, Overflow::Hidden | Overflow::Scroll | Overflow::Auto,) => Overflow::Hidden,
+         _ => own,
+      };
+
+      ComputedValues {
+         display,
+         width: cx.get(L::Width, |p| p.width.clone(), len,),
+         height: cx.get(L::Height, |p| p.height.clone(), len,),
+         position,
+         inset: Sides {
+            top:    cx.get(L::Top, |p| p.inset.top.clone(), len,),
+            right:  cx.get(L::Right, |p| p.inset.right.clone(), len,),
+            bottom: cx.get(L::Bottom, |p| p.inset.bottom.clone(), len,),
+            left:   cx.get(L::Left, |p| p.inset.left.clone(), len,),
+         },
+         z_index: cx.get(L::ZIndex, |p| p.z_index, z_index,),
+         opacity: cx.get(L::Opacity, |p| p.opacity, opacity,),
+         transform: cx.get(L::Transform, |p| p.transform.clone(), |v| transform(v, font_size, root_font_size,),),
+         float,
+         clear: cx.get(L::Clear, |p| p.clear, keyword(Clear::from_keyword,),),
+         overflow_x: overflow(overflow_x, overflow_y,),
+         overflow_y: overflow(overflow_y, overflow_x,),
+         margin: Sides {
+            top:    cx.get(L::MarginTop, |p| p.margin.top.clone(), len,),
+            right:  cx.get(L::MarginRight, |p| p.margin.right.clone(), len,),
+            bottom: cx.get(L::MarginBottom, |p| p.margin.bottom.clone(), len,),
+            left:   cx.get(L::MarginLeft, |p| p.margin.left.clone(), len,),
+         },
+         padding: Sides {
+            top:    cx.get(L::PaddingTop, |p| p.padding.top.clone(), non_auto,),
+            right:  cx.get(L::PaddingRight, |p| p.padding.right.clone(), non_auto,),
+            bottom: cx.get(L::PaddingBottom, |p| p.padding.bottom.clone(), non_auto,),
+            left:   cx.get(L::PaddingLeft, |p| p.padding.left.clone(), non_auto,),
+         },
+         border_width: Sides {
+            top:    border_width(L::BorderTopWidth, border_style.top, |p| p.border_width.top,),
+            right:  border_width(L::BorderRightWidth, border_style.right, |p| p.border_width.right,),
+            bottom: border_width(L::BorderBottomWidth, border_style.bottom, |p| p.border_width.bottom,),
+            left:   border_width(L::BorderLeftWidth, border_style.left, |p| p.border_width.left,),
+         },
+         border_style,
+         border_color: Sides {
+            top:    cx.get(L::BorderTopColor, |p| p.border_color.top.clone(), |v| self::color(v, &color,),),
+            right:  cx.get(L::BorderRightColor, |p| p.border_color.right.clone(), |v| self::color(v, &color,),),
+            bottom: cx.get(L::BorderBottomColor, |p| p.border_color.bottom.clone(), |v| self::color(v, &color,),),
+            left:   cx.get(L::BorderLeftColor, |p| p.border_color.left.clone(), |v| self::color(v, &color,),),
+         },
+         background_color: cx.get(L::BackgroundColor, |p| p.background_color.clone(), |v| self::color(v, &color,),),
+         font_family: cx.get(L::FontFamily, |p| p.font_family.clone(), font_family,),
+         font_style: cx.get(L::FontStyle, |p| p.font_style, keyword(FontStyle::from_keyword,),),
+         font_weight: cx.get(L::FontWeight, |p| p.font_weight, |v| font_weight(v, cx.parent_font_weight(),),),
+         line_height: cx.get(L::LineHeight, |p| p.line_height, |v| line_height(v, font_size, root_font_size,),),
+         white_space: cx.get(L::WhiteSpace, |p| p.white_space, keyword(WhiteSpace::from_keyword,),),
+         direction: cx.get(L::Direction, |p| p.direction, keyword(Direction::from_keyword,),),
+         unicode_bidi: cx.get(L::UnicodeBidi, |p| p.unicode_bidi, keyword(UnicodeBidi::from_keyword,),),
+         container_type: cx.get(L::ContainerType, |p| p.container_type, keyword(ContainerType::from_keyword,),),
+         container_name: cx.get(L::ContainerName, |p| p.container_name.clone(), container_name,),
+         font_size,
+         color,
+         custom,
+      }
+   }
+
+   ///Values of a node with nothing declared, such as a text node.
+   pub fn inherit_from(parent: &ComputedValues, root_font_size: f64,) -> ComputedValues {
+      ComputedValues::compute(&PropertyMap::new(), Some(parent,), root_font_size,)
+   }
+}
+
+///Context for computing the values of one node.
+struct Cx<'a,> {
+   declared: &'a PropertyMap,
+   parent:   Option<&'a ComputedValues,>,
+   custom:   &'a HashMap<String, String,>,
+}
+
+///The result of defaulting the declared values of a property.
+enum Cascaded {
+   Val(Value,),
+   Inherit,
+   Initial,
+}
+
+///Pick the winning value among 'decls', resolving 'inherit', 'initial', 'unset'
+/// and 'revert'. 'resolve' prepares a declared value, and a value it rejects
+/// is invalid at computed-value time, acting as 'unset'.
+fn cascaded(decls: &[(Origin, Value,)], inherited: bool, resolve: impl Fn(&Value,) -> Option<Value,>,) -> Cascaded {
+   let mut end = decls.len();
+   //Walk back from the winning declaration while 'revert' rolls back origins.
+   while let Some((origin, val,),) = end.checked_sub(1,).map(|i| &decls[i],) {
+      let Some(val,) = resolve(val,) else { break };
+      match val.keyword() {
+         Some("inherit",) => return Cascaded::Inherit,
+         Some("initial",) => return Cascaded::Initial,
+         Some("unset",) => break,
+         Some("revert",) => end = decls[..end].iter().rposition(|(o, _,)| o < origin,).map_or(0, |i| i + 1,),
+         _ => return Cascaded::Val(val,),
+      }
+   }
+   //Nothing declared, or 'unset'.
+   if inherited {
+      Cascaded::Inherit
+   } else {
+      Cascaded::Initial
+   }
+}
+
+impl Cx<'_,> {
+   ///The specified value of 'id' after resolving 'inherit', 'initial', 'unset'
+   /// and 'revert'. 'None' means the parent's computed value is inherited.
+   fn specified(&self, id: LonghandId,) -> Option<Value,> {
+      match cascaded(self.declared.get(id,), id.inherited(), |val| self.substitute(id, val,),) {
+         Cascaded::Val(val,) => Some(val,),
+         Cascaded::Inherit => None,
+         Cascaded::Initial => Some(id.initial(),),
+      }
+   }
+
+   ///Substitute the 'var()' references of an unparsed value and parse the
+   /// result as a value of 'id'. Other values are returned as they are. 'None'
+   /// if the result can't be parsed or is invalid for 'id'.
+   fn substitute(&self, id: LonghandId, val: &Value,) -> Option<Value,> {
+      let Value::Unparsed { css, shorthand, } = val else { return Some(val.clone(),) };
+      let src = css::substitute_vars(css, &mut |nam| self.custom.get(nam,).cloned(),)?;
+      let decls = css::parse_substituted(shorthand.as_deref().unwrap_or(id.name(),), src,)?;
+      decls.into_iter().find(|decl| decl.id == PropertyId::Longhand(id,),).map(|decl| decl.val,)
+   }
+
+   ///Compute 'id' with 'compute', or take the parent's value through 'inherited'.
+   /// A value 'compute' can't handle falls back to the initial value.
+   fn get<T,>(
+      &self,
+      id: LonghandId,
+      inherited: impl FnOnce(&ComputedValues,) -> T,
+      compute: impl Fn(&Value,) -> Option<T,>,
+   ) -> T {
+      let specified = match (self.specified(id,), self.parent,) {
+         (Some(val,), _,) => val,
+         (None, Some(parent,),) => return inherited(parent,),
+         //The root inherits initial values.
+         (None, None,) => id.initial(),
+      };
+      compute(&specified,)
+         .or_else(|| compute(&id.initial(),),)
+         .unwrap_or_else(|| panic!("invalid initial value for {}", id.name()),)
+   }
+
+   fn parent_font_size(&self,) -> f64 { self.parent.map_or(MEDIUM_FONT_SIZE, |p| p.font_size,) }
+
+   fn parent_font_weight(&self,) -> u16 { self.parent.map_or(400, |p| p.font_weight,) }
+
+   fn parent_color(&self,) -> css::Color { self.parent.map_or(css::Color { r: 0, g: 0, b: 0, a: 255, }, |p| p.color.clone(),) }
+}
+
+//Custom properties. ------------------------
+
+///Compute the custom properties of a node: inherited ones, overridden by its
+/// own declarations with their 'var()' references substituted.
+fn custom_properties(declared: &PropertyMap, parent: Option<&ComputedValues,>,) -> Arc<HashMap<String, String,>,> {
+   let inherited = parent.map(|p| p.custom.clone(),).unwrap_or_default();
+   if declared.custom().is_empty() {
+      return inherited;
+   }
+   let mut resolver =
+      CustomResolver { declared, inherited: &inherited, resolved: HashMap::new(), stack: vec![], in_cycle: HashSet::new(), };
+   let mut custom = (*inherited).clone();
+   for nam in declared.custom().keys() {
+      match resolver.resolve(nam,) {
+         Some(val,) => custom.insert(nam.clone(), val,),
+         None => custom.remove(nam,),
+      };
+   }
+   Arc::new(custom,)
+}
+
+///Substitutes 'var()' in the custom properties declared on one node,
+/// detecting reference cycles.
+struct CustomResolver<'a,> {
+   declared:  &'a PropertyMap,
+   inherited: &'a HashMap<String, String,>,
+   ///Finished properties. 'None' is the guaranteed-invalid value.
+   resolved:  HashMap<String, Option<String,>,>,
+   ///Properties being resolved, innermost last.
+   stack:     Vec<String,>,
+   in_cycle:  HashSet<String,>,
+}
+
+impl CustomResolver<'_,> {
+   ///The computed value of custom property 'nam', or 'None' if it is guaranteed-invalid.
+   fn resolve(&mut self, nam: &str,) -> Option<String,> {
+      if let Some(val,) = self.resolved.get(nam,) {
+         return val.clone();
+      }
+      let Some(decls,) = self.declared.custom().get(nam,) else { return self.inherited.get(nam,).cloned() };
+      if let Some(pos,) = self.stack.iter().position(|n| n == nam,) {
+         //Every property in a cycle is guaranteed-invalid, whatever its fallbacks.
+         self.in_cycle.extend(self.stack[pos..].iter().cloned(),);
+         return None;
+      }
+      let own = match cascaded(decls, true, |val| Some(custom_keyword(val,),),) {
+         Cascaded::Val(Value::Unparsed { css, .. },) => Some(css,),
+         Cascaded::Val(_,) | Cascaded::Initial => None,
+         Cascaded::Inherit => return self.inherited.get(nam,).cloned(),
+      };
+      self.stack.push(nam.to_string(),);
+      let val = own.and_then(|css| css::substitute_vars(&css, &mut |dep| self.resolve(dep,),),);
+      self.stack.pop();
+      let val = val.filter(|_| !self.in_cycle.contains(nam,),);
+      self.resolved.insert(nam.to_string(), val.clone(),);
+      val
+   }
+}
+
+///A custom property's raw value, as a keyword if it is a CSS-wide keyword.
+fn custom_keyword(val: &Value,) -> Value {
+   match val {
+      Value::Unparsed { css, .. } if css::is_css_wide_keyword(css,) => Value::Keyword(css.clone(),),
+      _ => val.clone(),
+   }
+}
+
+impl LonghandId {
+   ///The initial value of the property.
+   pub fn initial(self,) -> Value {
+      css::parse_value(self.initial_src().to_string(),).unwrap_or_else(|| panic!("invalid initial value for {}", self.name()),)
+   }
+}
+
+//Computing single values. ------------------------
+
+///Read a keyword with 'from_keyword'.
+fn keyword<T,>(from_keyword: fn(&str,) -> Option<T,>,) -> impl Fn(&Value,) -> Option<T,> {
+   move |v| v.keyword().and_then(from_keyword,)
+}
+
+fn display(v: &Value,) -> Option<Display,> {
+   match v.keyword()? {
+      "inline" => Some(Display::Inline,),
+      "block" | "list-item" => Some(Display::Block,),
+      "flow-root" => Some(Display::FlowRoot,),
+      "none" => Some(Display::Non,),
+      _ => None,
+   }
+}
+
+///Resolve an absolute or font-relative length.
+fn length(v: &Value, font_size: f64, root_font_size: f64,) -> Option<Length,> {
+   match *v {
+      Value::Length(f, Unit::Em,) => Some(Length::Px(f * font_size,),),
+      Value::Length(f, Unit::Rem,) => Some(Length::Px(f * root_font_size,),),
+      Value::Length(..,) => Some(Length::Px(v.to_px(),),),
+      Value::Percentage(pct,) => Some(Length::Percent(pct,),),
+      //Unitless zero is a valid length.
+      Value::Number(0.0,) => Some(Length::Px(0.0,),),
+      Value::Keyword(ref kw,) if kw == "auto" => Some(Length::Auto,),
+      Value::Calc(ref calc,) if calc.kind()? != CalcKind::Number => Some(match calc.absolutize(font_size, root_font_size,) {
+         Calc::Leaf(Value::Length(px, _,),) => Length::Px(px,),
+         Calc::Leaf(Value::Percentage(pct,),) => Length::Percent(pct,),
+         calc => Length::Calc(Box::new(calc,),),
+      },),
+      _ => None,
+   }
+}
+
+///'auto' as 'None', or an integer.
+fn z_index(v: &Value,) -> Option<Option<i32,>,> {
+   match *v {
+      Value::Keyword(ref kw,) if kw == "auto" => Some(None,),
+      Value::Number(n,) if n.fract() == 0.0 => Some(Some(n as i32,),),
+      _ => None,
+   }
+}
+
+///A number or percentage, clamped to 0 to 1.
+fn opacity(v: &Value,) -> Option<f64,> {
+   match *v {
+      Value::Number(n,) => Some(n.clamp(0.0, 1.0,),),
+      Value::Percentage(pct,) => Some((pct / 100.0).clamp(0.0, 1.0,),),
+      _ => None,
+   }
+}
+
+///'none', or a space-separated list of transform functions.
+fn transform(v: &Value, font_size: f64, root_font_size: f64,) -> Option<Vec<TransformFunction,>,> {
+   let functions = match v {
+      Value::Keyword(kw,) if kw == "none" => return Some(vec![],),
+      Value::List(groups,) => match &groups[..] {
+         [Value::List(functions,)] => &functions[..],
+         _ => return None,
+      },
+      function => std::slice::from_ref(function,),
+   };
+   let len = |v: &Value| length(v, font_size, root_font_size,).filter(|l| *l != Length::Auto,);
+   let num = |v: &Value| match *v {
+      Value::Number(n,) => Some(n,),
+      _ => None,
+   };
+   //Unitless zero is a valid angle here, as it is a length.
+   let angle = |v: &Value| match *v {
+      Value::Angle(deg,) => Some(deg,),
+      Value::Number(0.0,) => Some(0.0,),
+      _ => None,
+   };
+   let zero = || Length::Px(0.0,);
+   functions
+      .iter()
+      .map(|function| {
+         use TransformFunction as T;
+         let Value::Function(nam, args,) = function else { return None };
+         Some(match (&**nam, &args[..],) {
+            ("translate", [x],) => T::Translate(len(x,)?, zero(),),
+            ("translate", [x, y],) => T::Translate(len(x,)?, len(y,)?,),
+            ("translatex", [x],) => T::Translate(len(x,)?, zero(),),
+            ("translatey", [y],) => T::Translate(zero(), len(y,)?,),
+            ("scale", [s],) => T::Scale(num(s,)?, num(s,)?,),
+            ("scale", [x, y],) => T::Scale(num(x,)?, num(y,)?,),
+            ("scalex", [x],) => T::Scale(num(x,)?, 1.0,),
+            ("scaley", [y],) => T::Scale(1.0, num(y,)?,),
+            ("rotate", [a],) => T::Rotate(angle(a,)?,),
+            ("skew", [x],) => T::Skew(angle(x,)?, 0.0,),
+            ("skew", [x, y],) => T::Skew(angle(x,)?, angle(y,)?,),
+            ("skewx", [x],) => T::Skew(angle(x,)?, 0.0,),
+            ("skewy", [y],) => T::Skew(0.0, angle(y,)?,),
+            ("matrix", [a, b, c, d, e, f],) => {
+               T::Matrix(Matrix { a: num(a,)?, b: num(b,)?, c: num(c,)?, d: num(d,)?, e: num(e,)?, f: num(f,)?, },)
+            }
+            _ => return None,
+         },)
+      },)
+      .collect()
+}
+
+fn line_width(v: &Value, font_size: f64, root_font_size: f64,) -> Option<f64,> {
+   match v.keyword() {
+      Some("thin",) => Some(1.0,),
+      Some("medium",) => Some(3.0,),
+      Some("thick",) => Some(5.0,),
+      _ => match length(v, font_size, root_font_size,)? {
+         Length::Px(px,) => Some(px,),
+         _ => None,
+      },
+   }
+}
+
+fn color(v: &Value, current_color: &css::Color,) -> Option<css::Color,> {
+   match v {
+      Value::Color(clr,) => Some(clr.clone(),),
+      Value::Keyword(kw,) if kw == "currentcolor" => Some(current_color.clone(),),
+      _ => None,
+   }
+}
+
+fn font_size(v: &Value, parent_font_size: f64, root_font_size: f64,) -> Option<f64,> {
+   let scale = match v.keyword() {
+      Some("xx-small",) => 9.0 / 16.0,
+      Some("x-small",) => 10.0 / 16.0,
+      Some("small",) => 13.0 / 16.0,
+      Some("medium",) => 1.0,
+      Some("large",) => 18.0 / 16.0,
+      Some("x-large",) => 24.0 / 16.0,
+      Some("xx-large",) => 2.0,
+      Some("larger",) => return Some(parent_font_size * 1.2,),
+      Some("smaller",) => return Some(parent_font_size / 1.2,),
+      //'em' and percentages are relative to the parent's font size.
+      _ => {
+         return match length(v, parent_font_size, root_font_size,)? {
+            Length::Px(px,) => Some(px,),
+            len @ (Length::Percent(_,) | Length::Calc(_,)) => Some(len.to_px(parent_font_size,),),
+            Length::Auto => None,
+         }
+      }
+   };
+   Some(MEDIUM_FONT_SIZE * scale,)
+}
+
+fn font_weight(v: &Value, parent_weight: u16,) -> Option<u16,> {
+   match *v {
+      Value::Number(n,) if (1.0..=1000.0).contains(&n,) => Some(n as u16,),
+      _ => match v.keyword()? {
+         "normal" => Some(400,),
+         "bold" => Some(700,),
+         //Relative weights follow the table in CSS Fonts 4.
+         "bolder" => Some(match parent_weight {
+            0..=349 => 400,
+            350..=549 => 700,
+            _ => 900,
+         },),
+         "lighter" => Some(match parent_weight {
+            0..=549 => 100,
+            550..=749 => 400,
+            _ => 700,
+         },),
+         _ => None,
+      },
+   }
+}
+
+fn font_family(v: &Value,) -> Option<Vec<String,>,> {
+   let family = |v: &Value| match v {
+      Value::Keyword(s,) | Value::Str(s,) => Some(s.clone(),),
+      _ => None,
+   };
+   match v {
+      Value::List(vals,) => vals.iter().map(family,).collect(),
+      _ => Some(vec![family(v,)?],),
+   }
+}
+
+///'none' or a space-separated list of names.
+fn container_name(v: &Value,) -> Option<Vec<String,>,> {
+   match v.keyword()? {
+      "none" => Some(vec![],),
+      names => Some(names.split(' ',).map(str::to_string,).collect(),),
+   }
+}
+
+fn line_height(v: &Value, font_size: f64, root_font_size: f64,) -> Option<LineHeight,> {
+   match *v {
+      Value::Number(n,) => Some(LineHeight::Number(n,),),
+      Value::Keyword(ref kw,) if kw == "normal" => Some(LineHeight::Normal,),
+      //Percentages compute to a length, unlike numbers.
+      Value::Percentage(pct,) => Some(LineHeight::Px(font_size * pct / 100.0,),),
+      _ => match length(v, font_size, root_font_size,)? {
+         Length::Px(px,) => Some(LineHeight::Px(px,),),
+         len @ Length::Calc(_,) => Some(LineHeight::Px(len.to_px(font_size,),),),
+         _ => None,
+      },
+   }
+}
+
+//Validating values. ------------------------
+
+///A length, percentage or 'auto'.
+fn valid_length(v: &Value,) -> bool { length(v, MEDIUM_FONT_SIZE, MEDIUM_FONT_SIZE,).is_some() }
+
+///A non-negative length or percentage, or 'auto'.
+fn valid_size(v: &Value,) -> bool { v.keyword() == Some("auto",) || valid_padding(v,) }
+
+///A non-negative length or percentage.
+fn valid_padding(v: &Value,) -> bool {
+   match length(v, MEDIUM_FONT_SIZE, MEDIUM_FONT_SIZE,) {
+      Some(Length::Px(f,) | Length::Percent(f,),) => f >= 0.0,
+      //The range of a math function is only known once it's resolved.
+      Some(Length::Calc(_,),) => true,
+      _ => false,
+   }
+}
+
+fn valid_line_width(v: &Value,) -> bool { line_width(v, MEDIUM_FONT_SIZE, MEDIUM_FONT_SIZE,).is_some_and(|px| px >= 0.0,) }
+
+fn valid_color(v: &Value,) -> bool { color(v, &css::Color { r: 0, g: 0, b: 0, a: 255, },).is_some() }
+
+fn valid_non_negative_number(v: &Value,) -> bool { matches!(*v, Value::Number(n) if n >= 0.0) }
+
+fn valid_font_stretch(v: &Value,) -> bool {
+   match v {
+      Value::Percentage(pct,) => *pct >= 0.0,
+      _ => matches!(
+         v.keyword(),
+         Some(
+            "normal"
+               | "ultra-condensed"
+               | "extra-condensed"
+               | "condensed"
+               | "semi-condensed"
+               | "semi-expanded"
+               | "expanded"
+               | "extra-expanded"
+               | "ultra-expanded"
+         )
+      ),
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use {
+      super::*,
+      crate::{html, layout, media, style},
+   };
+
+   ///The computed values of the root element of 'src' with the author
+   /// stylesheet 'sheet'.
+   fn computed(src: &str, sheet: &str,) -> ComputedValues {
+      let root = html::parse(src.to_string(),);
+      let sheets = [css::parse(sheet.to_string(), Origin::Author,),];
+      let env = media::MediaEnvironment::new(&layout::Dimensions::default(),);
+      style::style_tree(&root, &sheets, &env, &Default::default(), style::Traversal::Sequential,).computed().clone()
+   }
+
+   ///'width' and 'color' set to 'var(--x)' with '--x: <val>', next to a valid 'height'.
+   fn substituted(val: &str,) -> ComputedValues {
+      computed("<div></div>", &format!("div {{ --x: {val}; width: var(--x); color: var(--x); height: 5px }}"),)
+   }
+
+   #[test]
+   fn unknown_unit_in_substitution_is_unset() {
+      let style = substituted("10vw",);
+      assert!(style.width == Length::Auto);
+      assert!(style.height == Length::Px(5.0));
+   }
+
+   #[test]
+   fn non_value_in_substitution_is_unset() {
+      let style = substituted("@@",);
+      assert!(style.width == Length::Auto);
+      assert!(style.color == css::Color { r: 0, g: 0, b: 0, a: 255, });
+   }
+
+   #[test]
+   fn bad_hex_color_in_substitution_is_unset() {
+      let style = substituted("#12",);
+      assert!(style.color == css::Color { r: 0, g: 0, b: 0, a: 255, });
+      assert!(style.height == Length::Px(5.0));
+   }
+
+   #[test]
+   fn custom_properties_are_inherited_and_substituted() {
+      let style = first_child("<div><p></p></div>", "div { --w: 10px } p { width: var(--w) }",);
+      assert!(style.width == Length::Px(10.0));
+   }
+
+   #[test]
+   fn fallback_is_used_for_missing_variables() {
+      let style = computed("<div></div>", "div { width: var(--nope, 7px) }",);
+      assert!(style.width == Length::Px(7.0));
+   }
+
+   #[test]
+   fn variables_in_a_cycle_are_invalid() {
+      let style = computed("<div></div>", "div { --a: var(--b); --b: var(--a); width: var(--a, 3px); height: var(--b) }",);
+      assert!(style.width == Length::Px(3.0));
+      assert!(style.height == Length::Auto);
+   }
+
+   #[test]
+   fn shorthands_substitute_before_expanding() {
+      let style = computed("<div></div>", "div { --m: 1px 2px; margin: var(--m) }",);
+      assert!(style.margin.top == Length::Px(1.0));
+      assert!(style.margin.right == Length::Px(2.0));
+      assert!(style.margin.left == Length::Px(2.0));
+   }
+
+   ///The computed values of the first child of the root element of 'src'.
+   fn first_child(src: &str, sheet: &str,) -> ComputedValues {
+      let root = html::parse(src.to_string(),);
+      let sheets = [css::parse(sheet.to_string(), Origin::Author,),];
+      let env = media::MediaEnvironment::new(&layout::Dimensions::default(),);
+      style::style_tree(&root, &sheets, &env, &Default::default(), style::Traversal::Sequential,).children[0].computed().clone()
+   }
+
+   const RED: css::Color = css::Color { r: 255, g: 0, b: 0, a: 255, };
+   const BLACK: css::Color = css::Color { r: 0, g: 0, b: 0, a: 255, };
+
+   #[test]
+   fn root_takes_initial_values() {
+      let style = computed("<div></div>", "",);
+      assert!(style.width == Length::Auto);
+      assert!(style.color == BLACK);
+      assert_eq!(style.font_size, MEDIUM_FONT_SIZE);
+      assert_eq!(style.font_weight, 400);
+   }
+
+   #[test]
+   fn only_inherited_properties_take_the_parents_value() {
+      let style = first_child("<div><p></p></div>", "div { color: #ff0000; width: 10px; font-weight: bold }",);
+      assert!(style.color == RED);
+      assert_eq!(style.font_weight, 700);
+      assert!(style.width == Length::Auto);
+   }
+
+   #[test]
+   fn inherit_initial_and_unset() {
+      let sheet = "div { color: #ff0000; width: 10px } p { width: inherit; color: initial }";
+      let style = first_child("<div><p></p></div>", sheet,);
+      assert!(style.width == Length::Px(10.0));
+      assert!(style.color == BLACK);
+
+      let sheet = "div { color: #ff0000; width: 10px } p { width: 5px; width: unset; color: unset }";
+      let style = first_child("<div><p></p></div>", sheet,);
+      assert!(style.width == Length::Auto);
+      assert!(style.color == RED);
+   }
+
+   #[test]
+   fn revert_rolls_back_to_the_user_agent_origin() {
+      let style = first_child("<div><p></p></div>", "p { display: none } p { display: revert }",);
+      assert!(style.display == Display::Block);
+   }
+
+   #[test]
+   fn font_relative_lengths_resolve_against_the_font_size() {
+      let sheet = "div { font-size: 20px } p { font-size: 150%; width: 2em; margin-top: 1rem; line-height: 50% }";
+      let style = first_child("<div><p></p></div>", sheet,);
+      assert_eq!(style.font_size, 30.0);
+      assert!(style.width == Length::Px(60.0));
+      assert!(style.margin.top == Length::Px(20.0));
+      assert!(style.line_height == LineHeight::Px(15.0));
+   }
+
+   #[test]
+   fn borders_without_a_style_have_no_width() {
+      let style = computed("<div></div>", "div { border-top-width: 5px; border-left-width: 5px; border-left-style: solid }",);
+      assert_eq!(style.border_width.top, 0.0);
+      assert_eq!(style.border_width.left, 5.0);
+   }
+
+   #[test]
+   fn currentcolor_is_the_elements_color() {
+      let style = computed("<div></div>", "div { color: #ff0000; background-color: currentcolor }",);
+      assert!(style.background_color == RED);
+   }
+
+   #[test]
+   fn longhands_are_found_by_name() {
+      let id = LonghandId::from_name("margin-top",).unwrap();
+      assert_eq!(id.name(), "margin-top");
+      assert!(!id.inherited());
+      assert!(LonghandId::from_name("color",).unwrap().inherited());
+      assert!(LonghandId::from_name("margin",).is_none());
+   }
+
+   #[test]
+   fn invalid_values_are_dropped_at_parse_time() {
+      let decls = css::parse_inline(
+         "width: 10px; width: -5px; height: red; padding-top: 2px; padding-top: -1px; color: 12px; flex-basis: -1px".to_string(),
+      );
+      let names: Vec<&str,> = decls.iter().map(|decl| decl.id.name(),).collect();
+      assert_eq!(names, ["width", "padding-top"]);
+      assert!(computed("<div></div>", "div { width: 10px; width: -5px }",).width == Length::Px(10.0));
+   }
+
+   #[test]
+   fn css_wide_keywords_are_valid_everywhere() {
+      assert_eq!(css::parse_inline("width: inherit; color: unset; padding: initial".to_string(),).len(), 6);
+   }
+}
@@ -0,0 +1,207 @@
+#![allow(unused)]
+use crate::{
+   css, dom, layout,
+   properties::{ComputedValues, Length, LineHeight, LonghandId},
+   style, transform,
+};
+
+///The resolved values of a node's properties, as 'getComputedStyle' returns
+/// them: computed values, except for the box sizes layout decides, which are
+/// used values in px.
+pub struct ResolvedStyle {
+   computed: ComputedValues,
+   ///The box dimensions, if the node generated a block box.
+   block:    Option<layout::Dimensions,>,
+}
+
+///The resolved style of 'node', drawing on its style in 'style_root' and its
+/// box in 'layout_root'. 'None' if 'node' isn't in the style tree.
+pub fn resolved_style(
+   node: &dom::Node,
+   style_root: &style::StyledNode,
+   layout_root: &layout::LayoutBox,
+) -> Option<ResolvedStyle,> {
+   let styled = find_styled(style_root, node.id(),)?;
+   Some(ResolvedStyle { computed: styled.computed().clone(), block: find_block(layout_root, node.id(),).cloned(), },)
+}
+
+fn find_styled<'s, 'a,>(styled: &'s style::StyledNode<'a,>, id: dom::NodeId,) -> Option<&'s style::StyledNode<'a,>,> {
+   if styled.node().id() == id {
+      return Some(styled,);
+   }
+   styled.children.iter().find_map(|child| find_styled(child, id,),)
+}
+
+fn find_block<'l,>(layout_box: &'l layout::LayoutBox, id: dom::NodeId,) -> Option<&'l layout::Dimensions,> {
+   match layout_box.box_type {
+      layout::BoxType::BlockNode(styled,) if styled.node().id() == id => Some(&layout_box.dimensions,),
+      _ => layout_box.children.iter().find_map(|child| find_block(child, id,),),
+   }
+}
+
+impl ResolvedStyle {
+   ///The resolved value of the longhand or custom property 'nam'. 'None' for
+   /// shorthands, unknown properties, and longhands layout doesn't use yet.
+   pub fn get(&self, nam: &str,) -> Option<String,> {
+      if nam.starts_with("--",) {
+         return self.computed.custom.get(nam,).cloned();
+      }
+      self.longhand(LonghandId::from_name(nam,)?,)
+   }
+
+   ///The resolved values of all the longhands 'get' knows, by name.
+   pub fn values(&self,) -> Vec<(&'static str, String,),> {
+      LonghandId::ALL.iter().filter_map(|&id| Some((id.name(), self.longhand(id,)?,),),).collect()
+   }
+
+   fn longhand(&self, id: LonghandId,) -> Option<String,> {
+      use LonghandId as L;
+      let c = &self.computed;
+      //Sizes of block boxes are the used values, and others the computed ones.
+      let used = |val: &Length, used: fn(&layout::Dimensions,) -> f64| match &self.block {
+         Some(d,) => px(used(d,),),
+         None => val.to_string(),
+      };
+      Some(match id {
+         L::Display => match c.display {
+            style::Display::Block => "block",
+            style::Display::FlowRoot => "flow-root",
+            style::Display::Inline => "inline",
+            style::Display::Non => "none",
+         }
+         .to_string(),
+         L::Width => used(&c.width, |d| d.content.width,),
+         L::Height => used(&c.height, |d| d.content.height,),
+         L::Position => c.position.keyword().to_string(),
+         L::Top => c.inset.top.to_string(),
+         L::Right => c.inset.right.to_string(),
+         L::Bottom => c.inset.bottom.to_string(),
+         L::Left => c.inset.left.to_string(),
+         L::ZIndex => c.z_index.map_or("auto".to_string(), |z| z.to_string(),),
+         L::Opacity => c.opacity.to_string(),
+         //A transform resolves to the matrix it makes, with translations of the border box.
+         L::Transform if c.transform.is_empty() => "none".to_string(),
+         L::Transform => {
+            let border_box = self.block.as_ref().map(layout::Dimensions::border_box,).unwrap_or_default();
+            transform::compose(&c.transform, border_box.width, border_box.height,).to_string()
+         }
+         L::Float => c.float.keyword().to_string(),
+         L::Clear => c.clear.keyword().to_string(),
+         L::OverflowX => c.overflow_x.keyword().to_string(),
+         L::OverflowY => c.overflow_y.keyword().to_string(),
+         L::MarginTop => used(&c.margin.top, |d| d.margin.top,),
+         L::MarginRight => used(&c.margin.right, |d| d.margin.right,),
+         L::MarginBottom => used(&c.margin.bottom, |d| d.margin.bottom,),
+         L::MarginLeft => used(&c.margin.left, |d| d.margin.left,),
+         L::PaddingTop => used(&c.padding.top, |d| d.padding.top,),
+         L::PaddingRight => used(&c.padding.right, |d| d.padding.right,),
+         L::PaddingBottom => used(&c.padding.bottom, |d| d.padding.bottom,),
+         L::PaddingLeft => used(&c.padding.left, |d| d.padding.left,),
+         L::BorderTopWidth => px(c.border_width.top,),
+         L::BorderRightWidth => px(c.border_width.right,),
+         L::BorderBottomWidth => px(c.border_width.bottom,),
+         L::BorderLeftWidth => px(c.border_width.left,),
+         L::BorderTopStyle => c.border_style.top.keyword().to_string(),
+         L::BorderRightStyle => c.border_style.right.keyword().to_string(),
+         L::BorderBottomStyle => c.border_style.bottom.keyword().to_string(),
+         L::BorderLeftStyle => c.border_style.left.keyword().to_string(),
+         L::BorderTopColor => rgb(&c.border_color.top,),
+         L::BorderRightColor => rgb(&c.border_color.right,),
+         L::BorderBottomColor => rgb(&c.border_color.bottom,),
+         L::BorderLeftColor => rgb(&c.border_color.left,),
+         L::BackgroundColor => rgb(&c.background_color,),
+         L::Color => rgb(&c.color,),
+         L::FontFamily => c.font_family.join(", ",),
+         L::FontSize => px(c.font_size,),
+         L::FontStyle => c.font_style.keyword().to_string(),
+         L::FontWeight => c.font_weight.to_string(),
+         //A number resolves to the used line height.
+         L::LineHeight => match c.line_height {
+            LineHeight::Normal => "normal".to_string(),
+            LineHeight::Number(n,) => px(n * c.font_size,),
+            LineHeight::Px(h,) => px(h,),
+         },
+         L::WhiteSpace => c.white_space.keyword().to_string(),
+         L::Direction => c.direction.keyword().to_string(),
+         L::UnicodeBidi => c.unicode_bidi.keyword().to_string(),
+         L::ContainerType => c.container_type.keyword().to_string(),
+         L::ContainerName if c.container_name.is_empty() => "none".to_string(),
+         L::ContainerName => c.container_name.join(" ",),
+         _ => return None,
+      },)
+   }
+}
+
+fn px(v: f64,) -> String { format!("{v}px") }
+
+///'rgb(r, g, b)', or 'rgba(r, g, b, a)' if not opaque, with the shortest alpha
+/// that maps back to the same byte.
+fn rgb(c: &css::Color,) -> String {
+   if c.a == 255 {
+      return format!("rgb({}, {}, {})", c.r, c.g, c.b);
+   }
+   let alpha = [100.0, 1000.0,]
+      .iter()
+      .map(|scale| (c.a as f64 / 255.0 * scale).round() / scale,)
+      .find(|alpha| (alpha * 255.0).round() as u8 == c.a,)
+      .unwrap_or(c.a as f64 / 255.0,);
+   format!("rgba({}, {}, {}, {alpha})", c.r, c.g, c.b)
+}
+
+#[cfg(test)]
+mod tests {
+   use {
+      super::*,
+      crate::{font, html, media},
+   };
+
+   ///The resolved values of 'names' of the element '#id' of 'src', styled with
+   /// 'sheet' and laid out in an 800 by 600 viewport.
+   fn resolved(src: &str, sheet: &str, id: &str, names: &[&str],) -> Vec<String,> {
+      fn find<'a,>(node: &'a dom::Node, id: &str,) -> Option<&'a dom::Node,> {
+         match node.node_type {
+            dom::NodeType::Element(ref elem,) if elem.id().is_some_and(|i| i == id,) => Some(node,),
+            _ => node.children.iter().find_map(|child| find(child, id,),),
+         }
+      }
+      let root = html::parse(src.to_string(),);
+      let sheets = [css::parse(sheet.to_string(), css::Origin::Author,),];
+      let mut viewport = layout::Dimensions::default();
+      viewport.content.width = 800.0;
+      viewport.content.height = 600.0;
+      let env = media::MediaEnvironment::new(&viewport,);
+      let style_root = style::style_tree(&root, &sheets, &env, &Default::default(), style::Traversal::Sequential,);
+      let fonts = font::FontDatabase::new();
+      let layout_root = layout::layout_tree(&style_root, viewport, &fonts,);
+      let style = resolved_style(find(&root, id,).unwrap(), &style_root, &layout_root,).unwrap();
+      names.iter().map(|nam| style.get(nam,).unwrap(),).collect()
+   }
+
+   const DOC: &str = "<html><body><div id=\"outer\"><div id=\"inner\"><span id=\"s\"></span></div></div></body></html>";
+
+   #[test]
+   fn widths_resolve_to_used_px() {
+      let sheet = "body { margin: 0 } #outer { width: 600px; padding: 10px } #inner { width: 50%; height: 3em; font-size: 10px }";
+      assert_eq!(resolved(DOC, sheet, "outer", &["width", "padding-left"],), ["600px", "10px"]);
+      assert_eq!(resolved(DOC, sheet, "inner", &["width", "height"],), ["300px", "30px"]);
+   }
+
+   #[test]
+   fn auto_sizes_resolve_against_layout() {
+      let sheet = "body { margin: 0 } #outer { padding-left: 5% } #inner { width: 200px; margin: 0 auto }";
+      assert_eq!(resolved(DOC, sheet, "outer", &["width", "padding-left"],), ["760px", "40px"]);
+      assert_eq!(resolved(DOC, sheet, "inner", &["margin-left", "margin-right"],), ["280px", "280px"]);
+   }
+
+   #[test]
+   fn inline_boxes_keep_computed_values() {
+      let sheet = "#s { width: 50%; margin-left: 2em; font-size: 10px; line-height: 1.5; color: #ff000080 }";
+      assert_eq!(resolved(DOC, sheet, "s", &["width", "margin-left", "line-height", "color", "display"],), [
+         "50%",
+         "20px",
+         "15px",
+         "rgba(255, 0, 0, 0.5)",
+         "inline"
+      ]);
+   }
+}
@@ -0,0 +1,55 @@
+#![allow(unused)]
+use std::{env, fs, path::PathBuf};
+
+///Fetches resources a page refers to by URL, such as font files.
+pub trait ResourceLoader {
+   ///The resource's bytes, or 'None' if it can't be loaded.
+   fn load(&self, url: &str,) -> Option<Vec<u8,>,>;
+
+   ///Directories installed fonts are read from, which 'local()' sources and
+   /// font fallback use. None by default.
+   fn font_dirs(&self,) -> Vec<PathBuf,> { vec![] }
+}
+
+///Loads 'file:' URLs and relative paths from the local file system.
+pub struct FileLoader {
+   ///Directory relative URLs are resolved against, usually that of the document.
+   pub base:      PathBuf,
+   pub font_dirs: Vec<PathBuf,>,
+}
+
+///The directories in the 'FONT_PATH' environment variable, separated as in
+/// 'PATH', or else where the platform usually installs fonts.
+pub fn system_font_dirs() -> Vec<PathBuf,> {
+   if let Some(paths,) = env::var_os("FONT_PATH",) {
+      return env::split_paths(&paths,).collect();
+   }
+   let home = env::var_os("HOME",).map(PathBuf::from,);
+   match env::consts::OS {
+      "windows" => vec![PathBuf::from("C:\\Windows\\Fonts",)],
+      "macos" => ["/System/Library/Fonts", "/Library/Fonts",]
+         .into_iter()
+         .map(PathBuf::from,)
+         .chain(home.map(|home| home.join("Library/Fonts",),),)
+         .collect(),
+      _ => ["/usr/share/fonts", "/usr/local/share/fonts",]
+         .into_iter()
+         .map(PathBuf::from,)
+         .chain(home.map(|home| home.join(".local/share/fonts",),),)
+         .collect(),
+   }
+}
+
+impl ResourceLoader for FileLoader {
+   fn load(&self, url: &str,) -> Option<Vec<u8,>,> {
+      let path = match url.strip_prefix("file://",) {
+         Some(path,) => PathBuf::from(path,),
+         //Other schemes, such as 'http:', need a network.
+         None if url.contains("://",) => return None,
+         None => self.base.join(url,),
+      };
+      fs::read(path,).ok()
+   }
+
+   fn font_dirs(&self,) -> Vec<PathBuf,> { self.font_dirs.clone() }
+}
@@ -1,97 +1,1152 @@
 #![allow(unused)]
-use crate::{css, dom};
+use std::{
+   collections::HashMap,
+   sync::{Arc, OnceLock},
+};
 
-///Map from CSS property names to values.
-type PropertyMap = std::collections::HashMap<String, css::Value,>;
+use rayon::prelude::*;
+
+use crate::{
+   css, dom,
+   media::MediaEnvironment,
+   properties::{ComputedValues, LonghandId, PropertyId, MEDIUM_FONT_SIZE},
+};
+
+///Map from longhand properties to the declared values that apply to an element,
+/// from lowest to highest precedence, along with their origins.
+#[derive(PartialEq,)]
+pub struct PropertyMap {
+   decls:  Vec<Vec<(css::Origin, css::Value,),>,>,
+   custom: HashMap<String, Vec<(css::Origin, css::Value,),>,>,
+}
+
+impl Default for PropertyMap {
+   fn default() -> PropertyMap { PropertyMap::new() }
+}
+
+impl PropertyMap {
+   pub fn new() -> PropertyMap { PropertyMap { decls: vec![vec![]; LonghandId::ALL.len()], custom: HashMap::new(), } }
+
+   ///Declared values of 'id', the winning one last.
+   pub fn get(&self, id: LonghandId,) -> &[(css::Origin, css::Value,)] { &self.decls[id as usize] }
+
+   ///Declared values of custom properties, by name.
+   pub fn custom(&self,) -> &HashMap<String, Vec<(css::Origin, css::Value,),>,> { &self.custom }
+
+   fn push(&mut self, id: &PropertyId, origin: css::Origin, val: css::Value,) {
+      match id {
+         PropertyId::Longhand(id,) => self.decls[*id as usize].push((origin, val,),),
+         PropertyId::Custom(nam,) => self.custom.entry(nam.clone(),).or_default().push((origin, val,),),
+      }
+   }
+}
 ///Tuple from 'Specificity' and matched 'Rule'
 type MatchedRule<'a,> = (css::Specificity, &'a css::Rule,);
 
 ///Layout property
+#[derive(Clone, Copy, PartialEq, Eq,)]
 pub enum Display {
    Inline,
    Block,
+   ///A block that establishes a block formatting context for its contents.
+   FlowRoot,
    Non,
 }
 
 ///A node with associated style data.
 pub struct StyledNode<'a,> {
    node:             &'a dom::Node, //Pointer to a dom node
-   specified_values: PropertyMap,
+   ///Shared with siblings that have the same style.
+   specified_values: Arc<PropertyMap,>,
+   computed:         Arc<ComputedValues,>,
+   ///Whether the style differs from the one 'restyle' started from.
+   changed:          bool,
    pub children:     Vec<StyledNode<'a,>,>,
 }
 
 impl<'a,> StyledNode<'a,> {
-   ///Return the specified value of a property if it exists, otherwise 'Non'.
-   pub fn val(&self, nam: &str,) -> Option<css::Value,> { self.specified_values.get(nam,).map(|v| v.clone(),) }
+   ///Return the winning declared value of a property if it exists, otherwise 'Non'.
+   pub fn val(&self, id: LonghandId,) -> Option<css::Value,> {
+      self.specified_values.get(id,).last().map(|(_, val,)| val.clone(),)
+   }
+
+   ///The DOM node this style belongs to.
+   pub fn node(&self,) -> &'a dom::Node { self.node }
+
+   ///The computed values, with inheritance and defaulting applied.
+   pub fn computed(&self,) -> &ComputedValues { &self.computed }
 
    ///The value of the 'display' property (defaults to inline).
-   pub fn display(&self,) -> Display {
-      use css::Value;
-      match self.val("display",) {
-         Some(Value::Keyword(s,),) => match &*s {
-            "block" => Display::Block,
-            "none" => Display::Non,
-            _ => Display::Inline,
-         },
-         _ => Display::Inline,
-      }
+   pub fn display(&self,) -> Display { self.computed.display }
+
+   ///Did the node's style change since the previous styling? Always true after
+   /// 'style_tree', and after 'restyle' only for nodes whose specified or
+   /// computed values differ from the old ones. 'layout::layout_tree' does not
+   /// read it; see there for when layout can be skipped.
+   pub fn changed(&self,) -> bool { self.changed }
+
+   ///Keep the styles of the tree for a later 'restyle'.
+   pub fn snapshot(&self,) -> StyleSnapshot {
+      let mut styles = HashMap::new();
+      self.collect_styles(&mut styles,);
+      StyleSnapshot { styles, }
    }
 
-   pub fn lookup(&self, nam: &str, fallback_nam: &str, dflt: &css::Value,) -> css::Value {
-      self.val(nam,).unwrap_or_else(|| self.val(fallback_nam,).unwrap_or_else(|| dflt.clone(),),)
+   fn collect_styles(&self, out: &mut HashMap<dom::NodeId, (Arc<PropertyMap,>, Arc<ComputedValues,>,),>,) {
+      out.insert(self.node.id(), (self.specified_values.clone(), self.computed.clone(),),);
+      for child in &self.children {
+         child.collect_styles(out,);
+      }
    }
 }
 
-///Tell whether selector matches element
-fn matches(elem: &dom::ElementData, slctr: &css::Selector,) -> bool {
+///The styles of a tree by node, which outlive the tree's borrow of the DOM so
+/// the DOM can be changed and restyled.
+pub struct StyleSnapshot {
+   styles: HashMap<dom::NodeId, (Arc<PropertyMap,>, Arc<ComputedValues,>,),>,
+}
+
+///Tell whether selector matches element. 'ancestors' are the element's
+/// ancestors, nearest last.
+fn matches(elem: &dom::ElementData, slctr: &css::Selector, ancestors: &[&dom::ElementData],) -> bool {
    match *slctr {
       css::Selector::Simple(ref smpl_slctr,) => matches_ss(elem, smpl_slctr,),
+      css::Selector::Complex(ref compounds, ref subject,) => {
+         matches_ss(elem, subject,) && matches_ancestors(compounds, ancestors,)
+      }
+   }
+}
+
+///Do the simple selectors left of a subject match, the last one starting from
+/// the nearest of 'ancestors'?
+fn matches_ancestors(compounds: &[(css::SimpleSelector, css::Combinator,)], ancestors: &[&dom::ElementData],) -> bool {
+   let Some(((slctr, combinator,), rest,),) = compounds.split_last() else { return true };
+   match combinator {
+      css::Combinator::Child => {
+         ancestors.split_last().is_some_and(|(parent, above,)| matches_ss(parent, slctr,) && matches_ancestors(rest, above,),)
+      }
+      css::Combinator::Descendant => {
+         (0..ancestors.len()).rev().any(|i| matches_ss(ancestors[i], slctr,) && matches_ancestors(rest, &ancestors[..i],),)
+      }
    }
 }
 
 ///If all of class, id, tag_name match, return true
 fn matches_ss(elem: &dom::ElementData, slctr: &css::SimpleSelector,) -> bool {
-   slctr.tag_name.iter().any(|nam| elem.tag_name != *nam,) && //Check type selector
-   slctr.id.iter().any(|id| elem.id()!=Some(id)) && //Check id selector
-   slctr.class.iter().any(|cls| elem.classes().contains(&**cls)) //Check class
+   slctr.tag_name.iter().all(|nam| elem.tag_name == *nam,) && //Check type selector
+   slctr.id.iter().all(|id| elem.id()==Some(id)) && //Check id selector
+   slctr.class.iter().all(|cls| elem.classes().contains(&**cls)) //Check class
 }
 
 ///If 'rule' matches 'elem', return a 'MatchedRule'. Otherwise return 'None'.
-fn match_rule<'a,>(elem: &dom::ElementData, rule: &'a css::Rule,) -> Option<MatchedRule<'a,>,> {
+/// 'hashes' are the 'ancestor_hashes' of each of the rule's selectors.
+fn match_rule<'a,>(
+   elem: &dom::ElementData,
+   rule: &'a css::Rule,
+   hashes: &[Vec<u32,>],
+   ancestors: &Ancestors,
+) -> Option<MatchedRule<'a,>,> {
    //Find the first (highest-specificity) matching selector.
-   rule.selectors.iter().find(|slctr| matches(elem, slctr,),).map(|slctr| (slctr.specificity(), rule,),)
+   rule
+      .selectors
+      .iter()
+      .zip(hashes,)
+      .find(|(slctr, hashes,)| {
+         hashes.iter().all(|&hash| ancestors.filter.might_contain(hash,),) && matches(elem, slctr, &ancestors.elems,)
+      },)
+      .map(|(slctr, _,)| (slctr.specificity(), rule,),)
 }
 
-///Find all CSS rules that match the given element
-fn matching_rules<'a,>(elem: &dom::ElementData, stylesheet: &'a css::Stylesheet,) -> Vec<MatchedRule<'a,>,> {
-   stylesheet.rules.iter().filter_map(|rule| match_rule(elem, rule,),).collect()
+///Number of counters in an 'AncestorFilter'. Each key sets two of them.
+const FILTER_SIZE: usize = 4096;
+
+///A counting Bloom filter of the ids, classes and tags of an element's
+/// ancestors. A selector needing an ancestor key that isn't in the filter can't
+/// match, which rules out most descendant selectors without walking up the tree.
+#[derive(Clone,)]
+struct AncestorFilter {
+   counts: Box<[u8; FILTER_SIZE],>,
+}
+
+impl AncestorFilter {
+   fn new() -> AncestorFilter { AncestorFilter { counts: Box::new([0; FILTER_SIZE],), } }
+
+   fn slots(hash: u32,) -> [usize; 2] { [hash as usize % FILTER_SIZE, (hash >> 16) as usize % FILTER_SIZE,] }
+
+   fn insert(&mut self, hash: u32,) {
+      for slot in AncestorFilter::slots(hash,) {
+         self.counts[slot] = self.counts[slot].saturating_add(1,);
+      }
+   }
+
+   fn remove(&mut self, hash: u32,) {
+      for slot in AncestorFilter::slots(hash,) {
+         //A saturated counter may stand for more keys than it counts, so it stays set.
+         if self.counts[slot] != u8::MAX {
+            self.counts[slot] -= 1;
+         }
+      }
+   }
+
+   ///False if no ancestor has the key, true if one may have it.
+   fn might_contain(&self, hash: u32,) -> bool { AncestorFilter::slots(hash,).iter().all(|&slot| self.counts[slot] > 0,) }
+}
+
+///Hash of an id, class or tag, which 'kind' tells apart. 32-bit FNV-1a.
+fn key_hash(kind: u8, nam: &str,) -> u32 {
+   [kind,].iter().chain(nam.as_bytes(),).fold(0x811c9dc5, |hash, &b| (hash ^ b as u32).wrapping_mul(0x01000193,),)
+}
+
+///Hashes of the keys an element has, or a simple selector requires.
+fn simple_hashes<'s,>(tag_name: Option<&str,>, id: Option<&str,>, classes: impl Iterator<Item = &'s str,>,) -> Vec<u32,> {
+   let tag = tag_name.map(|tag| key_hash(b't', tag,),);
+   let id = id.map(|id| key_hash(b'#', id,),);
+   tag.into_iter().chain(id,).chain(classes.map(|class| key_hash(b'.', class,),),).collect()
+}
+
+fn element_hashes(elem: &dom::ElementData,) -> Vec<u32,> {
+   simple_hashes(Some(&elem.tag_name,), elem.id().map(|id| &**id,), elem.classes().into_iter(),)
+}
+
+///Hashes of the keys that ancestors of a matching element must have.
+fn ancestor_hashes(slctr: &css::Selector,) -> Vec<u32,> {
+   let css::Selector::Complex(compounds, _,) = slctr else { return vec![] };
+   compounds
+      .iter()
+      .flat_map(|(simple, _,)| {
+         simple_hashes(simple.tag_name.as_deref(), simple.id.as_deref(), simple.class.iter().map(|class| &**class,),)
+      },)
+      .collect()
+}
+
+///The elements above the one being styled, nearest last, with a filter of their keys.
+#[derive(Clone,)]
+struct Ancestors<'a,> {
+   elems:  Vec<&'a dom::ElementData,>,
+   filter: AncestorFilter,
+}
+
+impl<'a,> Ancestors<'a,> {
+   fn new() -> Ancestors<'a,> { Ancestors { elems: vec![], filter: AncestorFilter::new(), } }
+
+   fn push(&mut self, elem: &'a dom::ElementData,) {
+      for hash in element_hashes(elem,) {
+         self.filter.insert(hash,);
+      }
+      self.elems.push(elem,);
+   }
+
+   fn pop(&mut self,) {
+      let elem = self.elems.pop().expect("no ancestor to pop",);
+      for hash in element_hashes(elem,) {
+         self.filter.remove(hash,);
+      }
+   }
+}
+
+///Number of recently styled siblings 'SharingCache' remembers.
+const SHARING_CACHE_SIZE: usize = 8;
+
+///Styles of recently styled siblings. An element with the same tag and
+/// attributes as a sibling matches the same rules, since selectors can't tell
+/// them apart by anything else, so it can reuse the sibling's style.
+#[derive(Default,)]
+struct SharingCache<'a,> {
+   entries: Vec<(&'a dom::ElementData, Arc<PropertyMap,>, Arc<ComputedValues,>,),>,
+}
+
+impl<'a,> SharingCache<'a,> {
+   fn get(&self, elem: &dom::ElementData,) -> Option<(Arc<PropertyMap,>, Arc<ComputedValues,>,),> {
+      self
+         .entries
+         .iter()
+         .find(|(sibling, ..,)| *sibling == elem,)
+         .map(|(_, specified, computed,)| (specified.clone(), computed.clone(),),)
+   }
+
+   fn insert(&mut self, elem: &'a dom::ElementData, specified: &Arc<PropertyMap,>, computed: &Arc<ComputedValues,>,) {
+      if self.entries.len() == SHARING_CACHE_SIZE {
+         self.entries.remove(0,);
+      }
+      self.entries.push((elem, specified.clone(), computed.clone(),),);
+   }
+}
+
+///Origin and importance of a declaration, from lowest to highest precedence.
+/// '!important' reverses the order of origins.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord,)]
+enum CascadeLevel {
+   UserAgent,
+   User,
+   Author,
+   AuthorImportant,
+   UserImportant,
+   UserAgentImportant,
+}
+
+impl CascadeLevel {
+   fn new(origin: css::Origin, important: bool,) -> CascadeLevel {
+      use css::Origin;
+      match (origin, important,) {
+         (Origin::UserAgent, false,) => CascadeLevel::UserAgent,
+         (Origin::User, false,) => CascadeLevel::User,
+         (Origin::Author, false,) => CascadeLevel::Author,
+         (Origin::Author, true,) => CascadeLevel::AuthorImportant,
+         (Origin::User, true,) => CascadeLevel::UserImportant,
+         (Origin::UserAgent, true,) => CascadeLevel::UserAgentImportant,
+      }
+   }
+
+   fn origin(self,) -> css::Origin {
+      use css::Origin;
+      match self {
+         CascadeLevel::UserAgent | CascadeLevel::UserAgentImportant => Origin::UserAgent,
+         CascadeLevel::User | CascadeLevel::UserImportant => Origin::User,
+         CascadeLevel::Author | CascadeLevel::AuthorImportant => Origin::Author,
+      }
+   }
+}
+
+///Sort key of a declaration in the cascade: origin and importance, then whether
+/// it comes from the 'style' attribute, then cascade layer, then specificity,
+/// then source order. The declaration with the greatest key wins.
+type CascadeKey = (CascadeLevel, bool, usize, css::Specificity, usize,);
+
+///A style rule that applies in the current environment, taken out of the
+/// '@media', '@supports' and '@layer' rules around it.
+struct CascadeRule<'a,> {
+   rule:       &'a css::Rule,
+   origin:     css::Origin,
+   ///Rank of the rule's layer in the cascade. Unlayered rules rank highest.
+   layer:      usize,
+   ///'@container' rules around the rule, innermost last. All must match.
+   containers: Vec<&'a css::ContainerRule,>,
+}
+
+///The rules of 'cascade_rules', bucketed by the id, class or tag of the
+/// subject of each of their selectors, so an element only tests rules that share one with it.
+struct RuleIndex<'a,> {
+   rules:     Vec<CascadeRule<'a,>,>,
+   ///'ancestor_hashes' of each selector of each rule.
+   hashes:    Vec<Vec<Vec<u32,>,>,>,
+   by_id:     HashMap<&'a str, Vec<usize,>,>,
+   by_class:  HashMap<&'a str, Vec<usize,>,>,
+   by_tag:    HashMap<&'a str, Vec<usize,>,>,
+   ///Rules with a selector like '*' that has none of the above.
+   universal: Vec<usize,>,
+}
+
+impl<'a,> RuleIndex<'a,> {
+   ///Put each rule in one bucket per selector: that of the subject's id if it
+   /// has one, else of its first class, else of its tag. An element must have
+   /// that key for the selector to match.
+   fn new(rules: Vec<CascadeRule<'a,>,>,) -> RuleIndex<'a,> {
+      let mut index = RuleIndex {
+         rules:     vec![],
+         hashes:    rules.iter().map(|rule| rule.rule.selectors.iter().map(ancestor_hashes,).collect(),).collect(),
+         by_id:     HashMap::new(),
+         by_class:  HashMap::new(),
+         by_tag:    HashMap::new(),
+         universal: vec![],
+      };
+      for (i, rule,) in rules.iter().enumerate() {
+         for simple in rule.rule.selectors.iter().map(css::Selector::subject,) {
+            let bucket = match (&simple.id, simple.class.first(), &simple.tag_name,) {
+               (Some(id,), ..,) => index.by_id.entry(id,).or_default(),
+               (None, Some(class,), _,) => index.by_class.entry(class,).or_default(),
+               (None, None, Some(tag,),) => index.by_tag.entry(tag,).or_default(),
+               (None, None, None,) => &mut index.universal,
+            };
+            //Selectors of the same rule may share a bucket.
+            if bucket.last() != Some(&i,) {
+               bucket.push(i,);
+            }
+         }
+      }
+      index.rules = rules;
+      index
+   }
+
+   ///Indices in 'rules' of the rules that may match 'elem', in source order.
+   fn candidates(&self, elem: &dom::ElementData,) -> Vec<usize,> {
+      let mut candidates = self.universal.clone();
+      let mut add = |bucket: Option<&Vec<usize,>,>| candidates.extend(bucket.into_iter().flatten(),);
+      add(elem.id().and_then(|id| self.by_id.get(&**id,),),);
+      for class in elem.classes() {
+         add(self.by_class.get(class,),);
+      }
+      add(self.by_tag.get(&*elem.tag_name,),);
+      candidates.sort_unstable();
+      candidates.dedup();
+      candidates
+   }
+}
+
+///Layers by name, each with its sublayers in the order they first appear.
+#[derive(Default,)]
+struct LayerTree {
+   sublayers: Vec<(String, LayerTree,),>,
+}
+
+impl LayerTree {
+   ///Add the layer at 'path', and any of its parents, unless already known.
+   fn register(&mut self, path: &[String],) {
+      if let Some((nam, rest,),) = path.split_first() {
+         let i = match self.sublayers.iter().position(|(n, _,)| n == nam,) {
+            Some(i,) => i,
+            None => {
+               self.sublayers.push((nam.clone(), LayerTree::default(),),);
+               self.sublayers.len() - 1
+            }
+         };
+         self.sublayers[i].1.register(rest,);
+      }
+   }
+
+   ///Rank every layer in cascade order. Sublayers come before the rules placed
+   /// directly in their parent, so the unlayered rules at 'path' rank last.
+   fn ranks(&self, path: &mut Vec<String,>, out: &mut HashMap<Vec<String,>, usize,>,) {
+      for (nam, sublayer,) in &self.sublayers {
+         path.push(nam.clone(),);
+         sublayer.ranks(path, out,);
+         path.pop();
+      }
+      out.insert(path.clone(), out.len(),);
+   }
+}
+
+///Collect the style rules of 'stylesheets' that apply in 'env', in source order.
+fn cascade_rules<'a,>(stylesheets: &[&'a css::Stylesheet], env: &MediaEnvironment,) -> Vec<CascadeRule<'a,>,> {
+   let mut layers = LayerTree::default();
+   let mut rules = vec![];
+   let mut anonymous = 0;
+   for sheet in stylesheets {
+      let mut walk = RuleWalk { origin: sheet.origin, env, layers: &mut layers, anonymous: &mut anonymous, out: &mut rules, };
+      walk.rules(&sheet.rules, &mut vec![], &mut vec![],);
+   }
+   let mut ranks = HashMap::new();
+   layers.ranks(&mut vec![], &mut ranks,);
+   rules.into_iter().map(|(rule, path,)| CascadeRule { layer: ranks[&path], ..rule },).collect()
+}
+
+///State while flattening the rules of one stylesheet.
+struct RuleWalk<'a, 'w,> {
+   origin:    css::Origin,
+   env:       &'w MediaEnvironment,
+   layers:    &'w mut LayerTree,
+   ///Number of anonymous layers so far, to give each a name of its own.
+   anonymous: &'w mut usize,
+   ///Rules with the path of their layer, to be ranked once all layers are known.
+   out:       &'w mut Vec<(CascadeRule<'a,>, Vec<String,>,),>,
+}
+
+impl<'a,> RuleWalk<'a, '_,> {
+   fn rules(&mut self, rules: &'a [css::CssRule], layer: &mut Vec<String,>, containers: &mut Vec<&'a css::ContainerRule,>,) {
+      use css::CssRule;
+      for rule in rules {
+         match rule {
+            CssRule::Style(rule,) => {
+               let rule = CascadeRule { rule, origin: self.origin, layer: 0, containers: containers.clone(), };
+               self.out.push((rule, layer.clone(),),);
+            }
+            CssRule::Media(media,) if media.queries.matches(self.env,) => self.rules(&media.rules, layer, containers,),
+            CssRule::Supports(supports,) if supports.condition.matches() => self.rules(&supports.rules, layer, containers,),
+            CssRule::Media(_,) | CssRule::Supports(_,) => {}
+            CssRule::LayerStatement(names,) => {
+               for nam in names {
+                  self.layers.register(&[&layer[..], nam,].concat(),);
+               }
+            }
+            CssRule::Layer(layer_rule,) => {
+               let len = layer.len();
+               match layer_rule.name {
+                  Some(ref nam,) => layer.extend(nam.iter().cloned(),),
+                  //'#' can't start a layer name, so this doesn't clash with named layers.
+                  None => {
+                     *self.anonymous += 1;
+                     layer.push(format!("#{}", self.anonymous),);
+                  }
+               }
+               self.layers.register(layer,);
+               self.rules(&layer_rule.rules, layer, containers,);
+               layer.truncate(len,);
+            }
+            CssRule::Container(container,) => {
+               containers.push(container,);
+               self.rules(&container.rules, layer, containers,);
+               containers.pop();
+            }
+            CssRule::FontFace(_,) => {}
+         }
+      }
+   }
+}
+
+///Content size of each laid-out query container, keyed by its DOM node. Feeds
+/// the results of layout back into '@container' rules.
+pub type ContainerSizes = HashMap<dom::NodeId, (f64, f64,),>;
+
+///An ancestor that '@container' rules can query.
+#[derive(Clone,)]
+struct Container {
+   names:  Vec<String,>,
+   ///'None' if the size can't be queried, or isn't known before layout.
+   width:  Option<f64,>,
+   height: Option<f64,>,
+}
+
+///Do the '@container' rules match, each against the nearest ancestor container
+/// with its name? Ancestors are in 'containers', outermost first.
+fn containers_match(rules: &[&css::ContainerRule], containers: &[Container],) -> bool {
+   rules.iter().all(|rule| {
+      let container = containers.iter().rev().find(|c| rule.name.as_ref().is_none_or(|nam| c.names.contains(nam,),),);
+      container.is_some_and(|c| rule.condition.matches_container(c.width, c.height,),)
+   },)
 }
 
 ///Apply styles to a single element, returning the specified values.
-fn specified_values(elem: &dom::ElementData, stylesheet: &css::Stylesheet,) -> PropertyMap {
-   let mut values = PropertyMap::new();
-   let mut rules = matching_rules(elem, stylesheet,);
-   //Go through the rules from lowest to highest specificity
-   rules.sort_by(|&(a, ..,), &(b, ..,)| a.cmp(&b,),);
-   for (_, rule,) in rules {
-      for decl in &rule.declarations {
-         values.insert(decl.nam.clone(), decl.val.clone(),);
+fn specified_values(elem: &dom::ElementData, rules: &RuleIndex, containers: &[Container], ancestors: &Ancestors,) -> PropertyMap {
+   let mut decls: Vec<(CascadeKey, &css::Declaration,),> = vec![];
+   for order in rules.candidates(elem,) {
+      let rule = &rules.rules[order];
+      let Some((specificity, matched,),) = match_rule(elem, rule.rule, &rules.hashes[order], ancestors,) else { continue };
+      if !containers_match(&rule.containers, containers,) {
+         continue;
       }
+      for decl in &matched.declarations {
+         //Layers in '!important' declarations apply in reverse order.
+         let layer = if decl.important { usize::MAX - rule.layer } else { rule.layer };
+         decls.push(((CascadeLevel::new(rule.origin, decl.important,), false, layer, specificity, order,), decl,),);
+      }
+   }
+   //Declarations in the 'style' attribute belong to the author origin but beat any selector.
+   let inline = elem.attr("style",).map(|src| css::parse_inline(src.clone(),),).unwrap_or_default();
+   for decl in &inline {
+      decls.push(((CascadeLevel::new(css::Origin::Author, decl.important,), true, 0, (0, 0, 0,), 0,), decl,),);
+   }
+   //Go through the declarations from lowest to highest precedence. The sort is
+   // stable, so later declarations in the same rule still win.
+   decls.sort_by_key(|&(key, _,)| key,);
+   let mut values = PropertyMap::new();
+   for ((level, ..,), decl,) in decls {
+      values.push(&decl.id, level.origin(), decl.val.clone(),);
    }
    values
 }
 
-///Apply a stylesheet to an entire DOM tree, returning a StyledNode tree.
-pub fn style_tree<'a,>(root: &'a dom::Node, stylesheet: &'a css::Stylesheet,) -> StyledNode<'a,> {
-   use dom::NodeType;
+///The built-in user-agent stylesheet, based on the rendering section of the
+/// HTML spec. It is parsed on first use.
+pub fn user_agent_stylesheet() -> &'static css::Stylesheet {
+   static UA: OnceLock<css::Stylesheet,> = OnceLock::new();
+   UA.get_or_init(|| css::parse(include_str!("ua.css").to_string(), css::Origin::UserAgent,),)
+}
 
+///How 'style_tree' walks the DOM.
+#[derive(Clone, Copy, PartialEq, Eq,)]
+pub enum Traversal {
+   Sequential,
+   ///Style the children of each element in parallel on the rayon thread pool.
+   /// The result is the same as 'Sequential'.
+   Parallel,
+}
+
+///Apply stylesheets to an entire DOM tree, returning a StyledNode tree. Each
+/// stylesheet takes part in the cascade at the level of its 'origin'. The
+/// built-in 'user_agent_stylesheet' is applied first, unless 'stylesheets'
+/// contains its own user-agent stylesheet to replace it. '@media' rules
+/// apply if they match 'env', and '@container' rules are evaluated against
+/// 'containers' from an earlier layout, if any.
+pub fn style_tree<'a,>(
+   root: &'a dom::Node,
+   stylesheets: &'a [css::Stylesheet],
+   env: &MediaEnvironment,
+   containers: &ContainerSizes,
+   traversal: Traversal,
+) -> StyledNode<'a,> {
+   let rules = rule_index(stylesheets, env,);
+   let mut ancestors = Ancestors::new();
    let specified_values = match root.node_type {
-      NodeType::Element(ref elem,) => specified_values(elem, stylesheet,),
-      NodeType::Text(_,) => PropertyMap::new(),
+      dom::NodeType::Element(ref elem,) => specified_values(elem, &rules, &[], &ancestors,),
+      dom::NodeType::Text(_,) => PropertyMap::new(),
+   };
+   let computed = ComputedValues::compute(&specified_values, None, MEDIUM_FONT_SIZE,);
+   //'rem' units refer to the root element's font size.
+   let cx = StyleCx { rules, sizes: containers, root_font_size: computed.font_size, traversal, };
+   let children = style_children(root, &computed, &cx, &[], &mut ancestors,);
+   let (specified_values, computed,) = (Arc::new(specified_values,), Arc::new(computed,),);
+   StyledNode { node: root, specified_values, computed, changed: true, children, }
+}
+
+///Index the rules of 'stylesheets' that apply in 'env', adding the user-agent
+/// stylesheet unless they have their own.
+fn rule_index<'a,>(stylesheets: &'a [css::Stylesheet], env: &MediaEnvironment,) -> RuleIndex<'a,> {
+   let mut sheets: Vec<&css::Stylesheet,> = stylesheets.iter().collect();
+   if !stylesheets.iter().any(|sheet| sheet.origin == css::Origin::UserAgent,) {
+      sheets.insert(0, user_agent_stylesheet(),);
+   }
+   RuleIndex::new(cascade_rules(&sheets, env,),)
+}
+
+///What styling a tree needs besides the node being styled.
+struct StyleCx<'a, 's,> {
+   rules:          RuleIndex<'a,>,
+   sizes:          &'s ContainerSizes,
+   root_font_size: f64,
+   traversal:      Traversal,
+}
+
+impl StyleCx<'_, '_,> {
+   ///The containers the children of 'node' can query: 'ancestors', and 'node'
+   /// itself if it has a 'container-type'.
+   fn containers(&self, node: &dom::Node, computed: &ComputedValues, ancestors: &[Container],) -> Vec<Container,> {
+      use crate::properties::ContainerType;
+      let mut containers = ancestors.to_vec();
+      if computed.container_type != ContainerType::Normal {
+         let size = self.sizes.get(&node.id(),);
+         containers.push(Container {
+            names:  computed.container_name.clone(),
+            width:  size.map(|s| s.0,),
+            height: size.map(|s| s.1,).filter(|_| computed.container_type == ContainerType::Size,),
+         },);
+      }
+      containers
+   }
+}
+
+///Style the children of 'node', which has the computed values 'computed'.
+/// 'containers' and 'ancestors' are those of 'node'.
+fn style_children<'a,>(
+   node: &'a dom::Node,
+   computed: &ComputedValues,
+   cx: &StyleCx<'a, '_,>,
+   containers: &[Container],
+   ancestors: &mut Ancestors<'a,>,
+) -> Vec<StyledNode<'a,>,> {
+   let dom::NodeType::Element(ref elem,) = node.node_type else { return vec![] };
+   let containers = cx.containers(node, computed, containers,);
+   ancestors.push(elem,);
+   let children = match cx.traversal {
+      Traversal::Sequential => {
+         let mut cache = SharingCache::default();
+         node.children.iter().map(|child| style_node(child, cx, &containers, computed, ancestors, &mut cache,),).collect()
+      }
+      //Each task gets its own copy of the ancestors, and shares styles among
+      // the siblings it styles.
+      Traversal::Parallel => {
+         let ancestors = &*ancestors;
+         node
+            .children
+            .par_iter()
+            .map_init(
+               || (ancestors.clone(), SharingCache::default(),),
+               |(ancestors, cache,), child| style_node(child, cx, &containers, computed, ancestors, cache,),
+            )
+            .collect()
+      }
+   };
+   ancestors.pop();
+   children
+}
+
+///Style 'node' and its descendants, inheriting from 'parent'. 'containers' are
+/// the ancestors '@container' rules can query, and 'cache' holds the styles of
+/// earlier siblings.
+fn style_node<'a,>(
+   node: &'a dom::Node,
+   cx: &StyleCx<'a, '_,>,
+   containers: &[Container],
+   parent: &ComputedValues,
+   ancestors: &mut Ancestors<'a,>,
+   cache: &mut SharingCache<'a,>,
+) -> StyledNode<'a,> {
+   use dom::NodeType;
+
+   let (specified_values, computed,) = match node.node_type {
+      NodeType::Element(ref elem,) => match cache.get(elem,) {
+         Some(shared,) => shared,
+         None => {
+            let specified_values = specified_values(elem, &cx.rules, containers, ancestors,);
+            let computed = ComputedValues::compute(&specified_values, Some(parent,), cx.root_font_size,);
+            let (specified_values, computed,) = (Arc::new(specified_values,), Arc::new(computed,),);
+            cache.insert(elem, &specified_values, &computed,);
+            (specified_values, computed,)
+         }
+      },
+      //Text has no declarations of its own and inherits everything.
+      NodeType::Text(_,) => (Arc::new(PropertyMap::new(),), Arc::new(ComputedValues::inherit_from(parent, cx.root_font_size,),),),
+   };
+   let children = style_children(node, &computed, cx, containers, ancestors,);
+   StyledNode { node, specified_values, computed, changed: true, children, }
+}
+
+///What a change to an id or class invalidates, from the selectors that mention it.
+#[derive(Default,)]
+struct InvalidationSet {
+   ///A selector's subject needs the key, so an element that gains or loses it
+   /// must match again.
+   self_:       bool,
+   ///Selectors need the key on an ancestor. Descendants with one of these
+   /// hashes of their subjects' keys must match again.
+   descendants: Vec<u32,>,
+   ///As 'descendants', for a subject with no key, like that of '.a *', which
+   /// any descendant may match.
+   subtree:     bool,
+}
+
+///Invalidation sets of the ids and classes in the selectors of 'rules', by
+/// 'key_hash'. Tags never change, and only 'style' of the other attributes
+/// matters, so nothing else needs a set.
+fn invalidation_sets(rules: &RuleIndex,) -> HashMap<u32, InvalidationSet,> {
+   let mut sets: HashMap<u32, InvalidationSet,> = HashMap::new();
+   let keys =
+      |simple: &css::SimpleSelector| simple_hashes(None, simple.id.as_deref(), simple.class.iter().map(|class| &**class,),);
+   for slctr in rules.rules.iter().flat_map(|rule| &rule.rule.selectors,) {
+      let subject = slctr.subject();
+      for hash in keys(subject,) {
+         sets.entry(hash,).or_default().self_ = true;
+      }
+      let css::Selector::Complex(compounds, _,) = slctr else { continue };
+      //The key 'RuleIndex' buckets the selector by, which a matching element has.
+      let subject_key = match (&subject.id, subject.class.first(), &subject.tag_name,) {
+         (Some(id,), ..,) => Some(key_hash(b'#', id,),),
+         (None, Some(class,), _,) => Some(key_hash(b'.', class,),),
+         (None, None, Some(tag,),) => Some(key_hash(b't', tag,),),
+         (None, None, None,) => None,
+      };
+      for hash in compounds.iter().flat_map(|(simple, _,)| keys(simple,),) {
+         let set = sets.entry(hash,).or_default();
+         match subject_key {
+            Some(key,) if !set.descendants.contains(&key,) => set.descendants.push(key,),
+            Some(_,) => {}
+            None => set.subtree = true,
+         }
+      }
+   }
+   sets
+}
+
+///Descendants of changed elements that must match selectors again.
+#[derive(Clone, Default,)]
+struct Invalid {
+   ///Subject key hashes from 'InvalidationSet::descendants'.
+   keys:    Vec<u32,>,
+   subtree: bool,
+}
+
+///What restyling a tree needs besides what styling it does.
+struct RestyleCx<'a, 's,> {
+   style:         StyleCx<'a, 's,>,
+   invalidations: HashMap<u32, InvalidationSet,>,
+   changes:       HashMap<dom::NodeId, dom::Changes,>,
+   old:           &'s StyleSnapshot,
+   ///Compute all values again, because the root font size 'rem' refers to changed.
+   recompute:     bool,
+}
+
+impl RestyleCx<'_, '_,> {
+   ///Must 'elem', below the invalidations 'above', match selectors again? Also
+   /// return the invalidations for its descendants.
+   fn invalidate(&self, node: &dom::Node, elem: &dom::ElementData, above: &Invalid,) -> (bool, Invalid,) {
+      let mut below = above.clone();
+      let mut rematch = above.subtree || element_hashes(elem,).iter().any(|hash| above.keys.contains(hash,),);
+      let Some(changes,) = self.changes.get(&node.id(),) else { return (rematch, below,) };
+      rematch |= changes.attrs.iter().any(|nam| nam == "style",);
+      let ids = changes.ids.iter().map(|id| key_hash(b'#', id,),);
+      for hash in ids.chain(changes.classes.iter().map(|class| key_hash(b'.', class,),),) {
+         let Some(set,) = self.invalidations.get(&hash,) else { continue };
+         rematch |= set.self_;
+         below.keys.extend(set.descendants.iter().filter(|key| !below.keys.contains(key,),).collect::<Vec<_,>>(),);
+         below.subtree |= set.subtree;
+      }
+      (rematch, below,)
+   }
+}
+
+///Style a tree again after changes to its DOM, starting from the styles in
+/// 'old' and only matching selectors for the elements the changes can affect:
+/// those whose 'style', or an id or class that a selector needs on the element
+/// itself, changed, descendants a changed id or class can affect through a
+/// selector's ancestors, and nodes inserted since. Computed values are only
+/// recomputed under those and where inherited values changed. No selector
+/// depends on siblings, so removed nodes affect nothing. Changes to the
+/// stylesheets, 'env' or 'containers' need a full 'style_tree' instead.
+/// 'changes' are those 'dom::Node::take_changes' took from 'root' since 'old'.
+/// 'StyledNode::changed' tells which nodes got a different style.
+pub fn restyle<'a,>(
+   root: &'a dom::Node,
+   changes: HashMap<dom::NodeId, dom::Changes,>,
+   stylesheets: &'a [css::Stylesheet],
+   env: &MediaEnvironment,
+   containers: &ContainerSizes,
+   old: &StyleSnapshot,
+) -> StyledNode<'a,> {
+   let rules = rule_index(stylesheets, env,);
+   let invalidations = invalidation_sets(&rules,);
+   let style = StyleCx { rules, sizes: containers, root_font_size: MEDIUM_FONT_SIZE, traversal: Traversal::Sequential, };
+   let mut cx = RestyleCx { style, invalidations, changes, old, recompute: false, };
+   let mut ancestors = Ancestors::new();
+   let (specified_values, computed, changed, below,) = restyle_values(root, &cx, &[], None, &ancestors, &Invalid::default(),);
+   cx.recompute = old.styles.get(&root.id(),).is_none_or(|(_, old,)| old.font_size != computed.font_size,);
+   cx.style.root_font_size = computed.font_size;
+   let children = restyle_children(root, &computed, changed, &cx, &[], &mut ancestors, &below,);
+   StyledNode { node: root, specified_values, computed, changed, children, }
+}
+
+///Restyle the children of 'node', which has the computed values 'computed'
+/// that 'changed' or not. 'invalid' are the invalidations from 'node' and above.
+fn restyle_children<'a,>(
+   node: &'a dom::Node,
+   computed: &ComputedValues,
+   changed: bool,
+   cx: &RestyleCx<'a, '_,>,
+   containers: &[Container],
+   ancestors: &mut Ancestors<'a,>,
+   invalid: &Invalid,
+) -> Vec<StyledNode<'a,>,> {
+   let dom::NodeType::Element(ref elem,) = node.node_type else { return vec![] };
+   let containers = cx.style.containers(node, computed, containers,);
+   ancestors.push(elem,);
+   let children = node
+      .children
+      .iter()
+      .map(|child| {
+         let parent = Some((computed, changed,),);
+         let (specified_values, computed, changed, below,) = restyle_values(child, cx, &containers, parent, ancestors, invalid,);
+         let children = restyle_children(child, &computed, changed, cx, &containers, ancestors, &below,);
+         StyledNode { node: child, specified_values, computed, changed, children, }
+      },)
+      .collect();
+   ancestors.pop();
+   children
+}
+
+///The specified and computed values of 'node', whether they changed, and the
+/// invalidations for its descendants. 'parent' has the parent's computed values
+/// and whether they changed.
+fn restyle_values(
+   node: &dom::Node,
+   cx: &RestyleCx,
+   containers: &[Container],
+   parent: Option<(&ComputedValues, bool,),>,
+   ancestors: &Ancestors,
+   invalid: &Invalid,
+) -> (Arc<PropertyMap,>, Arc<ComputedValues,>, bool, Invalid,) {
+   use dom::NodeType;
+
+   let old = cx.old.styles.get(&node.id(),);
+   let (rematch, below,) = match node.node_type {
+      NodeType::Element(ref elem,) => cx.invalidate(node, elem, invalid,),
+      NodeType::Text(_,) => (false, invalid.clone(),),
+   };
+   let specified_values = match (old, &node.node_type,) {
+      (Some((specified, _,),), _,) if !rematch => specified.clone(),
+      (_, NodeType::Element(ref elem,),) => Arc::new(specified_values(elem, &cx.style.rules, containers, ancestors,),),
+      (_, NodeType::Text(_,),) => Arc::new(PropertyMap::new(),),
    };
-   StyledNode {
-      node: root,
-      specified_values,
-      children: root.children.iter().map(|child| style_tree(child, stylesheet,),).collect(),
+   let parent_changed = parent.is_some_and(|(_, changed,)| changed,);
+   let computed = match old {
+      Some((_, computed,),) if !rematch && !parent_changed && !cx.recompute => computed.clone(),
+      _ => Arc::new(match (&node.node_type, parent,) {
+         //Text has no declarations of its own and inherits everything.
+         (NodeType::Text(_,), Some((parent, _,),),) => ComputedValues::inherit_from(parent, cx.style.root_font_size,),
+         _ => ComputedValues::compute(&specified_values, parent.map(|(parent, _,)| parent,), cx.style.root_font_size,),
+      },),
+   };
+   let changed =
+      old.is_none_or(|(old_specified, old_computed,)| *old_specified != specified_values || *old_computed != computed,);
+   (specified_values, computed, changed, below,)
+}
+
+#[cfg(test)]
+mod tests {
+   use {
+      super::*,
+      crate::{font, html, layout},
+   };
+
+   ///The styled element with the id 'id' in the tree 'node'.
+   fn find<'b, 'a,>(node: &'b StyledNode<'a,>, id: &str,) -> &'b StyledNode<'a,> {
+      fn search<'b, 'a,>(node: &'b StyledNode<'a,>, id: &str,) -> Option<&'b StyledNode<'a,>,> {
+         match node.node().node_type {
+            dom::NodeType::Element(ref elem,) if elem.id().is_some_and(|i| i == id,) => Some(node,),
+            _ => node.children.iter().find_map(|child| search(child, id,),),
+         }
+      }
+      search(node, id,).unwrap_or_else(|| panic!("no element #{id}"),)
+   }
+
+   ///Style 'root' with the author stylesheet 'sheets' in an 800 by 600 viewport.
+   fn style<'a,>(root: &'a dom::Node, sheets: &'a [css::Stylesheet], containers: &ContainerSizes,) -> StyledNode<'a,> {
+      let env = MediaEnvironment::new(&viewport(),);
+      style_tree(root, sheets, &env, containers, Traversal::Sequential,)
+   }
+
+   fn viewport() -> layout::Dimensions {
+      let mut viewport = layout::Dimensions::default();
+      viewport.content.width = 800.0;
+      viewport.content.height = 600.0;
+      viewport
+   }
+
+   ///The color of the element '#id' of 'src' styled with 'sheet'.
+   fn color(src: &str, sheet: &str, id: &str,) -> css::Color {
+      let root = html::parse(src.to_string(),);
+      let sheets = [css::parse(sheet.to_string(), css::Origin::Author,),];
+      find(&style(&root, &sheets, &ContainerSizes::new(),), id,).computed().color.clone()
+   }
+
+   fn rgb(r: u8, g: u8, b: u8,) -> css::Color { css::Color { r, g, b, a: 255, } }
+
+   #[test]
+   fn later_layers_win() {
+      let sheet = "@layer a, b; @layer b { p { color: #0000ff } } @layer a { #p { color: #ff0000 } }";
+      assert!(color("<p id=\"p\"></p>", sheet, "p",) == rgb(0, 0, 255,));
+   }
+
+   #[test]
+   fn unlayered_rules_win_over_layers() {
+      let sheet = "p { color: #008000 } @layer a { #p { color: #ff0000 } }";
+      assert!(color("<p id=\"p\"></p>", sheet, "p",) == rgb(0, 128, 0,));
+      //Within a layer, its own rules come after its sublayers.
+      let sheet = "@layer a { p { color: #0000ff } @layer x { #p { color: #ff0000 } } }";
+      assert!(color("<p id=\"p\"></p>", sheet, "p",) == rgb(0, 0, 255,));
+   }
+
+   #[test]
+   fn important_declarations_reverse_layer_order() {
+      let sheet = "@layer a { p { color: #ff0000 !important } } @layer b { p { color: #0000ff !important } } p { color: #008000 !important }";
+      assert!(color("<p id=\"p\"></p>", sheet, "p",) == rgb(255, 0, 0,));
+   }
+
+   #[test]
+   fn container_queries_use_the_laid_out_container_size() {
+      let src = "<div id=\"c\"><p id=\"p\">x</p></div>";
+      let fonts = font::FontDatabase::new();
+      for (width, expected,) in [(300, rgb(255, 0, 0,),), (500, rgb(0, 0, 255,),),] {
+         let root = html::parse(src.to_string(),);
+         let sheet = format!(
+            "#c {{ container-type: inline-size; width: {width}px }} @container (width < 400px) {{ p {{ color: #ff0000 }} }} \
+             @container (width >= 400px) {{ p {{ color: #0000ff }} }}"
+         );
+         let sheets = [css::parse(sheet, css::Origin::Author,),];
+         //Before layout, no container has a size and no query matches.
+         let first = style(&root, &sheets, &ContainerSizes::new(),);
+         assert!(find(&first, "p",).computed().color == rgb(0, 0, 0,));
+         let sizes = layout::container_sizes(&layout::layout_tree(&first, viewport(), &fonts,),);
+         assert!(find(&style(&root, &sheets, &sizes,), "p",).computed().color == expected);
+      }
+   }
+
+   #[test]
+   fn container_queries_need_a_container_type() {
+      let src = "<div id=\"c\"><p id=\"p\">x</p></div>";
+      let root = html::parse(src.to_string(),);
+      let sheets = [css::parse("@container (width >= 0px) { p { color: #ff0000 } }".to_string(), css::Origin::Author,),];
+      let first = style(&root, &sheets, &ContainerSizes::new(),);
+      let sizes = layout::container_sizes(&layout::layout_tree(&first, viewport(), &font::FontDatabase::new(),),);
+      assert!(sizes.is_empty());
+      assert!(find(&style(&root, &sheets, &sizes,), "p",).computed().color == rgb(0, 0, 0,));
+   }
+
+   ///The rules of 'sheet' that 'RuleIndex' offers the element '#id' of 'src', by
+   /// their position in the sheet.
+   fn candidates(src: &str, sheet: &str, id: &str,) -> Vec<usize,> {
+      fn search<'a,>(node: &'a dom::Node, id: &str,) -> Option<&'a dom::ElementData,> {
+         match node.node_type {
+            dom::NodeType::Element(ref elem,) if elem.id().is_some_and(|i| i == id,) => Some(elem,),
+            _ => node.children.iter().find_map(|child| search(child, id,),),
+         }
+      }
+      let root = html::parse(src.to_string(),);
+      let sheet = css::parse(sheet.to_string(), css::Origin::Author,);
+      let index = RuleIndex::new(cascade_rules(&[&sheet,], &MediaEnvironment::new(&viewport(),),),);
+      index.candidates(search(&root, id,).unwrap(),)
+   }
+
+   #[test]
+   fn rule_index_buckets_by_id_class_tag_or_universal() {
+      let sheet = "#a { } .x { } .y.x { } p { } div { } * { } .z { } #b.x { } p.x, #a { } div > p span#a { }";
+      let src = "<div><p id=\"a\" class=\"y x\"></p><span id=\"s\"></span><p id=\"c\" class=\"z\"></p><p id=\"d\" class=\"x\"></p></div>";
+      //The id bucket wins over classes, and the first class over the tag.
+      assert_eq!(candidates(src, sheet, "a",), [0, 1, 2, 3, 5, 8, 9]);
+      assert_eq!(candidates(src, sheet, "d",), [1, 3, 5, 8]);
+      assert_eq!(candidates(src, sheet, "s",), [5]);
+      assert_eq!(candidates(src, sheet, "c",), [3, 5, 6]);
+   }
+
+   #[test]
+   fn descendant_and_child_combinators() {
+      let src = "<div><section><p id=\"p\"></p></section><p id=\"q\"></p></div>";
+      let sheet = "div p { color: #ff0000 } div > p { color: #0000ff } span p { color: #008000 }";
+      assert!(color(src, sheet, "p",) == rgb(255, 0, 0,));
+      assert!(color(src, sheet, "q",) == rgb(0, 0, 255,));
+      assert!(color(src, "section > #p { color: #ff0000 } div > #p { color: #0000ff }", "p",) == rgb(255, 0, 0,));
+   }
+
+   #[test]
+   fn ancestor_filter_counts_its_keys() {
+      let mut filter = AncestorFilter::new();
+      let (div, span,) = (key_hash(b't', "div",), key_hash(b't', "span",),);
+      filter.insert(div,);
+      filter.insert(div,);
+      filter.remove(div,);
+      assert!(filter.might_contain(div));
+      assert!(!filter.might_contain(span));
+      filter.remove(div,);
+      assert!(!filter.might_contain(div));
+   }
+
+   #[test]
+   fn siblings_with_the_same_attributes_share_styles() {
+      let root = html::parse("<ul><li class=\"a\"></li><li class=\"a\"></li><li class=\"b\"></li></ul>".to_string(),);
+      let sheets = [css::parse(".b { color: #ff0000 }".to_string(), css::Origin::Author,),];
+      let styled = style(&root, &sheets, &ContainerSizes::new(),);
+      let [a, a2, b] = &styled.children[..] else { panic!("expected three items") };
+      assert!(Arc::ptr_eq(&a.computed, &a2.computed,));
+      assert!(!Arc::ptr_eq(&a.computed, &b.computed,));
+      assert!(b.computed().color == rgb(255, 0, 0,));
+   }
+
+   ///The 'display' keyword of the root element of 'src' styled with 'sheets',
+   /// each from its origin.
+   fn display(src: &str, sheets: &[(css::Origin, &str,)],) -> String {
+      let root = html::parse(src.to_string(),);
+      let sheets: Vec<css::Stylesheet,> = sheets.iter().map(|(origin, src,)| css::parse(src.to_string(), *origin,),).collect();
+      match style(&root, &sheets, &ContainerSizes::new(),).val(LonghandId::Display,) {
+         Some(css::Value::Keyword(kw,),) => kw,
+         _ => String::new(),
+      }
+   }
+
+   #[test]
+   fn important_reverses_the_order_of_origins() {
+      let ua = (css::Origin::UserAgent, "p { display: inline }",);
+      assert_eq!(display("<p></p>", &[ua, (css::Origin::Author, "p { display: block }",)],), "block");
+      let ua = (css::Origin::UserAgent, "p { display: none !important }",);
+      let user = (css::Origin::User, "p { display: inline !important }",);
+      let author = (css::Origin::Author, "p { display: block !important }",);
+      assert_eq!(display("<p></p>", &[author, user, ua],), "none");
+      assert_eq!(display("<p></p>", &[author, user],), "inline");
+   }
+
+   #[test]
+   fn inline_style_beats_selectors_but_not_important() {
+      let src = "<p id=\"a\" style=\"display: none\"></p>";
+      assert_eq!(display(src, &[(css::Origin::Author, "#a { display: block }",)],), "none");
+      assert_eq!(display(src, &[(css::Origin::Author, "#a { display: block !important }",)],), "block");
+   }
+
+   #[test]
+   fn specificity_then_source_order_decide() {
+      let src = "<p id=\"a\" class=\"c\"></p>";
+      assert_eq!(display(src, &[(css::Origin::Author, "#a { display: none } .c { display: block }",)],), "none");
+      assert_eq!(display(src, &[(css::Origin::Author, ".c { display: none } p { display: block }",)],), "none");
+      assert_eq!(display(src, &[(css::Origin::Author, "p { display: none } p { display: block }",)],), "block");
+      let sheets = [(css::Origin::Author, "p { display: none }",), (css::Origin::Author, "p { display: block }",),];
+      assert_eq!(display(src, &sheets,), "block");
+   }
+
+   #[test]
+   fn user_agent_stylesheet_has_the_lowest_precedence() {
+      assert_eq!(display("<div></div>", &[],), "block");
+      assert_eq!(display("<li></li>", &[],), "list-item");
+      assert_eq!(display("<head></head>", &[],), "none");
+      assert_eq!(display("<span></span>", &[],), "");
+      assert_eq!(display("<div></div>", &[(css::Origin::Author, "div { display: inline }",)],), "inline");
+   }
+
+   #[test]
+   fn user_agent_stylesheet_can_be_replaced() {
+      assert_eq!(display("<div></div>", &[(css::Origin::UserAgent, "span { display: block }",)],), "");
+   }
+
+   #[test]
+   fn parallel_styling_matches_sequential() {
+      let (doc, sheet,) = crate::bench::generate(12, 30, 120,);
+      let vars = "html { --accent: #123456; --gap: 3px } .section { --accent: #654321; font-size: 20px } \
+                  .section li span { color: var(--accent); margin-left: var(--gap) } ul { line-height: 2 }";
+      let root = html::parse(doc,);
+      let sheets = [css::parse(sheet, css::Origin::Author,), css::parse(vars.to_string(), css::Origin::Author,),];
+      let env = MediaEnvironment::new(&viewport(),);
+      let containers = ContainerSizes::new();
+      let sequential = style_tree(&root, &sheets, &env, &containers, Traversal::Sequential,);
+      let parallel = style_tree(&root, &sheets, &env, &containers, Traversal::Parallel,);
+      assert!(crate::bench::same_styles(&sequential, &parallel,));
+      //The custom properties and inherited values did get through.
+      let span = find(&parallel, "e10",).children.first().unwrap();
+      assert!(span.computed().color == rgb(0x65, 0x43, 0x21,));
+      assert_eq!(span.computed().font_size, 20.0);
+   }
+
+   ///Style 'src' with 'sheet', apply 'change' to the DOM and restyle it. Check
+   /// the result against a full 'style_tree', and return the elements whose
+   /// style changed, by id or else tag name.
+   fn restyled(src: &str, sheet: &str, change: impl FnOnce(&mut dom::Node,),) -> Vec<String,> {
+      fn changed(node: &StyledNode, out: &mut Vec<String,>,) {
+         if let (dom::NodeType::Element(ref elem,), true,) = (&node.node().node_type, node.changed(),) {
+            out.push(elem.id().unwrap_or(&elem.tag_name,).clone(),);
+         }
+         node.children.iter().for_each(|child| changed(child, out,),);
+      }
+      let mut root = html::parse(src.to_string(),);
+      let sheets = [css::parse(sheet.to_string(), css::Origin::Author,),];
+      let env = MediaEnvironment::new(&viewport(),);
+      let containers = ContainerSizes::new();
+      let old = style_tree(&root, &sheets, &env, &containers, Traversal::Sequential,).snapshot();
+      change(&mut root,);
+      let changes = root.take_changes();
+      let restyled = restyle(&root, changes, &sheets, &env, &containers, &old,);
+      let full = style_tree(&root, &sheets, &env, &containers, Traversal::Sequential,);
+      assert!(crate::bench::same_styles(&restyled, &full,), "restyle differs from style_tree");
+      let mut out = vec![];
+      changed(&restyled, &mut out,);
+      out
+   }
+
+   ///The DOM element with the id 'id' in the tree 'node'.
+   fn find_mut<'a,>(node: &'a mut dom::Node, id: &str,) -> &'a mut dom::Node {
+      fn search<'a,>(node: &'a mut dom::Node, id: &str,) -> Option<&'a mut dom::Node,> {
+         match node.node_type {
+            dom::NodeType::Element(ref elem,) if elem.id().is_some_and(|i| i == id,) => Some(node,),
+            _ => node.children.iter_mut().find_map(|child| search(child, id,),),
+         }
+      }
+      search(node, id,).unwrap_or_else(|| panic!("no element #{id}"),)
+   }
+
+   const RESTYLE_DOC: &str = "<body><div id=\"a\" class=\"x\"><p id=\"b\"></p><p id=\"c\" class=\"y\"><span id=\"s\"></span></p></div><div id=\"d\"><p id=\"e\"></p></div></body>";
+   const RESTYLE_SHEET: &str =
+      ".x p { color: #ff0000 } .on { margin-left: 4px } #d p { color: #0000ff } .y span { font-size: 20px }";
+
+   #[test]
+   fn restyle_after_class_and_id_changes() {
+      let restyled = |change: fn(&mut dom::Node,)| restyled(RESTYLE_DOC, RESTYLE_SHEET, change,);
+      //The class is only in an ancestor of selectors: its descendants change.
+      assert_eq!(restyled(|root| find_mut(root, "a",).set_attr("class", String::new(),),), ["b", "c", "s"]);
+      assert_eq!(restyled(|root| find_mut(root, "e",).set_attr("class", "y on".to_string(),),), ["e"]);
+      assert_eq!(restyled(|root| find_mut(root, "d",).set_attr("id", "f".to_string(),),), ["e"]);
+      assert_eq!(restyled(|root| find_mut(root, "c",).remove_attr("class",),), ["s"]);
+      //A change that matches nothing new leaves every style alone.
+      assert!(restyled(|root| find_mut(root, "b",).set_attr("class", "z".to_string(),),).is_empty());
+   }
+
+   #[test]
+   fn restyle_after_style_attribute_changes() {
+      let restyled = |change: fn(&mut dom::Node,)| restyled(RESTYLE_DOC, RESTYLE_SHEET, change,);
+      assert_eq!(restyled(|root| find_mut(root, "b",).set_attr("style", "color: #008000".to_string(),),), ["b"]);
+      assert_eq!(restyled(|root| find_mut(root, "c",).set_attr("style", "font-size: 30px".to_string(),),), ["c"]);
+      //Other attributes match no selector.
+      assert!(restyled(|root| find_mut(root, "b",).set_attr("title", "t".to_string(),),).is_empty());
+   }
+
+   #[test]
+   fn restyle_after_inserting_and_removing_nodes() {
+      let restyled = |change: fn(&mut dom::Node,)| restyled(RESTYLE_DOC, RESTYLE_SHEET, change,);
+      let insert = |root: &mut dom::Node| {
+         let attrs = [("id".to_string(), "n".to_string(),),].into_iter().collect();
+         find_mut(root, "d",).children.push(dom::elem("p".to_string(), attrs, vec![],),);
+      };
+      assert_eq!(restyled(insert,), ["n"]);
+      assert!(restyled(|root| drop(find_mut(root, "a",).children.remove(1,),),).is_empty());
    }
 }
@@ -0,0 +1,228 @@
+#![allow(unused)]
+use std::ops::Range;
+
+use {
+   ab_glyph_rasterizer::{point, Point, Rasterizer},
+   ttf_parser::{GlyphId, OutlineBuilder},
+};
+
+use crate::{
+   font::{FontDatabase, FontFace, Synthesis},
+   layout::Rct,
+   properties::ComputedValues,
+};
+
+///A glyph of a run of text, placed on the baseline from the run's start.
+#[derive(Clone,)]
+pub struct Glyph<'f,> {
+   pub face:      &'f FontFace,
+   pub id:        GlyphId,
+   ///Bold or oblique the face lacks, which drawing the glyph makes up.
+   pub synthesis: Synthesis,
+   ///Position of the glyph's origin from the start of the run on the
+   /// baseline, in px with y going down.
+   pub x:         f64,
+   pub y:         f64,
+   ///How far the glyph moves the pen, synthetic bold included.
+   pub advance:   f64,
+   ///Byte offset in the text of the first character the glyph draws.
+   pub cluster:   usize,
+}
+
+///The glyphs that draw 'text' in the font of 'style', in visual order from the
+/// left, and the width of the text. 'rtl' text is shaped right to left, so
+/// the glyph of its first character comes last. Characters no face has a glyph
+/// for leave a gap of half an em. Synthetic bold widens the advances.
+pub fn glyphs<'f,>(text: &str, style: &ComputedValues, rtl: bool, fonts: &'f FontDatabase,) -> (Vec<Glyph<'f,>,>, f64,) {
+   //Runs of characters drawn with the same face, each shaped as a whole.
+   let mut runs: Vec<(Option<&FontFace,>, Range<usize,>,),> = vec![];
+   for (i, ch,) in text.char_indices() {
+      let face = fonts.query(&style.font_family, style.font_weight, style.font_style, ch,);
+      match runs.last_mut() {
+         Some((last, range,),) if last.map(|f| f as *const FontFace,) == face.map(|f| f as *const FontFace,) => {
+            range.end = i + ch.len_utf8()
+         }
+         _ => runs.push((face, i..i + ch.len_utf8(),),),
+      }
+   }
+   if rtl {
+      runs.reverse();
+   }
+   let mut glyphs = vec![];
+   let mut x = 0.0;
+   for (face, range,) in runs {
+      match face {
+         Some(face,) => x = shape(text, range, face, style, rtl, x, &mut glyphs,),
+         None => x += text[range].chars().count() as f64 * style.font_size / 2.0,
+      }
+   }
+   (glyphs, x,)
+}
+
+///Shape 'range' of 'text' with 'face' in the font size, weight and style of
+/// 'style', starting at 'x', and add its glyphs to 'glyphs'. Returns where the
+/// next glyph goes.
+fn shape<'f,>(
+   text: &str,
+   range: Range<usize,>,
+   face: &'f FontFace,
+   style: &ComputedValues,
+   rtl: bool,
+   mut x: f64,
+   glyphs: &mut Vec<Glyph<'f,>,>,
+) -> f64 {
+   let shaper = face.face();
+   let (size, synthesis,) = (style.font_size, face.synthesis(style.font_weight, style.font_style,),);
+   let mut buffer = rustybuzz::UnicodeBuffer::new();
+   buffer.push_str(&text[range.clone()],);
+   buffer.set_direction(if rtl { rustybuzz::Direction::RightToLeft } else { rustybuzz::Direction::LeftToRight },);
+   //Finds the script, which picks the shaper for Arabic, Indic and other scripts.
+   buffer.guess_segment_properties();
+   let output = rustybuzz::shape(shaper, &[], buffer,);
+   let scale = size / shaper.units_per_em() as f64;
+   let bold = if synthesis.bold { Synthesis::BOLD_STRENGTH * size } else { 0.0 };
+   for (info, pos,) in output.glyph_infos().iter().zip(output.glyph_positions(),) {
+      let (dx, dy,) = (pos.x_offset as f64 * scale, pos.y_offset as f64 * scale,);
+      let advance = pos.x_advance as f64 * scale + bold;
+      let cluster = range.start + info.cluster as usize;
+      glyphs.push(Glyph { face, id: GlyphId(info.glyph_id as u16,), synthesis, x: x + dx, y: -dy, advance, cluster, },);
+      x += advance;
+   }
+   x
+}
+
+///Anti-aliased coverage of a glyph over a rectangle of pixels.
+pub struct GlyphBitmap {
+   ///Position of the top left pixel.
+   pub left:     i64,
+   pub top:      i64,
+   pub width:    usize,
+   pub height:   usize,
+   ///Coverage of each pixel from 0 to 1, row by row.
+   pub coverage: Vec<f32,>,
+}
+
+///Rasterize glyph 'id' of 'face' at a font size of 'size' px, with its origin
+/// at ('x', 'baseline'). 'None' for glyphs with no outline, like that of a space.
+/// Synthetic oblique slants the outline right going up, and synthetic bold
+/// draws it a second time further right, thickening its vertical strokes.
+pub fn rasterize(face: &FontFace, id: GlyphId, synthesis: Synthesis, size: f64, x: f64, baseline: f64,) -> Option<GlyphBitmap,> {
+   let Rct { x: left, y: top, width, height, } = glyph_bounds(face, id, synthesis, size, x, baseline,)?;
+   let (width, height,) = (width as usize, height as usize,);
+   let face = face.face();
+   let scale = size / face.units_per_em() as f64;
+   let slant = if synthesis.oblique { Synthesis::OBLIQUE_SLANT } else { 0.0 };
+   let bold = if synthesis.bold { Synthesis::BOLD_STRENGTH * size } else { 0.0 };
+   let mut raster = Rasterizer::new(width, height,);
+   for dx in if bold > 0.0 { vec![0.0, bold] } else { vec![0.0] } {
+      //Font units have y going up, and pixels y going down.
+      let to_px = move |fx: f32, fy: f32| {
+         let fx = fx as f64 + slant * fy as f64;
+         point((x + dx + fx * scale - left) as f32, (baseline - fy as f64 * scale - top) as f32,)
+      };
+      let mut outline = Outline { raster, to_px, start: point(0.0, 0.0,), current: point(0.0, 0.0,), };
+      face.outline_glyph(id, &mut outline,)?;
+      raster = outline.raster;
+   }
+   let mut coverage = vec![0.0; width * height];
+   raster.for_each_pixel(|i, c| coverage[i] = c.min(1.0,),);
+   Some(GlyphBitmap { left: left as i64, top: top as i64, width, height, coverage, },)
+}
+
+///The whole pixels glyph 'id' of 'face' covers when 'rasterize' draws it at
+/// ('x', 'baseline'). 'None' for glyphs with no outline.
+pub fn glyph_bounds(face: &FontFace, id: GlyphId, synthesis: Synthesis, size: f64, x: f64, baseline: f64,) -> Option<Rct,> {
+   let face = face.face();
+   let bbox = face.glyph_bounding_box(id,)?;
+   let scale = size / face.units_per_em() as f64;
+   let slant = if synthesis.oblique { Synthesis::OBLIQUE_SLANT } else { 0.0 };
+   let bold = if synthesis.bold { Synthesis::BOLD_STRENGTH * size } else { 0.0 };
+   let (x_min, x_max,) =
+      (bbox.x_min as f64 + slant * bbox.y_min.min(0,) as f64, bbox.x_max as f64 + slant * bbox.y_max.max(0,) as f64,);
+   let left = (x + x_min * scale).floor();
+   let top = (baseline - bbox.y_max as f64 * scale).floor();
+   let width = (x + x_max * scale + bold).ceil() - left;
+   let height = (baseline - bbox.y_min as f64 * scale).ceil() - top;
+   Some(Rct { x: left, y: top, width, height, },)
+}
+
+///Feeds the contours of a glyph to a rasterizer, in pixels.
+struct Outline<F: Fn(f32, f32,) -> Point,> {
+   raster:  Rasterizer,
+   to_px:   F,
+   ///Start of the current contour, which closing it returns to.
+   start:   Point,
+   current: Point,
+}
+
+impl<F: Fn(f32, f32,) -> Point,> OutlineBuilder for Outline<F,> {
+   fn move_to(&mut self, x: f32, y: f32,) {
+      self.start = (self.to_px)(x, y,);
+      self.current = self.start;
+   }
+
+   fn line_to(&mut self, x: f32, y: f32,) {
+      let to = (self.to_px)(x, y,);
+      self.raster.draw_line(self.current, to,);
+      self.current = to;
+   }
+
+   fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32,) {
+      let to = (self.to_px)(x, y,);
+      self.raster.draw_quad(self.current, (self.to_px)(x1, y1,), to,);
+      self.current = to;
+   }
+
+   fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32,) {
+      let to = (self.to_px)(x, y,);
+      self.raster.draw_cubic(self.current, (self.to_px)(x1, y1,), (self.to_px)(x2, y2,), to,);
+      self.current = to;
+   }
+
+   fn close(&mut self,) {
+      if self.current != self.start {
+         self.raster.draw_line(self.current, self.start,);
+      }
+      self.current = self.start;
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use {
+      super::*,
+      crate::{properties::MEDIUM_FONT_SIZE, style::PropertyMap},
+   };
+
+   ///The clusters of the glyphs of 'text' in the initial font, and its width.
+   fn clusters(text: &str, rtl: bool,) -> (Vec<usize,>, f64,) {
+      let fonts = FontDatabase::new();
+      let style = ComputedValues::compute(&PropertyMap::new(), None, MEDIUM_FONT_SIZE,);
+      let (glyphs, width,) = glyphs(text, &style, rtl, &fonts,);
+      (glyphs.iter().map(|glyph| glyph.cluster,).collect(), width,)
+   }
+
+   #[test]
+   fn ligatures_and_marks_draw_several_characters() {
+      assert_eq!(clusters("ffi", false,).0, [0]);
+      assert_eq!(clusters("e\u{301}", false,).0, [0]);
+      assert_eq!(clusters("a ffi b", false,).0, [0, 1, 2, 5, 6]);
+   }
+
+   #[test]
+   fn right_to_left_text_is_shaped_from_its_end() {
+      let (ltr, width,) = clusters("abc", false,);
+      assert_eq!(ltr, [0, 1, 2]);
+      let (rtl, rtl_width,) = clusters("abc", true,);
+      assert_eq!(rtl, [2, 1, 0]);
+      //Only kerning differs.
+      assert!((rtl_width - width).abs() < 1.0);
+   }
+
+   #[test]
+   fn characters_without_a_face_leave_half_an_em() {
+      assert_eq!(clusters("\u{5d0}\u{5d1}", false,), (vec![], MEDIUM_FONT_SIZE));
+      let (_, width,) = clusters("a", false,);
+      assert_eq!(clusters("a\u{5d0}", false,), (vec![0], width + MEDIUM_FONT_SIZE / 2.0));
+   }
+}
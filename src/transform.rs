@@ -0,0 +1,103 @@
+#![allow(unused)]
+use std::{fmt, ops::Mul};
+
+use crate::{layout::Rct, properties::Length};
+
+///A 2D affine transform, mapping (x, y) to (a x + c y + e, b x + d y + f) as
+/// 'matrix(a, b, c, d, e, f)' does.
+#[derive(Clone, Copy, PartialEq,)]
+pub struct Matrix {
+   pub a: f64,
+   pub b: f64,
+   pub c: f64,
+   pub d: f64,
+   pub e: f64,
+   pub f: f64,
+}
+
+impl Matrix {
+   pub const IDENTITY: Matrix = Matrix { a: 1.0, b: 0.0, c: 0.0, d: 1.0, e: 0.0, f: 0.0, };
+
+   pub fn translate(x: f64, y: f64,) -> Matrix { Matrix { e: x, f: y, ..Matrix::IDENTITY } }
+
+   pub fn scale(x: f64, y: f64,) -> Matrix { Matrix { a: x, d: y, ..Matrix::IDENTITY } }
+
+   ///Rotate clockwise by 'deg' degrees, y pointing down.
+   pub fn rotate(deg: f64,) -> Matrix {
+      let (sin, cos,) = deg.to_radians().sin_cos();
+      Matrix { a: cos, b: sin, c: -sin, d: cos, ..Matrix::IDENTITY }
+   }
+
+   ///Skew by 'x' degrees along the x axis and 'y' along the y axis.
+   pub fn skew(x: f64, y: f64,) -> Matrix { Matrix { b: y.to_radians().tan(), c: x.to_radians().tan(), ..Matrix::IDENTITY } }
+
+   pub fn apply(&self, x: f64, y: f64,) -> (f64, f64,) { (self.a * x + self.c * y + self.e, self.b * x + self.d * y + self.f,) }
+
+   ///The transform that undoes this one. 'None' if it flattens the plane.
+   pub fn inverse(&self,) -> Option<Matrix,> {
+      let det = self.a * self.d - self.b * self.c;
+      if det.abs() < f64::EPSILON {
+         return None;
+      }
+      let (a, b, c, d,) = (self.d / det, -self.b / det, -self.c / det, self.a / det,);
+      Some(Matrix { a, b, c, d, e: -(a * self.e + c * self.f), f: -(b * self.e + d * self.f), },)
+   }
+}
+
+///'self * rhs' applies 'rhs' first.
+impl Mul for Matrix {
+   type Output = Matrix;
+
+   fn mul(self, rhs: Matrix,) -> Matrix {
+      Matrix {
+         a: self.a * rhs.a + self.c * rhs.b,
+         b: self.b * rhs.a + self.d * rhs.b,
+         c: self.a * rhs.c + self.c * rhs.d,
+         d: self.b * rhs.c + self.d * rhs.d,
+         e: self.a * rhs.e + self.c * rhs.f + self.e,
+         f: self.b * rhs.e + self.d * rhs.f + self.f,
+      }
+   }
+}
+
+impl fmt::Display for Matrix {
+   fn fmt(&self, f: &mut fmt::Formatter,) -> fmt::Result {
+      write!(f, "matrix({}, {}, {}, {}, {}, {})", self.a, self.b, self.c, self.d, self.e, self.f)
+   }
+}
+
+///One function of a 'transform' list. Translations may be percentages of the
+/// border box, and angles are in degrees.
+#[derive(Clone, PartialEq,)]
+pub enum TransformFunction {
+   Matrix(Matrix,),
+   Translate(Length, Length,),
+   Scale(f64, f64,),
+   Rotate(f64,),
+   Skew(f64, f64,),
+}
+
+impl TransformFunction {
+   fn matrix(&self, width: f64, height: f64,) -> Matrix {
+      match self {
+         TransformFunction::Matrix(m,) => *m,
+         TransformFunction::Translate(x, y,) => Matrix::translate(x.to_px(width,), y.to_px(height,),),
+         TransformFunction::Scale(x, y,) => Matrix::scale(*x, *y,),
+         TransformFunction::Rotate(deg,) => Matrix::rotate(*deg,),
+         TransformFunction::Skew(x, y,) => Matrix::skew(*x, *y,),
+      }
+   }
+}
+
+///The transform 'functions' make, applied from the last to the first, with
+/// translations resolved against a border box of 'width' by 'height'.
+pub fn compose(functions: &[TransformFunction], width: f64, height: f64,) -> Matrix {
+   functions.iter().fold(Matrix::IDENTITY, |m, function| m * function.matrix(width, height,),)
+}
+
+///The transform of a box with 'border_box', which 'functions' make around the
+/// center of the box, the initial 'transform-origin'.
+pub fn transform_matrix(functions: &[TransformFunction], border_box: &Rct,) -> Matrix {
+   let (x, y,) = (border_box.x + border_box.width / 2.0, border_box.y + border_box.height / 2.0,);
+   Matrix::translate(x, y,) * compose(functions, border_box.width, border_box.height,) * Matrix::translate(-x, -y,)
+}
@@ -0,0 +1,408 @@
+#![allow(unused)]
+use std::io::Read;
+
+///Tags a WOFF2 table directory refers to by their index.
+const KNOWN_TAGS: [&[u8; 4]; 63] = [
+   b"cmap", b"head", b"hhea", b"hmtx", b"maxp", b"name", b"OS/2", b"post", b"cvt ", b"fpgm", b"glyf", b"loca", b"prep", b"CFF ",
+   b"VORG", b"EBDT", b"EBLC", b"gasp", b"hdmx", b"kern", b"LTSH", b"PCLT", b"VDMX", b"vhea", b"vmtx", b"BASE", b"GDEF", b"GPOS",
+   b"GSUB", b"EBSC", b"JSTF", b"MATH", b"CBDT", b"CBLC", b"COLR", b"CPAL", b"SVG ", b"sbix", b"acnt", b"avar", b"bdat", b"bloc",
+   b"bsln", b"cvar", b"fdsc", b"feat", b"fmtx", b"fvar", b"gvar", b"hsty", b"just", b"lcar", b"mort", b"morx", b"opbd", b"prop",
+   b"trak", b"Zapf", b"Silf", b"Glat", b"Gloc", b"Feat", b"Sill",
+];
+
+///An entry of the table directory.
+struct TableEntry {
+   tag:         [u8; 4],
+   transformed: bool,
+   ///Length of the table in the decompressed stream.
+   length:      usize,
+}
+
+///Decode a WOFF2 file into the TrueType or OpenType font it compresses. 'None'
+/// if the data is malformed, or is a font collection.
+pub fn decode(data: &[u8],) -> Option<Vec<u8,>,> {
+   let mut r = Reader::new(data,);
+   if r.u32()? != u32::from_be_bytes(*b"wOF2",) {
+      return None;
+   }
+   let flavor = r.u32()?;
+   if flavor == u32::from_be_bytes(*b"ttcf",) {
+      return None;
+   }
+   //Total length, number of tables, reserved, and the size of the uncompressed font.
+   r.skip(4,)?;
+   let num_tables = r.u16()?;
+   r.skip(6,)?;
+   let compressed_size = r.u32()? as usize;
+   //Version, and the extended metadata and private data blocks.
+   r.skip(24,)?;
+
+   let mut entries = vec![];
+   for _ in 0..num_tables {
+      let flags = r.u8()?;
+      let tag = match flags & 0x3f {
+         63 => r.u32()?.to_be_bytes(),
+         i => *KNOWN_TAGS[i as usize],
+      };
+      //'glyf' and 'loca' are transformed unless the version is 3, other tables
+      // only if it isn't 0.
+      let version = flags >> 6;
+      let transformed = if tag == *b"glyf" || tag == *b"loca" { version == 0 } else { version != 0 };
+      let orig_length = r.base128()?;
+      let length = if transformed { r.base128()? } else { orig_length };
+      entries.push(TableEntry { tag, transformed, length: length as usize, },);
+   }
+   let mut stream = vec![];
+   brotli_decompressor::Decompressor::new(r.bytes(compressed_size,)?, 4096,).read_to_end(&mut stream,).ok()?;
+
+   let mut raw = vec![];
+   let mut offset = 0;
+   for entry in &entries {
+      raw.push(stream.get(offset..offset + entry.length,)?,);
+      offset += entry.length;
+   }
+   let find = |tag: &[u8; 4]| entries.iter().position(|e| e.tag == *tag,).map(|i| (&entries[i], raw[i],),);
+
+   //'loca', and the left side bearings 'hmtx' may leave out, come from 'glyf'.
+   let mut glyf = None;
+   if let Some((entry, data,),) = find(b"glyf",).filter(|(e, _,)| e.transformed,) {
+      glyf = Some(reconstruct_glyf(data,)?,);
+   }
+   let mut tables = vec![];
+   for (entry, data,) in entries.iter().zip(&raw,) {
+      let table = match (&entry.tag, entry.transformed,) {
+         (_, false,) => data.to_vec(),
+         (b"glyf", true,) => glyf.as_ref()?.glyf.clone(),
+         (b"loca", true,) => glyf.as_ref()?.loca.clone(),
+         (b"hmtx", true,) => {
+            let glyf = glyf.as_ref()?;
+            let hhea = find(b"hhea",)?.1;
+            let num_h_metrics = u16::from_be_bytes([*hhea.get(34,)?, *hhea.get(35,)?,],) as usize;
+            reconstruct_hmtx(data, num_h_metrics, &glyf.x_mins,)?
+         }
+         _ => return None,
+      };
+      tables.push((entry.tag, table,),);
+   }
+   Some(build_sfnt(flavor, tables,),)
+}
+
+///Tables rebuilt from a transformed 'glyf' table.
+struct Glyf {
+   glyf:   Vec<u8,>,
+   loca:   Vec<u8,>,
+   ///Left edge of each glyph's bounding box.
+   x_mins: Vec<i16,>,
+}
+
+///Rebuild 'glyf' and 'loca' from the streams of a transformed 'glyf' table.
+fn reconstruct_glyf(data: &[u8],) -> Option<Glyf,> {
+   let mut r = Reader::new(data,);
+   r.skip(2,)?;
+   let option_flags = r.u16()?;
+   let num_glyphs = r.u16()? as usize;
+   let index_format = r.u16()?;
+   let mut sizes = [0; 7];
+   for size in &mut sizes {
+      *size = r.u32()? as usize;
+   }
+   let mut streams = vec![];
+   for size in sizes {
+      streams.push(Reader::new(r.bytes(size,)?,),);
+   }
+   let [mut n_contours, mut n_points, mut flags, mut glyphs, mut composites, mut bboxes, mut instructions]: [Reader; 7] =
+      streams.try_into().ok()?;
+   let bbox_bitmap = bboxes.bytes(4 * num_glyphs.div_ceil(32,),)?;
+   let overlap_bitmap = if option_flags & 1 != 0 { Some(r.bytes(num_glyphs.div_ceil(8,),)?,) } else { None };
+   let bit = |bitmap: &[u8], i: usize| bitmap[i >> 3] & (0x80 >> (i & 7)) != 0;
+
+   let mut glyf = vec![];
+   let mut offsets = vec![];
+   let mut x_mins = vec![];
+   for i in 0..num_glyphs {
+      offsets.push(glyf.len(),);
+      let has_bbox = bit(bbox_bitmap, i,);
+      match n_contours.i16()? {
+         0 if has_bbox => return None,
+         0 => x_mins.push(0,),
+         //A composite glyph, which always has an explicit bounding box.
+         -1 => {
+            let bbox = bboxes.bytes(8,).filter(|_| has_bbox,)?;
+            let start = composites.pos;
+            let mut have_instructions = false;
+            loop {
+               let flag = composites.u16()?;
+               have_instructions |= flag & 0x0100 != 0;
+               //Glyph index and two arguments of one or two bytes each, then the transform.
+               let mut len = if flag & 0x0001 != 0 { 6 } else { 4 };
+               if flag & 0x0008 != 0 {
+                  len += 2;
+               } else if flag & 0x0040 != 0 {
+                  len += 4;
+               } else if flag & 0x0080 != 0 {
+                  len += 8;
+               }
+               composites.skip(len,)?;
+               if flag & 0x0020 == 0 {
+                  break;
+               }
+            }
+            glyf.extend((-1i16).to_be_bytes(),);
+            glyf.extend(bbox,);
+            glyf.extend(&composites.data[start..composites.pos],);
+            if have_instructions {
+               let len = glyphs.u255_16()?;
+               glyf.extend(len.to_be_bytes(),);
+               glyf.extend(instructions.bytes(len as usize,)?,);
+            }
+            x_mins.push(i16::from_be_bytes([bbox[0], bbox[1],],),);
+         }
+         contours if contours > 0 => {
+            let mut end_points = vec![];
+            let mut total = 0;
+            for _ in 0..contours {
+               total += n_points.u255_16()? as usize;
+               end_points.push(total.checked_sub(1,)? as u16,);
+            }
+            let mut points = vec![];
+            for _ in 0..total {
+               points.push(decode_triplet(flags.u8()?, &mut glyphs,)?,);
+            }
+            let instruction_len = glyphs.u255_16()?;
+            let bbox =
+               if has_bbox { [bboxes.i16()?, bboxes.i16()?, bboxes.i16()?, bboxes.i16()?,] } else { points_bbox(&points,) };
+            let overlap = overlap_bitmap.is_some_and(|bitmap| bit(bitmap, i,),);
+
+            glyf.extend(contours.to_be_bytes(),);
+            for v in bbox {
+               glyf.extend(v.to_be_bytes(),);
+            }
+            for end in end_points {
+               glyf.extend(end.to_be_bytes(),);
+            }
+            glyf.extend(instruction_len.to_be_bytes(),);
+            glyf.extend(instructions.bytes(instruction_len as usize,)?,);
+            encode_points(&points, overlap, &mut glyf,);
+            x_mins.push(bbox[0],);
+         }
+         _ => return None,
+      }
+      //Keep every glyph 4-byte aligned, which also suits short 'loca' offsets.
+      glyf.resize(glyf.len().next_multiple_of(4,), 0,);
+   }
+   offsets.push(glyf.len(),);
+   let loca = match index_format {
+      0 => offsets.iter().flat_map(|o| ((o / 2) as u16).to_be_bytes(),).collect(),
+      _ => offsets.iter().flat_map(|o| (*o as u32).to_be_bytes(),).collect(),
+   };
+   Some(Glyf { glyf, loca, x_mins, },)
+}
+
+///A point of a simple glyph as its offset from the previous point, and
+/// whether it is on the curve.
+type Point = (i32, i32, bool,);
+
+///Decode a point from its flag byte and the bytes of its triplet encoding.
+fn decode_triplet(flag: u8, glyphs: &mut Reader,) -> Option<Point,> {
+   let on_curve = flag & 0x80 == 0;
+   let f = flag & 0x7f;
+   let n = match f {
+      0..=83 => 1,
+      84..=119 => 2,
+      120..=123 => 3,
+      _ => 4,
+   };
+   let data = glyphs.bytes(n,)?;
+   let b = |i: usize| data[i] as i32;
+   //The low bits of the flag give the signs of dx and dy.
+   let sign = |f: i32, v: i32| if f & 1 != 0 { v } else { -v };
+   let f = f as i32;
+   let (dx, dy,) = match f {
+      0..=9 => (0, sign(f, ((f & 14) << 7) + b(0,),),),
+      10..=19 => (sign(f, (((f - 10) & 14) << 7) + b(0,),), 0,),
+      20..=83 => {
+         let b0 = f - 20;
+         (sign(f, 1 + (b0 & 0x30) + (b(0,) >> 4),), sign(f >> 1, 1 + ((b0 & 0x0c) << 2) + (b(0,) & 0x0f),),)
+      }
+      84..=119 => {
+         let b0 = f - 84;
+         (sign(f, 1 + ((b0 / 12) << 8) + b(0,),), sign(f >> 1, 1 + (((b0 % 12) >> 2) << 8) + b(1,),),)
+      }
+      120..=123 => (sign(f, (b(0,) << 4) + (b(1,) >> 4),), sign(f >> 1, ((b(1,) & 0x0f) << 8) + b(2,),),),
+      _ => (sign(f, (b(0,) << 8) + b(1,),), sign(f >> 1, (b(2,) << 8) + b(3,),),),
+   };
+   Some((dx, dy, on_curve,),)
+}
+
+///[xMin, yMin, xMax, yMax] of the points, which are relative to each other.
+fn points_bbox(points: &[Point],) -> [i16; 4] {
+   if points.is_empty() {
+      return [0; 4];
+   }
+   let (mut x, mut y,) = (0, 0,);
+   let mut bbox = [i32::MAX, i32::MAX, i32::MIN, i32::MIN,];
+   for (dx, dy, _,) in points {
+      x += dx;
+      y += dy;
+      bbox = [bbox[0].min(x,), bbox[1].min(y,), bbox[2].max(x,), bbox[3].max(y,),];
+   }
+   bbox.map(|v| v as i16,)
+}
+
+///Write the flags and coordinates of a simple glyph in the 'glyf' format.
+fn encode_points(points: &[Point], overlap: bool, out: &mut Vec<u8,>,) {
+   let mut flags = vec![];
+   let mut xs = vec![];
+   let mut ys = vec![];
+   //Each coordinate is left out if zero, one byte and a sign flag if short, or two bytes.
+   let encode = |d: i32, short: u8, same: u8, coords: &mut Vec<u8,>| match d {
+      0 => same,
+      -255..=255 => {
+         coords.push(d.unsigned_abs() as u8,);
+         if d > 0 {
+            short | same
+         } else {
+            short
+         }
+      }
+      _ => {
+         coords.extend((d as i16).to_be_bytes(),);
+         0
+      }
+   };
+   for (i, &(dx, dy, on_curve,),) in points.iter().enumerate() {
+      let mut flag = on_curve as u8;
+      if overlap && i == 0 {
+         flag |= 0x40;
+      }
+      flag |= encode(dx, 0x02, 0x10, &mut xs,);
+      flag |= encode(dy, 0x04, 0x20, &mut ys,);
+      flags.push(flag,);
+   }
+   out.extend(flags,);
+   out.extend(xs,);
+   out.extend(ys,);
+}
+
+///Rebuild 'hmtx' from its transformed form, which may leave out left side
+/// bearings that equal the glyphs' 'x_mins'.
+fn reconstruct_hmtx(data: &[u8], num_h_metrics: usize, x_mins: &[i16],) -> Option<Vec<u8,>,> {
+   let mut r = Reader::new(data,);
+   let flags = r.u8()?;
+   let num_glyphs = x_mins.len();
+   let advances = (0..num_h_metrics).map(|_| r.u16(),).collect::<Option<Vec<_,>,>>()?;
+   let mut lsbs = match flags & 1 {
+      0 => (0..num_h_metrics).map(|_| r.i16(),).collect::<Option<Vec<_,>,>>()?,
+      _ => x_mins.get(..num_h_metrics,)?.to_vec(),
+   };
+   match flags & 2 {
+      0 => lsbs.extend((num_h_metrics..num_glyphs).map(|_| r.i16(),).collect::<Option<Vec<_,>,>>()?,),
+      _ => lsbs.extend(x_mins.get(num_h_metrics..,)?,),
+   }
+   let mut hmtx = vec![];
+   for (i, lsb,) in lsbs.iter().enumerate() {
+      if let Some(advance,) = advances.get(i,) {
+         hmtx.extend(advance.to_be_bytes(),);
+      }
+      hmtx.extend(lsb.to_be_bytes(),);
+   }
+   Some(hmtx,)
+}
+
+///Assemble tables into an sfnt font file, with their checksums.
+fn build_sfnt(flavor: u32, mut tables: Vec<([u8; 4], Vec<u8,>,),>,) -> Vec<u8,> {
+   tables.sort_by_key(|(tag, _,)| *tag,);
+   let num_tables = tables.len() as u16;
+   let entry_selector = num_tables.max(1,).ilog2() as u16;
+   let search_range = (1 << entry_selector) * 16;
+   let mut font = vec![];
+   font.extend(flavor.to_be_bytes(),);
+   for v in [num_tables, search_range, entry_selector, num_tables * 16 - search_range,] {
+      font.extend(v.to_be_bytes(),);
+   }
+   let mut offset = 12 + 16 * tables.len();
+   let mut head_offset = None;
+   for (tag, table,) in &mut tables {
+      if *tag == *b"head" && table.len() >= 12 {
+         //The font's checksum is filled in once the whole file is known.
+         table[8..12].fill(0,);
+         head_offset = Some(offset,);
+      }
+      font.extend(*tag,);
+      font.extend(checksum(table,).to_be_bytes(),);
+      font.extend((offset as u32).to_be_bytes(),);
+      font.extend((table.len() as u32).to_be_bytes(),);
+      offset += table.len().next_multiple_of(4,);
+   }
+   for (_, table,) in &tables {
+      font.extend(table,);
+      font.resize(font.len().next_multiple_of(4,), 0,);
+   }
+   if let Some(head,) = head_offset {
+      let adjustment = 0xB1B0AFBAu32.wrapping_sub(checksum(&font,),);
+      font[head + 8..head + 12].copy_from_slice(&adjustment.to_be_bytes(),);
+   }
+   font
+}
+
+///Sum of the data as big-endian 32-bit words, zero-padded.
+fn checksum(data: &[u8],) -> u32 {
+   data.chunks(4,).fold(0u32, |sum, chunk| {
+      let mut word = [0; 4];
+      word[..chunk.len()].copy_from_slice(chunk,);
+      sum.wrapping_add(u32::from_be_bytes(word,),)
+   },)
+}
+
+///Reads big-endian values from a byte slice. Every read fails past the end.
+struct Reader<'a,> {
+   data: &'a [u8],
+   pos:  usize,
+}
+
+impl<'a,> Reader<'a,> {
+   fn new(data: &'a [u8],) -> Reader<'a,> { Reader { data, pos: 0, } }
+
+   fn bytes(&mut self, n: usize,) -> Option<&'a [u8],> {
+      let bytes = self.data.get(self.pos..self.pos.checked_add(n,)?,)?;
+      self.pos += n;
+      Some(bytes,)
+   }
+
+   fn skip(&mut self, n: usize,) -> Option<(),> { self.bytes(n,).map(|_| (),) }
+
+   fn u8(&mut self,) -> Option<u8,> { self.bytes(1,).map(|b| b[0],) }
+
+   fn u16(&mut self,) -> Option<u16,> { self.bytes(2,).map(|b| u16::from_be_bytes([b[0], b[1],],),) }
+
+   fn i16(&mut self,) -> Option<i16,> { self.u16().map(|v| v as i16,) }
+
+   fn u32(&mut self,) -> Option<u32,> { self.bytes(4,).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3],],),) }
+
+   ///A 'UIntBase128': up to five bytes of seven bits each, most significant first.
+   fn base128(&mut self,) -> Option<u32,> {
+      let mut val: u32 = 0;
+      for i in 0..5 {
+         let byte = self.u8()?;
+         //No leading zeros.
+         if i == 0 && byte == 0x80 {
+            return None;
+         }
+         val = val.checked_mul(128,)? | (byte & 0x7f) as u32;
+         if byte & 0x80 == 0 {
+            return Some(val,);
+         }
+      }
+      None
+   }
+
+   ///A '255UInt16', which takes one byte for small values.
+   fn u255_16(&mut self,) -> Option<u16,> {
+      match self.u8()? {
+         253 => self.u16(),
+         254 => self.u8().map(|b| b as u16 + 253 * 2,),
+         255 => self.u8().map(|b| b as u16 + 253,),
+         b => Some(b as u16,),
+      }
+   }
+}